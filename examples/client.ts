@@ -11,21 +11,90 @@ import * as borsh from "borsh";
 enum ProofSystem {
   Groth16 = 0,
   Stark = 1,
+  Plonk = 2,
 }
 
-class InitializeInstruction {
+enum Cluster {
+  Mainnet = 0,
+  Devnet = 1,
+  Testnet = 2,
+  Localnet = 3,
+}
+
+class InitializePoolInstruction {
   variant = 0;
+  pool_id: bigint;
+  asset: Uint8Array;
+  denomination: bigint | null;
+  cluster: Cluster;
+  crank_reward: bigint;
+  min_withdraw_delay: bigint;
+  max_withdraw_delay: bigint;
+  curator: Uint8Array;
+  auditor: Uint8Array | null;
+  min_note_age: bigint | null;
+
+  constructor(
+    poolId: bigint,
+    asset: Uint8Array,
+    denomination: bigint | null,
+    cluster: Cluster,
+    crankReward: bigint,
+    withdrawDelay: [bigint, bigint],
+    curator: Uint8Array,
+    auditor: Uint8Array | null,
+    minNoteAge: bigint | null
+  ) {
+    this.pool_id = poolId;
+    this.asset = asset;
+    this.denomination = denomination;
+    this.cluster = cluster;
+    this.crank_reward = crankReward;
+    [this.min_withdraw_delay, this.max_withdraw_delay] = withdrawDelay;
+    this.curator = curator;
+    this.auditor = auditor;
+    this.min_note_age = minNoteAge;
+  }
 }
 class DepositInstruction {
   variant = 1;
+  amount: bigint;
   commitment: Uint8Array;
+  encrypted_note: Uint8Array;
 
-  constructor(comm: Uint8Array) {
+  constructor(amount: bigint, comm: Uint8Array, encryptedNote: Uint8Array) {
+    this.amount = amount;
     this.commitment = comm;
+    this.encrypted_note = encryptedNote;
   }
 }
 
-const InitializeSchema = new Map([[InitializeInstruction, { kind: "struct", fields: [["variant", "u8"]] }]]);
+// `HandCrankedInstruction::Crank { max_leaves: u8 }`
+const CRANK_VARIANT = 9;
+
+const InitializePoolSchema = new Map([
+  [
+    InitializePoolInstruction,
+    {
+      kind: "struct",
+      fields: [
+        ["variant", "u8"],
+        ["pool_id", "u64"],
+        ["asset", [32]],
+        ["denomination", { kind: "option", type: "u64" }],
+        // PoolParams
+        ["cluster", "u8"],
+        ["crank_reward", "u64"],
+        ["min_withdraw_delay", "u64"],
+        ["max_withdraw_delay", "u64"],
+        ["curator", [32]],
+        // AuditorKey { x, y }
+        ["auditor", { kind: "option", type: [64] }],
+        ["min_note_age", { kind: "option", type: "u64" }],
+      ],
+    },
+  ],
+]);
 const DepositSchema = new Map([
   [
     DepositInstruction,
@@ -33,7 +102,9 @@ const DepositSchema = new Map([
       kind: "struct",
       fields: [
         ["variant", "u8"],
+        ["amount", "u64"],
         ["commitment", [32]],
+        ["encrypted_note", ["u8"]],
       ],
     },
   ],
@@ -49,20 +120,47 @@ async function main() {
 
   const programId = new PublicKey("<DEPLOYED_PROGRAM_ID>");
 
-  const [globalStatePda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("global-state")],
+  const poolId = 0n;
+  const poolIdBytes = Buffer.alloc(8);
+  poolIdBytes.writeBigUInt64LE(poolId);
+  const [poolPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("pool"), poolIdBytes],
+    programId
+  );
+  const [queuePda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("queue"), poolPda.toBuffer()],
+    programId
+  );
+  const [vaultPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), poolPda.toBuffer()],
+    programId
+  );
+  // SOL vault stats: the asset is NATIVE_ASSET (all zeroes).
+  const [statsPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("stats"), poolPda.toBuffer(), Buffer.alloc(32)],
     programId
   );
 
-  // Initialize
+  // Initialize a native SOL pool with arbitrary amounts
   {
-    const ixData = Buffer.from(borsh.serialize(InitializeSchema, new InitializeInstruction()));
+    const ixData = Buffer.from(
+      borsh.serialize(
+        InitializePoolSchema,
+        new InitializePoolInstruction(poolId, new Uint8Array(32), null, Cluster.Devnet, 5_000n, [
+          0n,
+          150n,
+        ], new Uint8Array(32), null, null)
+      )
+    );
     const ix = new TransactionInstruction({
       programId,
       keys: [
-        { pubkey: globalStatePda, isSigner: false, isWritable: true },
+        { pubkey: poolPda, isSigner: false, isWritable: true },
+        { pubkey: queuePda, isSigner: false, isWritable: true },
         { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+        { pubkey: vaultPda, isSigner: false, isWritable: true },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: statsPda, isSigner: false, isWritable: true },
       ],
       data: ixData,
     });
@@ -74,17 +172,20 @@ async function main() {
   {
     const commitment = new Uint8Array(32).fill(1);
     const [notePda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("note"), Buffer.from(commitment)],
+      [Buffer.from("note"), poolPda.toBuffer(), Buffer.from(commitment)],
       programId
     );
-    const ixData = Buffer.from(borsh.serialize(DepositSchema, new DepositInstruction(commitment)));
+    const ixData = Buffer.from(borsh.serialize(DepositSchema, new DepositInstruction(100_000_000n, commitment, new Uint8Array(0))));
     const ix = new TransactionInstruction({
       programId,
       keys: [
-        { pubkey: globalStatePda, isSigner: false, isWritable: true },
+        { pubkey: poolPda, isSigner: false, isWritable: false },
+        { pubkey: queuePda, isSigner: false, isWritable: true },
         { pubkey: payer.publicKey, isSigner: true, isWritable: true },
         { pubkey: notePda, isSigner: false, isWritable: true },
+        { pubkey: vaultPda, isSigner: false, isWritable: true },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: statsPda, isSigner: false, isWritable: true },
       ],
       data: ixData,
     });
@@ -92,7 +193,22 @@ async function main() {
     await connection.sendTransaction(tx, [payer]);
   }
 
-  console.log("Initialized + deposited commitment");
+  // Crank the queued leaf into the tree (anyone can do this)
+  {
+    const ix = new TransactionInstruction({
+      programId,
+      keys: [
+        { pubkey: poolPda, isSigner: false, isWritable: true },
+        { pubkey: queuePda, isSigner: false, isWritable: true },
+        { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+      ],
+      data: Buffer.from([CRANK_VARIANT, 8]),
+    });
+    const tx = new Transaction().add(ix);
+    await connection.sendTransaction(tx, [payer]);
+  }
+
+  console.log("Initialized + deposited + cranked commitment");
 }
 
 main().catch(console.error);
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use hand_cranked_privacy::merkle::MerkleTree;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::store::Store;
+
+const MAX_PAGE: usize = 1000;
+
+pub struct State {
+    pub store: Store,
+    /// Commitment tree of every pool seen so far.
+    pub trees: HashMap<Pubkey, MerkleTree>,
+}
+
+/// Serve the local JSON API:
+///
+/// - `GET /pools`                              every pool with its current root
+/// - `GET /pools/<pool>/root`                  current root and leaf count
+/// - `GET /pools/<pool>/roots/<hex>`           whether a root was ever current
+/// - `GET /pools/<pool>/leaves?from=<n>`       commitments and ciphertexts from leaf `n`
+/// - `GET /pools/<pool>/path/<leaf_index>`     Merkle path for a leaf against the current root
+/// - `GET /pools/<pool>/nullifiers/<hex>`      whether a nullifier is spent
+pub fn serve(listen: &str, state: Arc<Mutex<State>>) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::http(listen).map_err(|e| e.to_string())?;
+    println!("Indexer API listening on http://{}", listen);
+
+    for request in server.incoming_requests() {
+        let (status, body) = {
+            let state = state.lock().unwrap();
+            route(&request, &state)
+        };
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        if let Err(e) = request.respond(response) {
+            eprintln!("failed to respond: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn route(request: &Request, state: &State) -> (u16, Value) {
+    if request.method() != &Method::Get {
+        return error(405, "method not allowed");
+    }
+
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["pools"] => {
+            let pools: Vec<Value> = state
+                .trees
+                .iter()
+                .map(|(pool, tree)| {
+                    json!({
+                        "pool": pool.to_string(),
+                        "root": hex::encode(tree.root()),
+                        "leaves": tree.len(),
+                    })
+                })
+                .collect();
+            (200, json!({ "pools": pools }))
+        }
+        ["pools", pool, rest @ ..] => match Pubkey::from_str(pool) {
+            Ok(pool) => match state.trees.get(&pool) {
+                Some(tree) => route_pool(&state.store, &pool, tree, rest, query),
+                None => error(404, "unknown pool"),
+            },
+            Err(_) => error(400, "invalid pool"),
+        },
+        _ => error(404, "not found"),
+    }
+}
+
+fn route_pool(
+    store: &Store,
+    pool: &Pubkey,
+    tree: &MerkleTree,
+    segments: &[&str],
+    query: &str,
+) -> (u16, Value) {
+    match segments {
+        ["root"] => (
+            200,
+            json!({ "root": hex::encode(tree.root()), "leaves": tree.len() }),
+        ),
+        ["roots", root] => match parse_hash(root) {
+            Some(root) => match store.root_size(pool, &root) {
+                Ok(size) => (200, json!({ "known": size.is_some(), "leaves": size })),
+                Err(e) => error(500, &e.to_string()),
+            },
+            None => error(400, "invalid root"),
+        },
+        ["leaves"] => {
+            let from = query
+                .split('&')
+                .find_map(|kv| kv.strip_prefix("from="))
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0u64);
+            let leaves = tree.leaves();
+            match store.ciphertexts_from(pool, from, MAX_PAGE) {
+                Ok(entries) => {
+                    let items: Vec<Value> = entries
+                        .into_iter()
+                        .map(|(index, ciphertext)| {
+                            json!({
+                                "index": index,
+                                "commitment": hex::encode(leaves[index as usize]),
+                                "ciphertext": hex::encode(ciphertext),
+                            })
+                        })
+                        .collect();
+                    (200, json!({ "leaves": items }))
+                }
+                Err(e) => error(500, &e.to_string()),
+            }
+        }
+        ["path", index] => match index.parse::<u64>().ok().and_then(|i| tree.path(i)) {
+            Some(path) => (
+                200,
+                json!({
+                    "index": index.parse::<u64>().unwrap_or_default(),
+                    "root": hex::encode(tree.root()),
+                    "siblings": path.iter().map(hex::encode).collect::<Vec<_>>(),
+                }),
+            ),
+            None => error(404, "unknown leaf"),
+        },
+        ["nullifiers", nullifier] => match parse_hash(nullifier) {
+            Some(nullifier) => match store.nullifier(pool, &nullifier) {
+                Ok(signature) => (
+                    200,
+                    json!({ "spent": signature.is_some(), "signature": signature }),
+                ),
+                Err(e) => error(500, &e.to_string()),
+            },
+            None => error(400, "invalid nullifier"),
+        },
+        _ => error(404, "not found"),
+    }
+}
+
+fn parse_hash(s: &str) -> Option<[u8; 32]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
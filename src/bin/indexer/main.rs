@@ -0,0 +1,101 @@
+//! Local indexer for the hand-cranked privacy pool.
+//!
+//! Replays the program's transactions from an RPC endpoint, persists
+//! commitments, roots and spent nullifiers of every pool, and serves Merkle
+//! paths over HTTP/JSON so wallets can build proofs.
+//!
+//! ```text
+//! solana-test-validator --bpf-program <PROGRAM_ID> target/deploy/hand_cranked_privacy.so
+//! cargo run --features indexer --bin hand-cranked-indexer -- \
+//!     --rpc http://127.0.0.1:8899 --program-id <PROGRAM_ID>
+//! ```
+
+mod api;
+mod store;
+mod sync;
+
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{api::State, store::Store, sync::Syncer};
+
+struct Config {
+    rpc_url: String,
+    program_id: Pubkey,
+    db_path: String,
+    listen: String,
+    poll_interval: Duration,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        rpc_url: "http://127.0.0.1:8899".to_string(),
+        program_id: Pubkey::default(),
+        db_path: "indexer-db".to_string(),
+        listen: "127.0.0.1:8080".to_string(),
+        poll_interval: Duration::from_secs(2),
+    };
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--rpc" => config.rpc_url = value()?,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value()?).map_err(|e| e.to_string())?)
+            }
+            "--db" => config.db_path = value()?,
+            "--listen" => config.listen = value()?,
+            "--poll-secs" => {
+                config.poll_interval =
+                    Duration::from_secs(value()?.parse().map_err(|_| "invalid --poll-secs")?)
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+
+    config.program_id = program_id.ok_or("--program-id is required")?;
+    Ok(config)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = parse_args()?;
+
+    let store = Store::open(&config.db_path)?;
+    let trees = store.load_trees()?;
+    for (pool, tree) in &trees {
+        println!(
+            "Loaded {} leaves of pool {} from {}",
+            tree.len(),
+            pool,
+            config.db_path
+        );
+    }
+
+    let state = Arc::new(Mutex::new(State { store, trees }));
+    let syncer = Syncer::new(&config.rpc_url, config.program_id);
+
+    let sync_state = Arc::clone(&state);
+    let poll_interval = config.poll_interval;
+    thread::spawn(move || loop {
+        {
+            let mut guard = sync_state.lock().unwrap();
+            let State { store, trees } = &mut *guard;
+            match syncer.sync(store, trees) {
+                Ok(0) => {}
+                Ok(n) => println!("Applied {} transactions", n),
+                Err(e) => eprintln!("sync failed: {}", e),
+            }
+        }
+        thread::sleep(poll_interval);
+    });
+
+    api::serve(&config.listen, state)
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
+
+use hand_cranked_privacy::merkle::MerkleTree;
+use solana_sdk::pubkey::Pubkey;
+
+/// Embedded store for everything the indexer has seen.
+///
+/// Every pool has its own tree and nullifier set, so all keys start with
+/// the pool address:
+/// - `leaves`:      pool ++ leaf index (u64 BE) -> commitment
+/// - `ciphertexts`: pool ++ leaf index (u64 BE) -> note ciphertext
+/// - `roots`:       pool ++ root -> number of leaves when it became current (u64 BE)
+/// - `nullifiers`:  pool ++ nullifier -> signature of the spending transaction
+/// - `queued`:      pool ++ leaf index (u64 BE) -> commitment ++ ciphertext of a
+///   leaf still waiting in the deposit queue
+/// - `meta`:        sync cursor
+pub struct Store {
+    db: sled::Db,
+    leaves: sled::Tree,
+    ciphertexts: sled::Tree,
+    roots: sled::Tree,
+    nullifiers: sled::Tree,
+    queued: sled::Tree,
+}
+
+const LAST_SIGNATURE: &[u8] = b"last_signature";
+
+/// Commitment and ciphertext of a queued leaf.
+type QueuedLeaf = ([u8; 32], Vec<u8>);
+
+impl Store {
+    pub fn open(path: impl AsRef<Path>) -> sled::Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            leaves: db.open_tree("leaves")?,
+            ciphertexts: db.open_tree("ciphertexts")?,
+            roots: db.open_tree("roots")?,
+            nullifiers: db.open_tree("nullifiers")?,
+            queued: db.open_tree("queued")?,
+            db,
+        })
+    }
+
+    /// Rebuild the in-memory tree of every pool from the persisted leaves.
+    pub fn load_trees(&self) -> Result<HashMap<Pubkey, MerkleTree>, Box<dyn std::error::Error>> {
+        let mut trees = HashMap::new();
+        for entry in self.leaves.iter() {
+            let (key, commitment) = entry?;
+            let pool = Pubkey::new_from_array(to_array(&key[..32])?);
+            let tree = match trees.entry(pool) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(MerkleTree::new().map_err(|e| format!("{:?}", e))?),
+            };
+            tree.insert(to_array(&commitment)?)
+                .map_err(|e| format!("{:?}", e))?;
+        }
+        Ok(trees)
+    }
+
+    pub fn insert_leaf(
+        &self,
+        pool: &Pubkey,
+        leaf_index: u64,
+        commitment: &[u8; 32],
+        ciphertext: &[u8],
+    ) -> sled::Result<()> {
+        let key = leaf_key(pool, leaf_index);
+        self.leaves.insert(key, commitment.as_ref())?;
+        self.ciphertexts.insert(key, ciphertext)?;
+        Ok(())
+    }
+
+    /// Record that `root` became current once the tree of `pool` held
+    /// `size` leaves.
+    pub fn insert_root(&self, pool: &Pubkey, root: &[u8; 32], size: u64) -> sled::Result<()> {
+        self.roots
+            .insert(pool_key(pool, root), &size.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Remember a leaf logged when it was queued, until a crank inserts it.
+    pub fn queue_leaf(
+        &self,
+        pool: &Pubkey,
+        leaf_index: u64,
+        commitment: &[u8; 32],
+        ciphertext: &[u8],
+    ) -> sled::Result<()> {
+        let mut value = commitment.to_vec();
+        value.extend_from_slice(ciphertext);
+        self.queued.insert(leaf_key(pool, leaf_index), value)?;
+        Ok(())
+    }
+
+    /// Remove and return a queued leaf's commitment and ciphertext.
+    pub fn take_queued(
+        &self,
+        pool: &Pubkey,
+        leaf_index: u64,
+    ) -> Result<Option<QueuedLeaf>, Box<dyn std::error::Error>> {
+        match self.queued.remove(leaf_key(pool, leaf_index))? {
+            Some(value) => Ok(Some((to_array(&value[..32])?, value[32..].to_vec()))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn ciphertexts_from(
+        &self,
+        pool: &Pubkey,
+        from: u64,
+        limit: usize,
+    ) -> sled::Result<Vec<(u64, Vec<u8>)>> {
+        self.ciphertexts
+            .range(leaf_key(pool, from)..=leaf_key(pool, u64::MAX))
+            .take(limit)
+            .map(|entry| {
+                entry.map(|(k, v)| {
+                    let mut index = [0u8; 8];
+                    index.copy_from_slice(&k[32..]);
+                    (u64::from_be_bytes(index), v.to_vec())
+                })
+            })
+            .collect()
+    }
+
+    /// Number of leaves in the tree of `pool` when `root` was current, if known.
+    pub fn root_size(&self, pool: &Pubkey, root: &[u8; 32]) -> sled::Result<Option<u64>> {
+        Ok(self.roots.get(pool_key(pool, root))?.map(|v| {
+            let mut size = [0u8; 8];
+            size.copy_from_slice(&v);
+            u64::from_be_bytes(size)
+        }))
+    }
+
+    pub fn insert_nullifier(
+        &self,
+        pool: &Pubkey,
+        nullifier: &[u8; 32],
+        signature: &str,
+    ) -> sled::Result<()> {
+        self.nullifiers
+            .insert(pool_key(pool, nullifier), signature.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn nullifier(&self, pool: &Pubkey, nullifier: &[u8; 32]) -> sled::Result<Option<String>> {
+        Ok(self
+            .nullifiers
+            .get(pool_key(pool, nullifier))?
+            .map(|v| String::from_utf8_lossy(&v).into_owned()))
+    }
+
+    pub fn last_signature(&self) -> sled::Result<Option<String>> {
+        Ok(self
+            .db
+            .get(LAST_SIGNATURE)?
+            .map(|v| String::from_utf8_lossy(&v).into_owned()))
+    }
+
+    pub fn set_last_signature(&self, signature: &str) -> sled::Result<()> {
+        self.db.insert(LAST_SIGNATURE, signature.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+fn pool_key(pool: &Pubkey, hash: &[u8; 32]) -> [u8; 64] {
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(pool.as_ref());
+    key[32..].copy_from_slice(hash);
+    key
+}
+
+fn leaf_key(pool: &Pubkey, leaf_index: u64) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..32].copy_from_slice(pool.as_ref());
+    key[32..].copy_from_slice(&leaf_index.to_be_bytes());
+    key
+}
+
+fn to_array(bytes: &[u8]) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    Ok(bytes.try_into()?)
+}
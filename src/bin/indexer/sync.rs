@@ -0,0 +1,233 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    str::FromStr,
+};
+
+use hand_cranked_privacy::{
+    client::decode_events, events::HandCrankedEvent, instruction::HandCrankedInstruction,
+    merkle::MerkleTree,
+};
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::store::Store;
+
+type SyncResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Pulls the program's transaction history from RPC and applies it to the store.
+pub struct Syncer {
+    rpc: RpcClient,
+    program_id: Pubkey,
+}
+
+impl Syncer {
+    pub fn new(rpc_url: &str, program_id: Pubkey) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed()),
+            program_id,
+        }
+    }
+
+    /// Fetch every transaction after the stored cursor, oldest first, and
+    /// apply it. Returns the number of transactions processed.
+    pub fn sync(
+        &self,
+        store: &Store,
+        trees: &mut HashMap<Pubkey, MerkleTree>,
+    ) -> SyncResult<usize> {
+        let until = store
+            .last_signature()?
+            .map(|s| Signature::from_str(&s))
+            .transpose()?;
+
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self.rpc.get_signatures_for_address_with_config(
+                &self.program_id,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )?;
+            let Some(last) = page.last() else { break };
+            before = Some(Signature::from_str(&last.signature)?);
+            signatures.extend(page.into_iter().filter(|s| s.err.is_none()));
+        }
+
+        signatures.reverse();
+        for status in &signatures {
+            let signature = Signature::from_str(&status.signature)?;
+            self.apply_transaction(store, trees, &signature)?;
+            store.set_last_signature(&status.signature)?;
+        }
+
+        Ok(signatures.len())
+    }
+
+    fn apply_transaction(
+        &self,
+        store: &Store,
+        trees: &mut HashMap<Pubkey, MerkleTree>,
+        signature: &Signature,
+    ) -> SyncResult<()> {
+        let tx = self.rpc.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+
+        if let Some(versioned) = tx.transaction.transaction.decode() {
+            let keys = versioned.message.static_account_keys();
+            for ix in versioned.message.instructions() {
+                if keys.get(ix.program_id_index as usize) != Some(&self.program_id) {
+                    continue;
+                }
+                match HandCrankedInstruction::unpack(&ix.data) {
+                    Ok(instruction) => {
+                        println!(
+                            "{} slot {}: {}",
+                            signature,
+                            tx.slot,
+                            instruction_name(&instruction)
+                        )
+                    }
+                    Err(_) => eprintln!("{}: undecodable instruction", signature),
+                }
+            }
+        }
+
+        let logs: Vec<String> = tx
+            .transaction
+            .meta
+            .and_then(|meta| Option::<Vec<String>>::from(meta.log_messages))
+            .unwrap_or_default();
+
+        for event in decode_events(&self.program_id, &logs)? {
+            match event {
+                // Leaves are logged when queued but only enter the tree
+                // (and so only get a path) when a crank moves them.
+                HandCrankedEvent::Deposit(e) => {
+                    store.queue_leaf(&e.pool, e.leaf_index, &e.commitment, &e.ciphertext)?
+                }
+                HandCrankedEvent::NewCommitment(e) => {
+                    store.queue_leaf(&e.pool, e.leaf_index, &e.commitment, &e.ciphertext)?
+                }
+                HandCrankedEvent::QueueCranked(e) => {
+                    self.apply_crank(store, trees, &e.pool, e.first_leaf_index, e.count, &e.root)?
+                }
+                HandCrankedEvent::NullifierSpent(e) => {
+                    store.insert_nullifier(&e.pool, &e.nullifier, &signature.to_string())?
+                }
+                HandCrankedEvent::Withdrawal(_)
+                | HandCrankedEvent::WithdrawalRequested(_)
+                | HandCrankedEvent::WithdrawalExecuted(_)
+                | HandCrankedEvent::AuditorMemo(_)
+                | HandCrankedEvent::Swap(_)
+                | HandCrankedEvent::RewardClaimed(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the queued leaves `[first_leaf_index, first_leaf_index + count)`
+    /// of `pool` into its tree and check the result against the logged root.
+    fn apply_crank(
+        &self,
+        store: &Store,
+        trees: &mut HashMap<Pubkey, MerkleTree>,
+        pool: &Pubkey,
+        first_leaf_index: u64,
+        count: u64,
+        root: &[u8; 32],
+    ) -> SyncResult<()> {
+        let tree = match trees.entry(*pool) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(MerkleTree::new().map_err(|e| format!("{:?}", e))?),
+        };
+
+        for leaf_index in first_leaf_index..first_leaf_index + count {
+            if leaf_index < tree.len() {
+                // Already applied (e.g. restart before the cursor was saved);
+                // drop the copy queued again by the replay.
+                store
+                    .take_queued(pool, leaf_index)
+                    .map_err(|e| e.to_string())?;
+                continue;
+            }
+            if leaf_index != tree.len() {
+                return Err(format!(
+                    "pool {}: leaf {} missing before {}",
+                    pool,
+                    tree.len(),
+                    leaf_index
+                )
+                .into());
+            }
+            let (commitment, ciphertext) = store
+                .take_queued(pool, leaf_index)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("pool {}: leaf {} was never queued", pool, leaf_index))?;
+
+            tree.insert(commitment).map_err(|e| format!("{:?}", e))?;
+            store.insert_leaf(pool, leaf_index, &commitment, &ciphertext)?;
+        }
+
+        if tree.root() != *root {
+            return Err(format!("pool {}: root mismatch after crank", pool).into());
+        }
+        // A crank makes only its final root current on-chain.
+        store.insert_root(pool, root, tree.len())?;
+        Ok(())
+    }
+}
+
+fn instruction_name(instruction: &HandCrankedInstruction) -> &'static str {
+    match instruction {
+        HandCrankedInstruction::InitializePool { .. } => "InitializePool",
+        HandCrankedInstruction::Deposit { .. } => "Deposit",
+        HandCrankedInstruction::PrivateTransfer { .. } => "PrivateTransfer",
+        HandCrankedInstruction::Withdraw { .. } => "Withdraw",
+        HandCrankedInstruction::ConfigureConfidentialAccount => "ConfigureConfidentialAccount",
+        HandCrankedInstruction::ConfidentialDeposit { .. } => "ConfidentialDeposit",
+        HandCrankedInstruction::ConfidentialWithdraw { .. } => "ConfidentialWithdraw",
+        HandCrankedInstruction::ConfidentialTransfer => "ConfidentialTransfer",
+        HandCrankedInstruction::ApplyConfidentialPendingBalance => {
+            "ApplyConfidentialPendingBalance"
+        }
+        HandCrankedInstruction::Crank { .. } => "Crank",
+        HandCrankedInstruction::BatchInsert { .. } => "BatchInsert",
+        HandCrankedInstruction::RequestWithdraw { .. } => "RequestWithdraw",
+        HandCrankedInstruction::ExecuteWithdraw => "ExecuteWithdraw",
+        HandCrankedInstruction::AuditInvariant => "AuditInvariant",
+        HandCrankedInstruction::PublishAssociationRoot { .. } => "PublishAssociationRoot",
+        HandCrankedInstruction::OpenProofBuffer { .. } => "OpenProofBuffer",
+        HandCrankedInstruction::WriteProofChunk { .. } => "WriteProofChunk",
+        HandCrankedInstruction::CloseProofBuffer => "CloseProofBuffer",
+        HandCrankedInstruction::OpenVerificationSession { .. } => "OpenVerificationSession",
+        HandCrankedInstruction::StepVerificationSession { .. } => "StepVerificationSession",
+        HandCrankedInstruction::CloseVerificationSession => "CloseVerificationSession",
+        HandCrankedInstruction::InitializeSwapReserve { .. } => "InitializeSwapReserve",
+        HandCrankedInstruction::AddSwapLiquidity { .. } => "AddSwapLiquidity",
+        HandCrankedInstruction::RemoveSwapLiquidity { .. } => "RemoveSwapLiquidity",
+        HandCrankedInstruction::PrivateSwap { .. } => "PrivateSwap",
+        HandCrankedInstruction::InitializeAssetVault => "InitializeAssetVault",
+        HandCrankedInstruction::InitializeRewardProgram { .. } => "InitializeRewardProgram",
+        HandCrankedInstruction::FundRewards { .. } => "FundRewards",
+        HandCrankedInstruction::RecordCheckpoint => "RecordCheckpoint",
+        HandCrankedInstruction::ClaimReward { .. } => "ClaimReward",
+        HandCrankedInstruction::InitializeVerifyingKey { .. } => "InitializeVerifyingKey",
+        HandCrankedInstruction::WriteVerifyingKey { .. } => "WriteVerifyingKey",
+        HandCrankedInstruction::FinalizeVerifyingKey => "FinalizeVerifyingKey",
+    }
+}
@@ -0,0 +1,109 @@
+//! Off-chain helpers for wallets and indexers.
+
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use borsh::BorshDeserialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::events::{HandCrankedEvent, EVENT_VERSION};
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// A `Program data:` log line that could not be decoded into an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The event was logged with another `EVENT_VERSION`; only the current
+    /// layout can be decoded, so the caller must not treat it as absent.
+    UnsupportedVersion(u8),
+    /// The line is not a well-formed `[version, borsh(event)]` pair.
+    Malformed,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported event version {} (expected {})",
+                version, EVENT_VERSION
+            ),
+            Self::Malformed => write!(f, "malformed event data"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decode a single `Program data:` log line into an event.
+///
+/// Returns `Ok(None)` for lines that are not program data, and an error
+/// for program data that is not an event of the current version.
+pub fn decode_event(line: &str) -> Result<Option<HandCrankedEvent>, DecodeError> {
+    let Some(payload) = line.strip_prefix(PROGRAM_DATA_PREFIX) else {
+        return Ok(None);
+    };
+    let mut fields = payload.split_whitespace();
+    let mut field = || {
+        fields
+            .next()
+            .and_then(|field| STANDARD.decode(field).ok())
+            .ok_or(DecodeError::Malformed)
+    };
+
+    match field()?.as_slice() {
+        [EVENT_VERSION] => {}
+        [version] => return Err(DecodeError::UnsupportedVersion(*version)),
+        _ => return Err(DecodeError::Malformed),
+    }
+
+    let data = field()?;
+    if fields.next().is_some() {
+        return Err(DecodeError::Malformed);
+    }
+
+    HandCrankedEvent::try_from_slice(&data)
+        .map(Some)
+        .map_err(|_| DecodeError::Malformed)
+}
+
+/// Decode all events emitted by `program_id` from a transaction's log messages.
+///
+/// Log lines are attributed to the innermost program being invoked, so data
+/// logged by other programs in the same transaction is ignored. Fails on
+/// the first of the program's lines that does not decode, rather than
+/// returning a history with events missing.
+pub fn decode_events(
+    program_id: &Pubkey,
+    logs: &[String],
+) -> Result<Vec<HandCrankedEvent>, DecodeError> {
+    let program = program_id.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            let id = words.next().unwrap_or_default();
+            match words.next() {
+                Some("invoke") => {
+                    stack.push(id);
+                    continue;
+                }
+                Some("success") | Some("failed:") => {
+                    stack.pop();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        if stack.last() == Some(&program.as_str()) {
+            if let Some(event) = decode_event(line)? {
+                events.push(event);
+            }
+        }
+    }
+
+    Ok(events)
+}
@@ -0,0 +1,411 @@
+//! Cross-program invocation surface.
+//!
+//! Programs integrating with a pool can deposit into a note or withdraw
+//! to an account they own in the same transaction. The `*_instruction`
+//! builders produce the stable `HandCrankedInstruction` layouts; `deposit`
+//! and `withdraw` invoke them with `AccountInfo`s. Depend on this crate
+//! with the `no-entrypoint` feature to use it from another program.
+//!
+//! Signers: when the depositor or relayer is a PDA of the calling program,
+//! pass its seeds as `signer_seeds`. The signature carries through to the
+//! transfers this program makes on its behalf. In SOL pools a PDA
+//! depositor must be a system-owned account without data, since it funds
+//! the note account and the deposit itself; in token pools it must be the
+//! owner or delegate of the source token account.
+//!
+//! Multi-asset pools keep one vault per asset, so the builders take
+//! `multi_asset` to pick the asset's vault and name the asset moved.
+//!
+//! A withdrawal can pay a program-owned recipient and then run a
+//! `WithdrawCallback` on it. The callback is bound into the proof, so the
+//! prover decides what runs, not the relayer.
+
+use borsh::BorshSerialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{
+    instruction::{
+        AssociationProof, HandCrankedInstruction, ProofSource, ProofSystem, WithdrawCallback,
+    },
+    processor2::{
+        ASSOCIATION_SEED, NOTE_SEED, NULLIFIER_SEED, POOL_SEED, QUEUE_SEED, STATS_SEED, VAULT_SEED,
+        VERIFYING_KEY_SEED,
+    },
+    state::NATIVE_ASSET,
+    zk::Circuit,
+};
+
+pub fn find_pool_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_SEED, &pool_id.to_le_bytes()], program_id)
+}
+
+pub fn find_queue_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[QUEUE_SEED, pool.as_ref()], program_id)
+}
+
+pub fn find_vault_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, pool.as_ref()], program_id)
+}
+
+/// Vault of `mint` in a multi-asset pool (`NATIVE_ASSET` for SOL).
+pub fn find_asset_vault_address(program_id: &Pubkey, pool: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, pool.as_ref(), mint.as_ref()], program_id)
+}
+
+/// Stats of the `asset` vault of `pool` (`NATIVE_ASSET` for SOL).
+pub fn find_stats_address(program_id: &Pubkey, pool: &Pubkey, asset: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATS_SEED, pool.as_ref(), asset.as_ref()], program_id)
+}
+
+pub fn find_note_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    commitment: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NOTE_SEED, pool.as_ref(), commitment], program_id)
+}
+
+pub fn find_nullifier_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    nullifier: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NULLIFIER_SEED, pool.as_ref(), nullifier], program_id)
+}
+
+pub fn find_association_address(program_id: &Pubkey, pool: &Pubkey, set_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ASSOCIATION_SEED, pool.as_ref(), &set_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn find_verifying_key_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    proof_system: ProofSystem,
+    circuit: Circuit,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            VERIFYING_KEY_SEED,
+            pool.as_ref(),
+            &[proof_system as u8],
+            &[circuit as u8],
+        ],
+        program_id,
+    )
+}
+
+/// Token accounts of a token pool: the depositor's source account for
+/// deposits, the relayer's fee account for withdrawals.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenKeys {
+    pub mint: Pubkey,
+    pub token_program: Pubkey,
+    pub token_account: Pubkey,
+}
+
+/// Stats account of the vault that `token` (or SOL) moves through.
+fn stats_address(program_id: &Pubkey, pool: &Pubkey, token: Option<&TokenKeys>) -> Pubkey {
+    let asset = token.map_or(NATIVE_ASSET, |token| token.mint);
+    find_stats_address(program_id, pool, &asset).0
+}
+
+/// Vault that `token` (or SOL) moves through: the pool's vault, or the
+/// asset's vault in a multi-asset pool.
+fn vault_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    multi_asset: bool,
+    token: Option<&TokenKeys>,
+) -> Pubkey {
+    match multi_asset {
+        true => {
+            let asset = token.map_or(NATIVE_ASSET, |token| token.mint);
+            find_asset_vault_address(program_id, pool, &asset).0
+        }
+        false => find_vault_address(program_id, pool).0,
+    }
+}
+
+/// Trailing accounts naming the asset moved: mint, token program and
+/// token account for tokens; the system program for SOL in a multi-asset
+/// pool.
+fn asset_accounts(multi_asset: bool, token: Option<&TokenKeys>) -> Vec<AccountMeta> {
+    match token {
+        Some(token) => vec![
+            AccountMeta::new_readonly(token.mint, false),
+            AccountMeta::new_readonly(token.token_program, false),
+            AccountMeta::new(token.token_account, false),
+        ],
+        None if multi_asset => vec![AccountMeta::new_readonly(system_program::id(), false)],
+        None => vec![],
+    }
+}
+
+/// `Deposit` of `amount` into a note for `commitment`. `multi_asset`
+/// says whether `pool` is a multi-asset pool; `token` is `None` for SOL.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_instruction(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    depositor: &Pubkey,
+    amount: u64,
+    commitment: [u8; 32],
+    encrypted_note: Vec<u8>,
+    multi_asset: bool,
+    token: Option<&TokenKeys>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*pool, false),
+        AccountMeta::new(find_queue_address(program_id, pool).0, false),
+        AccountMeta::new(*depositor, true),
+        AccountMeta::new(find_note_address(program_id, pool, &commitment).0, false),
+        AccountMeta::new(vault_address(program_id, pool, multi_asset, token), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(stats_address(program_id, pool, token), false),
+    ];
+    accounts.extend(asset_accounts(multi_asset, token));
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: HandCrankedInstruction::Deposit {
+            amount,
+            commitment,
+            encrypted_note,
+        }
+        .try_to_vec()
+        .expect("instruction serialization is infallible"),
+    }
+}
+
+/// Arguments of a `Withdraw`.
+#[derive(Clone, Debug)]
+pub struct WithdrawArgs {
+    pub proof_system: ProofSystem,
+    pub proof: ProofSource,
+    /// Proof buffer or verification session for `ProofSource::Buffer` /
+    /// `ProofSource::Session`.
+    pub proof_account: Option<Pubkey>,
+    /// Verifying key of the pool's withdraw circuit
+    /// (`find_verifying_key_address`), unless `ProofSource::Session`.
+    pub verifying_key: Option<Pubkey>,
+    pub root: [u8; 32],
+    pub nullifier: [u8; 32],
+    pub amount: u64,
+    pub fee: u64,
+    pub association: Option<AssociationProof>,
+    pub callback: Option<WithdrawCallback>,
+}
+
+/// `Withdraw` of a note to `recipient`, with `relayer` signing and taking
+/// the fee. `multi_asset` and `token` are as for `deposit_instruction`.
+pub fn withdraw_instruction(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    recipient: &Pubkey,
+    relayer: &Pubkey,
+    args: WithdrawArgs,
+    multi_asset: bool,
+    token: Option<&TokenKeys>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*pool, false),
+        AccountMeta::new(
+            find_nullifier_address(program_id, pool, &args.nullifier).0,
+            false,
+        ),
+        AccountMeta::new(vault_address(program_id, pool, multi_asset, token), false),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new(*relayer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(stats_address(program_id, pool, token), false),
+    ];
+    if let Some(association) = &args.association {
+        let set = find_association_address(program_id, pool, association.set_id).0;
+        let key = find_verifying_key_address(
+            program_id,
+            pool,
+            association.proof_system,
+            Circuit::Association,
+        )
+        .0;
+        accounts.push(AccountMeta::new_readonly(set, false));
+        accounts.push(AccountMeta::new_readonly(key, false));
+    }
+    if let Some(proof_account) = args.proof_account {
+        accounts.push(AccountMeta::new(proof_account, false));
+    }
+    if let Some(verifying_key) = args.verifying_key {
+        accounts.push(AccountMeta::new_readonly(verifying_key, false));
+    }
+    accounts.extend(asset_accounts(multi_asset, token));
+    if let Some(callback) = &args.callback {
+        accounts.push(AccountMeta::new_readonly(callback.program_id, false));
+        accounts.extend(
+            callback
+                .accounts
+                .iter()
+                .map(|account| match account.is_writable {
+                    true => AccountMeta::new(account.pubkey, false),
+                    false => AccountMeta::new_readonly(account.pubkey, false),
+                }),
+        );
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: HandCrankedInstruction::Withdraw {
+            proof_system: args.proof_system,
+            proof: args.proof,
+            root: args.root,
+            nullifier: args.nullifier,
+            amount: args.amount,
+            fee: args.fee,
+            association: args.association,
+            callback: args.callback,
+        }
+        .try_to_vec()
+        .expect("instruction serialization is infallible"),
+    }
+}
+
+/// Accounts for `deposit`, in instruction order.
+pub struct DepositAccounts<'a, 'info> {
+    pub program: &'a AccountInfo<'info>,
+    pub pool: &'a AccountInfo<'info>,
+    pub queue: &'a AccountInfo<'info>,
+    pub depositor: &'a AccountInfo<'info>,
+    pub note: &'a AccountInfo<'info>,
+    pub vault: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub stats: &'a AccountInfo<'info>,
+    /// Whether `pool` is a multi-asset pool, whose `vault` and `stats` are
+    /// those of the asset deposited.
+    pub multi_asset: bool,
+    /// Mint, token program and source token account of a token deposit.
+    pub token: Option<[&'a AccountInfo<'info>; 3]>,
+}
+
+/// Deposit into a note through CPI.
+pub fn deposit(
+    accounts: DepositAccounts,
+    amount: u64,
+    commitment: [u8; 32],
+    encrypted_note: Vec<u8>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let token = accounts
+        .token
+        .map(|[mint, token_program, source]| TokenKeys {
+            mint: *mint.key,
+            token_program: *token_program.key,
+            token_account: *source.key,
+        });
+    let ix = deposit_instruction(
+        accounts.program.key,
+        accounts.pool.key,
+        accounts.depositor.key,
+        amount,
+        commitment,
+        encrypted_note,
+        accounts.multi_asset,
+        token.as_ref(),
+    );
+
+    let mut infos = vec![
+        accounts.pool.clone(),
+        accounts.queue.clone(),
+        accounts.depositor.clone(),
+        accounts.note.clone(),
+        accounts.vault.clone(),
+        accounts.system_program.clone(),
+        accounts.stats.clone(),
+    ];
+    infos.extend(accounts.token.into_iter().flatten().cloned());
+    infos.push(accounts.program.clone());
+
+    invoke_signed(&ix, &infos, signer_seeds)
+}
+
+/// Accounts for `withdraw`, in instruction order.
+pub struct WithdrawAccounts<'a, 'info> {
+    pub program: &'a AccountInfo<'info>,
+    pub pool: &'a AccountInfo<'info>,
+    pub nullifier: &'a AccountInfo<'info>,
+    pub vault: &'a AccountInfo<'info>,
+    pub recipient: &'a AccountInfo<'info>,
+    pub relayer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub stats: &'a AccountInfo<'info>,
+    pub association_set: Option<&'a AccountInfo<'info>>,
+    /// Verifying key of the association proof, with `association_set`.
+    pub association_key: Option<&'a AccountInfo<'info>>,
+    pub proof_account: Option<&'a AccountInfo<'info>>,
+    pub verifying_key: Option<&'a AccountInfo<'info>>,
+    /// Whether `pool` is a multi-asset pool, whose `vault` and `stats` are
+    /// those of the asset withdrawn.
+    pub multi_asset: bool,
+    /// Mint, token program and relayer fee account of a token withdrawal.
+    pub token: Option<[&'a AccountInfo<'info>; 3]>,
+    /// Callback program followed by the callback's accounts.
+    pub callback: &'a [AccountInfo<'info>],
+}
+
+/// Withdraw a note through CPI. `args.proof_account` and
+/// `args.verifying_key` are taken from `accounts`.
+pub fn withdraw(
+    accounts: WithdrawAccounts,
+    args: WithdrawArgs,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let token = accounts
+        .token
+        .map(|[mint, token_program, fee_account]| TokenKeys {
+            mint: *mint.key,
+            token_program: *token_program.key,
+            token_account: *fee_account.key,
+        });
+    let ix = withdraw_instruction(
+        accounts.program.key,
+        accounts.pool.key,
+        accounts.recipient.key,
+        accounts.relayer.key,
+        WithdrawArgs {
+            proof_account: accounts.proof_account.map(|account| *account.key),
+            verifying_key: accounts.verifying_key.map(|account| *account.key),
+            ..args
+        },
+        accounts.multi_asset,
+        token.as_ref(),
+    );
+
+    let mut infos = vec![
+        accounts.pool.clone(),
+        accounts.nullifier.clone(),
+        accounts.vault.clone(),
+        accounts.recipient.clone(),
+        accounts.relayer.clone(),
+        accounts.system_program.clone(),
+        accounts.stats.clone(),
+    ];
+    infos.extend(accounts.association_set.cloned());
+    infos.extend(accounts.association_key.cloned());
+    infos.extend(accounts.proof_account.cloned());
+    infos.extend(accounts.verifying_key.cloned());
+    infos.extend(accounts.token.into_iter().flatten().cloned());
+    infos.extend(accounts.callback.iter().cloned());
+    infos.push(accounts.program.clone());
+
+    invoke_signed(&ix, &infos, signer_seeds)
+}
@@ -6,7 +6,7 @@ use solana_program::{
     account_info::AccountInfo,
 };
 
-use crate::processor::Processor;
+use crate::processor2::Processor;
 
 entrypoint!(process_instruction);
 
@@ -0,0 +1,145 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::zk::public_inputs::AuditorMemo;
+
+/// Version byte written in front of every event.
+///
+/// Bump this whenever an existing variant changes shape; adding new
+/// variants at the end of `HandCrankedEvent` does not require a bump.
+pub const EVENT_VERSION: u8 = 5;
+
+/// A commitment was shielded into the pool.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct DepositEvent {
+    /// Pool whose tree the leaf belongs to; leaf indices are per pool.
+    pub pool: Pubkey,
+    /// Index the leaf gets once cranked into the tree.
+    pub leaf_index: u64,
+    /// Leaf inserted into the tree (`Poseidon(amount, commitment)`).
+    pub commitment: [u8; 32],
+    /// Note encrypted to its owner (opaque to the program).
+    pub ciphertext: Vec<u8>,
+}
+
+/// A commitment was created by a private transfer.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct NewCommitmentEvent {
+    pub pool: Pubkey,
+    pub leaf_index: u64,
+    pub commitment: [u8; 32],
+    pub ciphertext: Vec<u8>,
+}
+
+/// A nullifier was consumed. Nullifiers are per pool.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct NullifierSpentEvent {
+    pub pool: Pubkey,
+    pub nullifier: [u8; 32],
+}
+
+/// A note was unshielded.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawalEvent {
+    pub nullifier: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    /// What the recipient got: `amount - fee`, less any Token-2022
+    /// transfer fee withheld by the mint.
+    pub received: u64,
+    /// Association set the note was proven to belong to, if any.
+    pub association_set: Option<u64>,
+}
+
+/// `Crank` moved queued leaves `[first_leaf_index, first_leaf_index + count)`
+/// into the tree.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct QueueCrankedEvent {
+    pub pool: Pubkey,
+    pub first_leaf_index: u64,
+    pub count: u64,
+    pub root: [u8; 32],
+}
+
+/// A delayed withdrawal was requested; the recipient's share is held in
+/// `pending` until `release_slot`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawalRequestedEvent {
+    pub nullifier: [u8; 32],
+    pub pending: Pubkey,
+    pub fee: u64,
+    pub release_slot: u64,
+    pub association_set: Option<u64>,
+}
+
+/// A delayed withdrawal was paid out.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawalExecutedEvent {
+    pub pending: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    /// `amount` less any Token-2022 transfer fee withheld by the mint.
+    pub received: u64,
+}
+
+/// Auditor memo of a private transfer in a pool with an auditor; its
+/// outputs start at `leaf_index`. For a swap, `leaf_index` is the output
+/// leaf in the output pool.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AuditorMemoEvent {
+    pub leaf_index: u64,
+    pub memo: AuditorMemo,
+}
+
+/// A note of `pool_in` was swapped through a reserve; the output note is
+/// the `Deposit` leaf at `leaf_index` of `pool_out`.
+///
+/// Everything here is public by design (see `PrivateSwap`): the trade
+/// amounts and the link from `nullifier` to the output leaf.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SwapEvent {
+    pub nullifier: [u8; 32],
+    pub pool_in: Pubkey,
+    pub pool_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    pub leaf_index: u64,
+}
+
+/// A note was spent for its reward from checkpoint `start_checkpoint` to
+/// `end_checkpoint`; the reward note, of hidden value, is the
+/// `NewCommitment` leaf at `leaf_index` of `reward_pool`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RewardClaimedEvent {
+    pub reward_pool: Pubkey,
+    pub start_checkpoint: u64,
+    pub end_checkpoint: u64,
+    pub fee: u64,
+    pub leaf_index: u64,
+}
+
+/// Program events – emitted with `sol_log_data` as `[EVENT_VERSION, borsh(event)]`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum HandCrankedEvent {
+    Deposit(DepositEvent),
+    NewCommitment(NewCommitmentEvent),
+    NullifierSpent(NullifierSpentEvent),
+    Withdrawal(WithdrawalEvent),
+    QueueCranked(QueueCrankedEvent),
+    WithdrawalRequested(WithdrawalRequestedEvent),
+    WithdrawalExecuted(WithdrawalExecutedEvent),
+    AuditorMemo(AuditorMemoEvent),
+    Swap(SwapEvent),
+    RewardClaimed(RewardClaimedEvent),
+}
+
+impl HandCrankedEvent {
+    /// Emit the event into the transaction log.
+    pub fn emit(&self) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        sol_log_data(&[&[EVENT_VERSION], &data]);
+        Ok(())
+    }
+}
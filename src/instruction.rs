@@ -1,74 +1,779 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    state::PoolParams,
+    zk::{public_inputs::AuditorMemo, Circuit},
+};
+
 /// Proof system enum – what kind of proof is attached.
 #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
 pub enum ProofSystem {
     Groth16 = 0,
     Stark = 1,
+    /// PLONK with KZG commitments over the shared powers-of-tau setup.
+    /// Off-chain only: the program refuses PLONK verifying keys, so pools
+    /// never accept PLONK proofs. See `zk::plonk`.
+    Plonk = 2,
+}
+
+/// Where a transfer or withdraw proof comes from.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum ProofSource {
+    /// Proof bytes carried in the instruction. Groth16 proofs use the
+    /// 128-byte encoding of `zk::groth16_encoding`.
+    Inline(Vec<u8>),
+    /// Proof staged with `OpenProofBuffer` / `WriteProofChunk` in a buffer
+    /// owned by the relayer; the buffer is closed and refunded to the
+    /// relayer once the proof is used.
+    Buffer,
+    /// Proof already checked by a `VerificationSession` of the relayer for
+    /// the same circuit and public inputs; the session is closed and
+    /// refunded to the relayer.
+    Session,
+}
+
+/// Optional second proof attached to a withdrawal: the spent note is in a
+/// curator's association set.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AssociationProof {
+    pub set_id: u64,
+    pub proof_system: ProofSystem,
+    pub proof: Vec<u8>,
+    /// A recent root published for the set.
+    pub root: [u8; 32],
+}
+
+/// Instruction a `Withdraw` invokes once the recipient is paid, e.g. to let
+/// the receiving program act on the funds atomically. It is part of
+/// `ExtData`, so the proof fixes the program, accounts and data.
+///
+/// The callback runs without any signature from this program.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawCallback {
+    pub program_id: Pubkey,
+    pub accounts: Vec<CallbackAccount>,
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CallbackAccount {
+    pub pubkey: Pubkey,
+    pub is_writable: bool,
 }
 
 /// Program instructions.
+///
+/// Variants are only ever appended and their fields never reordered, so
+/// the Borsh layout (a `u8` variant index followed by the fields) is stable
+/// for clients and CPI callers; see `cpi` for builders.
+///
+/// In multi-asset pools (`MULTI_ASSET`) the vault of an instruction is the
+/// vault of the asset it moves (PDA `["vault", pool, mint]`, with
+/// `NATIVE_ASSET` as the mint for SOL), and where token pools pass a mint
+/// and token program these pools pass the mint and token program, or just
+/// the system program for SOL.
+///
+/// The asset is not hidden: deposits and withdrawals move a given vault,
+/// and a transfer names the vault its fee is paid from and proves against
+/// that `asset_id` as a public input. Notes of all assets share one tree,
+/// but an observer can tell which asset every instruction moves, so each
+/// asset's anonymity set is still only the notes of that asset.
 #[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
 pub enum HandCrankedInstruction {
-    /// Initialize global state (PDA)
+    /// Create a pool (PDA `["pool", pool_id]`) with its own tree, vault and
+    /// nullifier namespace. The signer becomes the pool authority and funds
+    /// the vault up to rent exemption.
     ///
     /// Accounts:
-    /// 0. [writable] Global state account (PDA)
-    /// 1. [signer]   Payer / authority
-    /// 2. []         System program
-    Initialize,
+    /// 0. [writable] Pool account (PDA)
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable, signer] Payer / authority
+    /// 3. [writable] Vault (PDA `["vault", pool]`; ignored for multi-asset
+    ///    pools, whose vaults come from `InitializeAssetVault`)
+    /// 4. []         System program
+    /// 5. [writable] Vault stats (PDA `["stats", pool, asset]`; ignored for
+    ///    multi-asset pools)
+    ///
+    /// Token pools additionally pass (the vault is created as a token
+    /// account owned by itself):
+    /// 6. []         Mint
+    /// 7. []         Token program (`spl-token` or Token-2022)
+    ///
+    /// Data:
+    /// - pool_id: u64
+    /// - asset: Pubkey (`NATIVE_ASSET` for SOL, `MULTI_ASSET` for any asset,
+    ///   otherwise the mint; mints with permanent delegate, non-transferable,
+    ///   transfer hook or default account state extensions are rejected;
+    ///   multi-asset pools take no denomination or auditor)
+    /// - denomination: Option<u64> (fixed note amount, if any)
+    /// - params: PoolParams (`min_note_age` at most `MAX_NOTE_AGE`)
+    InitializePool {
+        pool_id: u64,
+        asset: Pubkey,
+        denomination: Option<u64>,
+        params: PoolParams,
+    },
 
     /// Deposit (shield) into a note commitment.
     ///
+    /// The leaf `Poseidon(amount, commitment)` is appended to the deposit
+    /// queue and enters the tree at the next `Crank`. The user also pays the
+    /// pool's `crank_reward` into the queue. In multi-asset pools the leaf
+    /// is `Poseidon(asset_id, Poseidon(amount, commitment))`.
+    ///
     /// Accounts:
-    /// 0. [writable] Global state
-    /// 1. [signer]   User
-    /// 2. [writable] Note account (PDA)
-    /// 3. []         System program
+    /// 0. []         Pool
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable, signer] User
+    /// 3. [writable] Note account (PDA `["note", pool, commitment]`)
+    /// 4. [writable] Vault (PDA `["vault", pool]`)
+    /// 5. []         System program
+    /// 6. [writable] Vault stats (PDA `["stats", pool, asset]`)
+    ///
+    /// Token pools additionally pass:
+    /// 7. []         Mint
+    /// 8. []         Token program
+    /// 9. [writable] User token account (source)
     ///
     /// Data:
+    /// - amount: u64 (moved into the vault; the leaf uses the amount the
+    ///   vault received after any Token-2022 transfer fee, which must equal
+    ///   the pool denomination for fixed-denomination pools)
     /// - commitment: [u8; 32]
+    /// - encrypted_note: Vec<u8> (note ciphertext for the owner, emitted in the Deposit event)
     Deposit {
+        amount: u64,
         commitment: [u8; 32],
+        encrypted_note: Vec<u8>,
     },
 
-    /// Private transfer using zkSNARKs / STARKs.
+    /// Private 2-in-2-out transfer using zkSNARKs / STARKs.
+    ///
+    /// Public inputs (see `PublicInputs::to_field_elements`): root, public
+    /// amount (0), fee, relayer, ext data hash, both nullifiers and both new
+    /// commitments. The fee is paid from the vault to the relayer, who pays
+    /// the crank reward for both new commitments into the deposit queue.
+    /// Not available in fixed-denomination pools. In multi-asset pools the
+    /// asset of the notes is public (see `HandCrankedInstruction`).
     ///
     /// Accounts:
-    /// 0. [writable] Global state
-    /// 1. [writable] Spent note account
-    /// 2. [writable] New note account 1
-    /// 3. [writable] New note account 2 (optional; can be zeroed)
-    /// 4. [signer]   Prover / user
-    /// 5. []         System program
+    /// 0. []         Pool
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable] Nullifier account 1 (PDA `["nullifier", pool, nullifier_1]`)
+    /// 3. [writable] Nullifier account 2 (PDA `["nullifier", pool, nullifier_2]`)
+    /// 4. [writable] Vault
+    /// 5. [writable, signer] Relayer / prover
+    /// 6. []         System program
+    /// 7. [writable] Vault stats (PDA `["stats", pool, asset]`)
+    ///
+    /// Then, only with `ProofSource::Buffer` / `ProofSource::Session`:
+    /// - [writable] Proof buffer / verification session of the relayer
+    ///
+    /// Then, unless `ProofSource::Session`:
+    /// - []         Verifying key (PDA `["verifying-key", pool, proof_system, circuit]`)
+    ///
+    /// Then, only for token pools:
+    /// - []         Mint
+    /// - []         Token program
+    /// - [writable] Relayer token account (receives the fee)
     ///
     /// Data:
     /// - proof_system: u8
-    /// - proof: Vec<u8> (serialized)
-    /// - public_inputs_commitment: [u8; 32] (hash root / commitment)
-    /// - nullifier: [u8; 32]
+    /// - proof: ProofSource
+    /// - root: [u8; 32] (a recent tree root)
+    /// - nullifier_1 / nullifier_2: [u8; 32]
+    /// - new_commitment_1 / new_commitment_2: [u8; 32]
+    /// - fee: u64
+    /// - encrypted_note_1 / encrypted_note_2: Vec<u8> (hashed into the ext data)
+    /// - auditor_memo: Option<AuditorMemo> (required iff the pool has an
+    ///   auditor; its fields are public inputs and it is emitted in an
+    ///   `AuditorMemo` event)
     PrivateTransfer {
         proof_system: ProofSystem,
-        proof: Vec<u8>,
-        public_inputs_commitment: [u8; 32],
-        nullifier: [u8; 32],
+        proof: ProofSource,
+        root: [u8; 32],
+        nullifier_1: [u8; 32],
+        nullifier_2: [u8; 32],
         new_commitment_1: [u8; 32],
         new_commitment_2: [u8; 32],
+        fee: u64,
+        encrypted_note_1: Vec<u8>,
+        encrypted_note_2: Vec<u8>,
+        auditor_memo: Option<AuditorMemo>,
     },
 
-    /// Withdraw (unshield) a note.
+    /// Withdraw (unshield) a note with a membership proof.
+    ///
+    /// Public inputs: root, root slot, amount, fee, relayer, ext data hash
+    /// (binding the recipient) and the nullifier. The recipient receives
+    /// `amount - fee`, the relayer `fee`. In fixed-denomination pools `amount`
+    /// must equal the denomination and the proof is for `Circuit::Membership`.
+    ///
+    /// The root slot is the slot `root` became current at, read from the
+    /// pool tree. In pools with a `min_note_age` it must be at least that
+    /// many slots in the past.
     ///
     /// Accounts:
-    /// 0. [writable] Global state
-    /// 1. [writable] Note account
-    /// 2. [signer]   Recipient
-    /// 3. []         System program
+    /// 0. []         Pool
+    /// 1. [writable] Nullifier account (PDA `["nullifier", pool, nullifier]`)
+    /// 2. [writable] Vault
+    /// 3. [writable] Recipient
+    /// 4. [writable, signer] Relayer / fee payer
+    /// 5. []         System program
+    /// 6. [writable] Vault stats (PDA `["stats", pool, asset]`)
+    ///
+    /// Then, only with an association proof:
+    /// - []         Association set (PDA `["association", pool, set_id]`)
+    /// - []         Verifying key of its `Circuit::Association` proof system
+    ///
+    /// Then, only with `ProofSource::Buffer` / `ProofSource::Session`:
+    /// - [writable] Proof buffer / verification session of the relayer
+    ///
+    /// Then, unless `ProofSource::Session`:
+    /// - []         Verifying key (PDA `["verifying-key", pool, proof_system, circuit]`)
+    ///
+    /// Then, only for token pools (the recipient is then a token account):
+    /// - []         Mint
+    /// - []         Token program
+    /// - [writable] Relayer token account (receives the fee)
+    ///
+    /// Then, only with a callback:
+    /// - []         Callback program
+    /// - the callback's accounts, in the order of `WithdrawCallback::accounts`
     ///
     /// Data:
+    /// - proof_system: u8
+    /// - proof: ProofSource
+    /// - root: [u8; 32] (one of the last `ROOT_HISTORY_SIZE` tree roots, or
+    ///   an age checkpoint of the tree)
     /// - nullifier: [u8; 32]
+    /// - amount: u64
+    /// - fee: u64
+    /// - association: Option<AssociationProof> (recorded in the event)
+    /// - callback: Option<WithdrawCallback> (invoked after the payout)
     Withdraw {
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        root: [u8; 32],
+        nullifier: [u8; 32],
+        amount: u64,
+        fee: u64,
+        association: Option<AssociationProof>,
+        callback: Option<WithdrawCallback>,
+    },
+
+    /// Create a confidential (account-model) balance for the signer.
+    ///
+    /// The ElGamal public key is taken from a `VerifyPubkeyValidity`
+    /// context state account created by the zk-token proof program.
+    ///
+    /// Proof contexts consumed by this and the other confidential
+    /// instructions must have the owner as `context_state_authority`; they
+    /// are closed to the owner afterwards.
+    ///
+    /// Accounts:
+    /// 0. [writable] Confidential account (PDA `["confidential", owner]`)
+    /// 1. [writable, signer] Owner
+    /// 2. [writable] Pubkey validity proof context account
+    /// 3. []         System program
+    /// 4. [writable] Confidential vault, funded to rent exemption if short
+    /// 5. []         zk-token proof program
+    ConfigureConfidentialAccount,
+
+    /// Move lamports into the confidential vault and add them to the
+    /// owner's pending balance.
+    ///
+    /// Accounts:
+    /// 0. [writable] Confidential account
+    /// 1. [writable] Confidential vault (PDA `["confidential-vault"]`)
+    /// 2. [signer]   Owner
+    /// 3. []         System program
+    ConfidentialDeposit {
+        amount: u64,
+    },
+
+    /// Pay `amount` lamports out of the owner's available balance.
+    ///
+    /// Accounts:
+    /// 0. [writable] Confidential account
+    /// 1. [writable] Confidential vault
+    /// 2. [writable, signer] Owner
+    /// 3. [writable] Withdraw proof context account
+    /// 4. []         System program
+    /// 5. []         zk-token proof program
+    ConfidentialWithdraw {
+        amount: u64,
+    },
+
+    /// Move a hidden amount between two confidential accounts.
+    ///
+    /// Amount, range and equality proofs are checked by the zk-token proof
+    /// program; this instruction consumes its `VerifyTransfer` context.
+    ///
+    /// Accounts:
+    /// 0. [writable] Source confidential account
+    /// 1. [writable] Destination confidential account
+    /// 2. [writable, signer] Source owner
+    /// 3. [writable] Transfer proof context account
+    /// 4. []         zk-token proof program
+    ConfidentialTransfer,
+
+    /// Fold the pending balance into the available balance.
+    ///
+    /// Accounts:
+    /// 0. [writable] Confidential account
+    /// 1. [signer]   Owner
+    ApplyConfidentialPendingBalance,
+
+    /// Move up to `max_leaves` queued leaves into the pool's tree. Only the
+    /// root after the last leaf enters the root history, so a large crank
+    /// does not evict the roots pending proofs were made against.
+    ///
+    /// Permissionless. The caller receives `crank_reward` lamports per leaf
+    /// from the queue's fee balance.
+    ///
+    /// Accounts:
+    /// 0. [writable] Pool
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable] Crank caller (reward recipient)
+    Crank {
+        max_leaves: u8,
+    },
+
+    /// Move the first `count` queued leaves into the tree at once.
+    ///
+    /// An off-chain prover shows that appending the leaves hashed into
+    /// `leaves_hash` at the current `next_index` forms a subtree with root
+    /// `subtree_root` and turns `old_root` into `new_root`. The program only
+    /// recomputes `leaves_hash` from the queue and hashes `subtree_root` up
+    /// the `TREE_DEPTH - log2(count)` remaining levels. `count` must be a
+    /// power of two and `next_index` a multiple of it. Permissionless, with
+    /// the same reward as `Crank`.
+    ///
+    /// Accounts:
+    /// 0. [writable] Pool
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable] Caller (reward recipient)
+    /// 3. []         Verifying key (PDA `["verifying-key", pool, proof_system, circuit]`)
+    ///
+    /// Data:
+    /// - proof_system: u8
+    /// - proof: Vec<u8>
+    /// - count: u16
+    /// - old_root / new_root: [u8; 32]
+    /// - leaves_hash: [u8; 32] (`public_inputs::leaves_hash` of the leaves)
+    /// - subtree_root: [u8; 32]
+    BatchInsert {
+        proof_system: ProofSystem,
+        proof: Vec<u8>,
+        count: u16,
+        old_root: [u8; 32],
+        new_root: [u8; 32],
+        leaves_hash: [u8; 32],
+        subtree_root: [u8; 32],
+    },
+
+    /// Like `Withdraw`, but the recipient is paid later by `ExecuteWithdraw`.
+    ///
+    /// The proof is verified, the nullifier consumed and the relayer fee
+    /// paid immediately. `amount - fee` is recorded in a pending withdrawal
+    /// with a release slot drawn from the pool's withdraw delay window.
+    ///
+    /// Accounts: as `Withdraw` (0-6), then
+    /// 7. [writable] Pending withdrawal (PDA `["pending", pool, nullifier]`)
+    ///
+    /// followed by the association, proof, verifying key and token accounts
+    /// of `Withdraw`. Delayed withdrawals take no callback.
+    RequestWithdraw {
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        root: [u8; 32],
+        nullifier: [u8; 32],
+        amount: u64,
+        fee: u64,
+        association: Option<AssociationProof>,
+    },
+
+    /// Pay out a pending withdrawal once its release slot has passed and
+    /// close the record. Permissionless.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Pending withdrawal
+    /// 2. [writable] Vault
+    /// 3. [writable] Recipient (token account for token pools)
+    /// 4. []         System program
+    /// 5. [writable] Vault stats (PDA `["stats", pool, asset]`)
+    ///
+    /// Token pools additionally pass:
+    /// 6. []         Mint
+    /// 7. []         Token program
+    ExecuteWithdraw,
+
+    /// Fail with `InvariantViolated` if the vault balance is below the
+    /// pool's tracked liability (unspent notes plus pending withdrawals).
+    /// Permissionless and read-only.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. []         Vault
+    /// 2. []         Vault stats (PDA `["stats", pool, asset]`; names the
+    ///    vault's asset)
+    AuditInvariant,
+
+    /// Publish a new root for an association set, creating the set on
+    /// first use. Only the pool's curator may call this.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Association set (PDA `["association", pool, set_id]`)
+    /// 2. [writable, signer] Curator
+    /// 3. []         System program
+    PublishAssociationRoot {
+        set_id: u64,
+        root: [u8; 32],
+    },
+
+    /// Create a buffer of `len` bytes for staging a proof
+    /// (PDA `["proof-buffer", authority, buffer_id]`).
+    ///
+    /// The authority pays rent for the whole proof up front, but the account
+    /// starts at most `MAX_PERMITTED_DATA_INCREASE` bytes long (the most a
+    /// program can create) and grows as chunks are appended; see
+    /// `WriteProofChunk`.
+    ///
+    /// Accounts:
+    /// 0. [writable] Proof buffer
+    /// 1. [writable, signer] Authority / payer
+    /// 2. []         System program
+    OpenProofBuffer {
+        buffer_id: u64,
+        len: u32,
+    },
+
+    /// Write `data` into a proof buffer at `offset`. A chunk may overwrite
+    /// any bytes the account already holds or append to its end, growing
+    /// it; past the first `MAX_PERMITTED_DATA_INCREASE` bytes the proof must
+    /// therefore be written in order, and a chunk starting past the end of
+    /// the account fails with `InvalidInstruction`.
+    ///
+    /// Accounts:
+    /// 0. [writable] Proof buffer
+    /// 1. [signer]   Authority
+    WriteProofChunk {
+        offset: u32,
+        data: Vec<u8>,
+    },
+
+    /// Close an unused proof buffer and refund its rent to the authority.
+    ///
+    /// Accounts:
+    /// 0. [writable] Proof buffer
+    /// 1. [writable, signer] Authority
+    CloseProofBuffer,
+
+    /// Start checking a proof over several transactions
+    /// (PDA `["session", prover, session_id]`). Consumed by a transfer or
+    /// withdraw with `ProofSource::Session` once `StepVerificationSession`
+    /// has reached `SessionStep::Verified`.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Verification session
+    /// 2. [writable, signer] Prover / payer (the relayer that will use it)
+    /// 3. []         System program
+    /// 4. [writable] Proof buffer (only with `ProofSource::Buffer`)
+    OpenVerificationSession {
+        session_id: u64,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        proof: ProofSource,
+        public_inputs: Vec<[u8; 32]>,
+    },
+
+    /// Run up to `max_steps` verification steps. Anyone may crank a
+    /// session until it times out.
+    ///
+    /// Accounts:
+    /// 0. [writable] Verification session
+    /// 1. []         Verifying key of the session's pool, proof system and circuit
+    StepVerificationSession {
+        max_steps: u8,
+    },
+
+    /// Close a session and refund its rent to the prover. Anyone may close
+    /// a session that has timed out; before that only the prover can.
+    ///
+    /// Accounts:
+    /// 0. [writable] Verification session
+    /// 1. [writable] Prover
+    /// 2. [signer]   Caller
+    CloseVerificationSession,
+
+    /// Create an empty constant-product reserve for swaps between two
+    /// pools (PDA `["reserve", pool_a, pool_b]`). The signer must be the
+    /// authority of both pools.
+    ///
+    /// Accounts:
+    /// 0. []         Pool A
+    /// 1. []         Pool B
+    /// 2. [writable] Swap reserve
+    /// 3. [writable, signer] Authority / payer
+    /// 4. []         System program
+    InitializeSwapReserve {
+        fee_bps: u16,
+    },
+
+    /// Move liquidity from the authority into the two pool vaults and
+    /// credit it to the reserve.
+    ///
+    /// Accounts:
+    /// 0. []         Pool A
+    /// 1. []         Pool B
+    /// 2. [writable] Swap reserve
+    /// 3. [writable] Vault A
+    /// 4. [writable] Vault B
+    /// 5. [writable, signer] Authority
+    /// 6. []         System program
+    ///
+    /// Then, for each of pool A and pool B that is a token pool:
+    /// - []         Mint
+    /// - []         Token program
+    /// - [writable] Authority token account
+    AddSwapLiquidity {
+        amount_a: u64,
+        amount_b: u64,
+    },
+
+    /// Pay reserve liquidity out of the two pool vaults to the authority.
+    /// Accounts as `AddSwapLiquidity`.
+    RemoveSwapLiquidity {
+        amount_a: u64,
+        amount_b: u64,
+    },
+
+    /// Spend a note of the input pool and queue a note of the output pool
+    /// worth what the swap reserve gives for it (`Circuit::Swap`).
+    ///
+    /// The note is worth `amount_in`; the relayer is paid `fee` from the
+    /// input vault and `amount_in - fee` is swapped. The output leaf is
+    /// `Poseidon(amount_out, output_commitment)`, like a deposit, and the
+    /// proof fixes `min_amount_out`.
+    ///
+    /// Swaps are not amount-private: `amount_in`, `fee` and `amount_out`
+    /// are public, and the `Swap` event ties the spent nullifier to the
+    /// output leaf, which is as public as a deposit. Only the note owners
+    /// stay hidden.
+    ///
+    /// If either pool has an auditor (both must then have the same one),
+    /// `auditor_memo` is required, the proof is for `Circuit::AuditedSwap`
+    /// and the memo is emitted in an `AuditorMemo` event with the output
+    /// leaf index.
+    ///
+    /// The proof is bound to the slot `root` became current at; with a
+    /// `min_note_age` on the input pool that slot must be at least that
+    /// many slots in the past.
+    ///
+    /// Accounts:
+    /// 0. []         Input pool
+    /// 1. [writable] Input vault stats (PDA `["stats", input pool, asset]`)
+    /// 2. [writable] Nullifier account (PDA `["nullifier", input pool, nullifier]`)
+    /// 3. [writable] Input vault
+    /// 4. []         Output pool
+    /// 5. [writable] Output deposit queue
+    /// 6. [writable] Swap reserve of the two pools
+    /// 7. [writable, signer] Relayer / fee payer
+    /// 8. []         System program
+    /// 9. [writable] Output vault stats (PDA `["stats", output pool, asset]`)
+    ///
+    /// Then, only with `ProofSource::Buffer` / `ProofSource::Session`:
+    /// - [writable] Proof buffer / verification session of the relayer
+    ///
+    /// Then, unless `ProofSource::Session`:
+    /// - []         Verifying key of the input pool for `Circuit::Swap` /
+    ///   `Circuit::AuditedSwap`
+    ///
+    /// Then, only if the input pool is a token pool:
+    /// - []         Mint
+    /// - []         Token program
+    /// - [writable] Relayer token account (receives the fee)
+    PrivateSwap {
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        root: [u8; 32],
         nullifier: [u8; 32],
+        amount_in: u64,
+        fee: u64,
+        min_amount_out: u64,
+        output_commitment: [u8; 32],
+        encrypted_note: Vec<u8>,
+        auditor_memo: Option<AuditorMemo>,
+    },
+
+    /// Create the vault of one asset of a multi-asset pool. Permissionless;
+    /// the payer funds the vault's rent.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Vault (PDA `["vault", pool, mint]`)
+    /// 2. [writable, signer] Payer
+    /// 3. []         System program
+    /// 4. [writable] Vault stats (PDA `["stats", pool, mint]`)
+    /// 5. []         Mint, or the system program for SOL
+    /// 6. []         Token program (token assets only)
+    InitializeAssetVault,
+
+    /// Create the anonymity mining program of a pool (PDA
+    /// `["rewards", pool]`), paying rewards as notes of `reward_pool`.
+    ///
+    /// The signer must be the authority of the pool. The reward pool must
+    /// take arbitrary amounts of a single asset.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. []         Reward pool
+    /// 2. [writable] Reward program (PDA)
+    /// 3. [writable, signer] Authority / payer
+    /// 4. []         System program
+    ///
+    /// Data:
+    /// - rate: u64 (reward per unit of note amount per slot, scaled by
+    ///   `RewardProgram::RATE_SCALE`)
+    /// - checkpoint_interval: u64 (fewest slots between checkpoints, at
+    ///   least `RewardProgram::MIN_CHECKPOINT_INTERVAL`)
+    InitializeRewardProgram {
+        rate: u64,
+        checkpoint_interval: u64,
     },
+
+    /// Move `amount` of the reward pool asset into the reward pool vault
+    /// and add what the vault received to the reward budget. Anyone may
+    /// fund. The funds count as shielded value of the reward pool from
+    /// here on, since claims issue them as notes of hidden value.
+    ///
+    /// Accounts:
+    /// 0. [writable] Reward program
+    /// 1. []         Reward pool
+    /// 2. [writable] Reward pool vault
+    /// 3. [writable, signer] Funder
+    /// 4. []         System program
+    /// 5. [writable] Reward pool vault stats (PDA `["stats", reward pool, asset]`)
+    ///
+    /// Token reward pools additionally pass:
+    /// 6. []         Mint
+    /// 7. []         Token program
+    /// 8. [writable] Funder token account (source)
+    FundRewards { amount: u64 },
+
+    /// Record the pool's current root with the current slot as a reward
+    /// checkpoint. Permissionless; at most once per `checkpoint_interval`.
+    /// Checkpoints are numbered from 0 and the last
+    /// `REWARD_CHECKPOINTS` can be claimed against.
+    ///
+    /// The pool's unspent value is recorded with the root, and the most
+    /// the notes of the previous checkpoint can claim up to this one is
+    /// reserved from the budget. If the budget cannot cover every slot
+    /// since the previous checkpoint, the span is only rewarded for as many
+    /// slots as it covers.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Reward program
+    /// 2. []         Pool vault stats (PDA `["stats", pool, asset]`)
+    RecordCheckpoint,
+
+    /// Claim anonymity mining rewards for a note (`Circuit::Reward`).
+    ///
+    /// The note must be in the tree at checkpoint `start_checkpoint`; the
+    /// reward covers the slots rewarded from that checkpoint to the later
+    /// `end_checkpoint`. The claim spends the note under `nullifier`, as a
+    /// transfer would, so it was unspent for the whole span and cannot be
+    /// rewarded twice; `new_commitment` is a note of the pool of the same
+    /// value, which earns from the first checkpoint after it is cranked.
+    ///
+    /// `reward_commitment` is a note of the reward pool whose hidden value
+    /// is the reward less `fee`; the relayer is paid `fee` from the reward
+    /// pool vault and pays the crank reward of both new notes. Neither the
+    /// note's value nor the reward is revealed; the claim only shows that
+    /// some note existed at `start_checkpoint`. With a `min_note_age` on
+    /// the pool, `start_checkpoint` must be at least that many slots old.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Deposit queue (PDA `["queue", pool]`)
+    /// 2. [writable] Nullifier account (PDA `["nullifier", pool, nullifier]`)
+    /// 3. []         Reward program
+    /// 4. []         Reward pool
+    /// 5. [writable] Reward pool deposit queue
+    /// 6. [writable] Reward pool vault
+    /// 7. [writable, signer] Relayer / fee payer
+    /// 8. []         System program
+    /// 9. [writable] Reward pool vault stats (PDA `["stats", reward pool, asset]`)
+    ///
+    /// Then, only with `ProofSource::Buffer` / `ProofSource::Session`:
+    /// - [writable] Proof buffer / verification session of the relayer
+    ///
+    /// Then, unless `ProofSource::Session`:
+    /// - []         Verifying key of the pool for `Circuit::Reward`
+    ///
+    /// Then, only if the reward pool is a token pool:
+    /// - []         Mint
+    /// - []         Token program
+    /// - [writable] Relayer token account (receives the fee)
+    ClaimReward {
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        start_checkpoint: u64,
+        end_checkpoint: u64,
+        nullifier: [u8; 32],
+        new_commitment: [u8; 32],
+        encrypted_note: Vec<u8>,
+        fee: u64,
+        reward_commitment: [u8; 32],
+        encrypted_reward_note: Vec<u8>,
+    },
+
+    /// Create the verifying key account of a pool for `circuit` under
+    /// `proof_system` (PDA `["verifying-key", pool, proof_system, circuit]`),
+    /// `len` bytes long, filled with `WriteVerifyingKey` and frozen with
+    /// `FinalizeVerifyingKey`. Only the pool authority can create or write
+    /// keys, since a key decides which proofs the pool accepts. PLONK keys
+    /// are rejected with `UnsupportedProofSystem`.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Verifying key
+    /// 2. [writable, signer] Pool authority / payer
+    /// 3. []         System program
+    InitializeVerifyingKey {
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        len: u32,
+    },
+
+    /// Write `data` into a verifying key at `offset`. Fails once the key is
+    /// finalized.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Verifying key
+    /// 2. [signer]   Pool authority
+    WriteVerifyingKey {
+        offset: u32,
+        data: Vec<u8>,
+    },
+
+    /// Freeze a fully written verifying key. Proofs are only checked
+    /// against finalized keys, and a finalized key cannot be written again,
+    /// so the authority cannot change the key under pending proofs.
+    ///
+    /// Accounts:
+    /// 0. []         Pool
+    /// 1. [writable] Verifying key
+    /// 2. [signer]   Pool authority
+    FinalizeVerifyingKey,
 }
 
 impl HandCrankedInstruction {
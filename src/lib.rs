@@ -1,5 +1,18 @@
-#![cfg_attr(not(feature = "std"), no_std)]
-
+pub mod cpi;
+#[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
+#[path = "src/error.rs"]
+pub mod error;
+pub mod events;
+pub mod instruction;
+pub mod merkle;
 pub mod processor;
+pub mod processor2;
+#[path = "src/state.rs"]
+pub mod state;
+pub mod token;
+pub mod utils;
+pub mod zk;
+#[cfg(not(target_arch = "bpf"))]
+pub mod client;
 pub use solana_program;
@@ -0,0 +1,452 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    poseidon::{hashv, Endianness, Parameters},
+    program_error::ProgramError,
+};
+
+use crate::error::HandCrankedError;
+
+/// Depth of the note commitment tree (2^20 leaves).
+pub const TREE_DEPTH: usize = 20;
+
+/// Number of recent roots accepted by proofs.
+pub const ROOT_HISTORY_SIZE: usize = 30;
+
+/// Length, in slots, of the epochs the tree keeps an age checkpoint for
+/// (about ten minutes).
+pub const AGE_CHECKPOINT_INTERVAL: u64 = 1_500;
+
+/// Age checkpoints kept by the tree, one per epoch in which it changed.
+pub const AGE_CHECKPOINTS: usize = 146;
+
+/// Longest `min_note_age` the age checkpoints serve (about a day): a note
+/// of that age can always be proven against a retained checkpoint root.
+pub const MAX_NOTE_AGE: u64 = (AGE_CHECKPOINTS as u64 - 2) * AGE_CHECKPOINT_INTERVAL;
+
+/// Value of an empty leaf.
+pub const ZERO_LEAF: [u8; 32] = [0u8; 32];
+
+/// Poseidon hash of two tree nodes (BN254, little-endian field elements).
+pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> Result<[u8; 32], ProgramError> {
+    hashv(
+        Parameters::Bn254X5,
+        Endianness::LittleEndian,
+        &[left, right],
+    )
+    .map(|h| h.to_bytes())
+    .map_err(|_| HandCrankedError::InvalidCommitment.into())
+}
+
+/// Roots of empty subtrees, `ZERO_HASHES[0]` being the empty leaf and
+/// `ZERO_HASHES[i + 1] = hash_pair(ZERO_HASHES[i], ZERO_HASHES[i])`.
+pub const ZERO_HASHES: [[u8; 32]; TREE_DEPTH + 1] = [
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0x64, 0x48, 0xb6, 0x46, 0x84, 0xee, 0x39, 0xa8, 0x23, 0xd5, 0xfe, 0x5f, 0xd5, 0x24, 0x31,
+        0xdc, 0x81, 0xe4, 0x81, 0x7b, 0xf2, 0xc3, 0xea, 0x3c, 0xab, 0x9e, 0x23, 0x9e, 0xfb, 0xf5,
+        0x98, 0x20,
+    ],
+    [
+        0xe1, 0xf1, 0xb1, 0x60, 0x44, 0x77, 0xa4, 0x67, 0xf0, 0x8d, 0xc6, 0x9d, 0xcb, 0x44, 0x1a,
+        0x26, 0xec, 0xa7, 0x84, 0xf5, 0x6f, 0x1a, 0x30, 0xdf, 0x63, 0x22, 0xb1, 0xcd, 0x3d, 0x67,
+        0x69, 0x10,
+    ],
+    [
+        0x38, 0xd2, 0x56, 0xb8, 0xb2, 0x7e, 0xd5, 0x28, 0xd5, 0x1d, 0x37, 0x50, 0xea, 0x6e, 0x7c,
+        0x46, 0x06, 0x21, 0xf7, 0x50, 0x8d, 0x75, 0x3d, 0x2e, 0xaf, 0xe2, 0x7e, 0x53, 0x31, 0x33,
+        0xf4, 0x18,
+    ],
+    [
+        0x2a, 0x95, 0xbc, 0x9d, 0x55, 0x97, 0xac, 0xca, 0x65, 0x82, 0x56, 0x1a, 0x57, 0x28, 0xb7,
+        0xf1, 0x45, 0x23, 0xa5, 0x3b, 0xe9, 0xff, 0x20, 0x63, 0xd3, 0xb0, 0x17, 0xcb, 0x37, 0xd8,
+        0xf9, 0x07,
+    ],
+    [
+        0x55, 0x3f, 0x18, 0x39, 0x16, 0xec, 0x5c, 0x7b, 0x4d, 0xad, 0xb2, 0x94, 0x8c, 0xc5, 0x99,
+        0xa6, 0x07, 0x29, 0xf3, 0x5d, 0x4c, 0x1f, 0x63, 0xc9, 0xf5, 0xb3, 0x46, 0x87, 0x5e, 0xcf,
+        0x94, 0x2b,
+    ],
+    [
+        0x78, 0x9d, 0xa0, 0x2e, 0xa3, 0xdd, 0x11, 0x1d, 0x61, 0x53, 0xb9, 0x51, 0x69, 0x1e, 0xd7,
+        0xfe, 0xbc, 0xe1, 0xa9, 0xcc, 0x22, 0x7d, 0xea, 0x46, 0x96, 0x45, 0x66, 0xa6, 0xc5, 0x93,
+        0xee, 0x2d,
+    ],
+    [
+        0x9d, 0x34, 0x87, 0x3c, 0xbe, 0xaa, 0xa4, 0xa8, 0x7f, 0xac, 0xb5, 0x8c, 0xa8, 0x15, 0x05,
+        0x8b, 0x7b, 0x59, 0x39, 0xb6, 0x1e, 0x60, 0xcf, 0x82, 0xe9, 0x84, 0x2b, 0xa2, 0xe5, 0x95,
+        0x82, 0x07,
+    ],
+    [
+        0x61, 0xcc, 0xf3, 0x99, 0x3a, 0xbe, 0x4c, 0x44, 0x1a, 0x21, 0x41, 0x4a, 0x27, 0x2e, 0x6b,
+        0x61, 0x2a, 0x47, 0x64, 0x45, 0x86, 0xec, 0x1b, 0x50, 0xa6, 0x27, 0x60, 0x8f, 0xf1, 0xe5,
+        0xa5, 0x2f,
+    ],
+    [
+        0x47, 0xd7, 0xfc, 0x14, 0xa6, 0x56, 0x21, 0x3e, 0xab, 0x28, 0xe2, 0xe3, 0xcc, 0x7a, 0x5e,
+        0xe4, 0x66, 0x1f, 0x94, 0x9e, 0x38, 0x80, 0xb7, 0xec, 0x21, 0xfd, 0xd8, 0xd0, 0x76, 0x43,
+        0x88, 0x0e,
+    ],
+    [
+        0xf2, 0x0a, 0x19, 0xda, 0xe5, 0x75, 0x61, 0xde, 0x33, 0x35, 0x71, 0x57, 0xf9, 0x92, 0x58,
+        0xf9, 0x69, 0xb4, 0x2e, 0xa5, 0xd1, 0x7a, 0x71, 0x28, 0x1e, 0x4f, 0x49, 0x72, 0xda, 0x01,
+        0x72, 0x1b,
+    ],
+    [
+        0x36, 0x76, 0x7d, 0xce, 0xfa, 0x6b, 0xbc, 0xbe, 0xb5, 0x08, 0x08, 0x65, 0xe4, 0xe1, 0xe6,
+        0xa6, 0x19, 0x98, 0x24, 0x01, 0xb2, 0xc0, 0x00, 0x52, 0x38, 0x36, 0x5e, 0x72, 0x22, 0x88,
+        0x8d, 0x1f,
+    ],
+    [
+        0x5a, 0xf8, 0xb5, 0x71, 0x04, 0x9a, 0x87, 0xd0, 0xa8, 0x88, 0xcf, 0x2a, 0xa1, 0xb0, 0x62,
+        0x61, 0xfb, 0xfc, 0x8c, 0xba, 0x89, 0x15, 0x70, 0xb9, 0xaf, 0x4b, 0x91, 0x6c, 0xf6, 0x82,
+        0x5d, 0x2c,
+    ],
+    [
+        0xd0, 0xbf, 0xbf, 0xe0, 0x70, 0xf2, 0x58, 0x64, 0x64, 0xf4, 0x13, 0xa1, 0xaa, 0xc4, 0xf5,
+        0x4e, 0x13, 0xa1, 0x3f, 0xdf, 0x5a, 0x7f, 0x95, 0x20, 0xb8, 0x0b, 0x94, 0xa0, 0x48, 0x41,
+        0xc5, 0x14,
+    ],
+    [
+        0x0c, 0xe8, 0xeb, 0xf4, 0x4b, 0x8e, 0x11, 0x16, 0xd4, 0x89, 0xad, 0x8c, 0x58, 0x25, 0xbe,
+        0x11, 0xaf, 0xb9, 0xd8, 0x44, 0xee, 0xc0, 0x10, 0x1e, 0x96, 0x6f, 0x98, 0x2f, 0xb1, 0x33,
+        0x0d, 0x19,
+    ],
+    [
+        0x92, 0x6c, 0xe0, 0x25, 0x93, 0x64, 0xb3, 0xa5, 0x0a, 0x51, 0xaf, 0x96, 0x65, 0xae, 0x67,
+        0x11, 0xed, 0x73, 0xad, 0x14, 0x49, 0x35, 0x17, 0xac, 0x52, 0x41, 0x70, 0xce, 0xa9, 0x8a,
+        0xf9, 0x22,
+    ],
+    [
+        0x23, 0x73, 0xba, 0x8b, 0xd3, 0x53, 0xb7, 0xf8, 0xee, 0xcc, 0x6e, 0xc6, 0x29, 0x6f, 0x52,
+        0x5a, 0x57, 0x6a, 0xbf, 0x72, 0x8d, 0x22, 0x6f, 0x9f, 0x0b, 0x88, 0xe5, 0x6c, 0x9b, 0x7c,
+        0x7c, 0x2a,
+    ],
+    [
+        0x92, 0xb9, 0x36, 0x3f, 0x64, 0xdd, 0x75, 0x4d, 0x95, 0x8b, 0x98, 0xc2, 0xc9, 0x43, 0x00,
+        0x47, 0xfc, 0x3f, 0x46, 0x4d, 0xc1, 0xf9, 0x7a, 0xc6, 0xc1, 0x8e, 0x69, 0x58, 0xe5, 0x86,
+        0x81, 0x2e,
+    ],
+    [
+        0x0f, 0xf1, 0x1f, 0x1c, 0x9d, 0x24, 0x46, 0x35, 0x27, 0x92, 0x73, 0x64, 0xad, 0x6e, 0xef,
+        0x8a, 0x94, 0xae, 0x0d, 0x05, 0xcf, 0xc8, 0xe2, 0x49, 0xab, 0x4e, 0x9a, 0x1e, 0x57, 0xc5,
+        0x57, 0x0f,
+    ],
+    [
+        0xca, 0x2c, 0xf7, 0x34, 0x61, 0xe3, 0x9c, 0x3c, 0xe4, 0x46, 0x7d, 0x69, 0x10, 0xe3, 0x78,
+        0xfe, 0x1c, 0x0e, 0x80, 0x88, 0x43, 0x3d, 0xf6, 0xd5, 0x4a, 0x55, 0xfb, 0xb5, 0x67, 0xee,
+        0x30, 0x18,
+    ],
+    [
+        0x3e, 0x1f, 0x19, 0x22, 0xdf, 0xb6, 0x71, 0xd3, 0xf9, 0x12, 0xf7, 0xea, 0x46, 0x1e, 0x0a,
+        0x88, 0xee, 0x84, 0x8f, 0xdd, 0xe1, 0x2b, 0x6c, 0x18, 0xab, 0x1a, 0xd2, 0xc5, 0x6a, 0xe7,
+        0x34, 0x21,
+    ],
+];
+
+/// Append-only Merkle tree kept on-chain: only the rightmost filled node
+/// of each level, a ring buffer of recent roots with the slot each became
+/// current at, and a ring of age checkpoints are stored.
+///
+/// Every insertion pushes one root, however many leaves it appends, so
+/// `roots` can be cycled by anyone willing to pay for `ROOT_HISTORY_SIZE`
+/// cranks. The age checkpoints keep
+/// the last root of each `AGE_CHECKPOINT_INTERVAL`-slot epoch in which the
+/// tree changed, so they are evicted at most once per epoch however many
+/// leaves arrive, and old enough roots stay available for `min_note_age`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct IncrementalMerkleTree {
+    pub next_index: u64,
+    pub filled_subtrees: [[u8; 32]; TREE_DEPTH],
+    pub roots: [[u8; 32]; ROOT_HISTORY_SIZE],
+    pub current_root_index: u64,
+    /// `root_slots[i]` is the slot `roots[i]` became current at.
+    pub root_slots: [u64; ROOT_HISTORY_SIZE],
+    /// Last root of each recent epoch in which the tree changed.
+    pub checkpoint_roots: [[u8; 32]; AGE_CHECKPOINTS],
+    /// `checkpoint_slots[i]` is the slot `checkpoint_roots[i]` became
+    /// current at.
+    pub checkpoint_slots: [u64; AGE_CHECKPOINTS],
+    pub current_checkpoint_index: u64,
+}
+
+impl IncrementalMerkleTree {
+    pub const LEN: usize = 8
+        + 32 * TREE_DEPTH
+        + 32 * ROOT_HISTORY_SIZE
+        + 8
+        + 8 * ROOT_HISTORY_SIZE
+        + 32 * AGE_CHECKPOINTS
+        + 8 * AGE_CHECKPOINTS
+        + 8;
+
+    pub fn new() -> Result<Self, ProgramError> {
+        let mut filled_subtrees = [ZERO_LEAF; TREE_DEPTH];
+        filled_subtrees.copy_from_slice(&ZERO_HASHES[..TREE_DEPTH]);
+
+        let mut roots = [ZERO_LEAF; ROOT_HISTORY_SIZE];
+        roots[0] = ZERO_HASHES[TREE_DEPTH];
+
+        Ok(Self {
+            next_index: 0,
+            filled_subtrees,
+            roots,
+            current_root_index: 0,
+            root_slots: [0; ROOT_HISTORY_SIZE],
+            checkpoint_roots: [ZERO_LEAF; AGE_CHECKPOINTS],
+            checkpoint_slots: [0; AGE_CHECKPOINTS],
+            current_checkpoint_index: 0,
+        })
+    }
+
+    /// Append a leaf at `slot`, record the new root and return the leaf
+    /// index.
+    pub fn insert(&mut self, leaf: &[u8; 32], slot: u64) -> Result<u64, ProgramError> {
+        self.insert_subtree(leaf, 0, slot)
+    }
+
+    /// Append `leaves` at `slot` and return the index of the first one.
+    ///
+    /// Only the root after the last leaf is recorded, so however many
+    /// leaves are appended they take a single entry of `roots`.
+    pub fn insert_leaves(&mut self, leaves: &[[u8; 32]], slot: u64) -> Result<u64, ProgramError> {
+        let index = self.next_index;
+        if leaves.len() as u64 > (1 << TREE_DEPTH) - index {
+            return Err(HandCrankedError::TreeFull.into());
+        }
+        if leaves.is_empty() {
+            return Ok(index);
+        }
+
+        let mut root = self.root();
+        for leaf in leaves {
+            root = self.append(leaf, 0)?;
+        }
+        self.push_root(root, slot);
+
+        Ok(index)
+    }
+
+    /// Append a complete subtree of `2^height` leaves given only its root
+    /// at `slot`, record the new root and return the index of its first
+    /// leaf.
+    ///
+    /// `next_index` must be a multiple of `2^height`. The levels below
+    /// `height` are left stale; they are always overwritten before being
+    /// read again because the next insertion starts on an aligned index.
+    pub fn insert_subtree(
+        &mut self,
+        subtree_root: &[u8; 32],
+        height: usize,
+        slot: u64,
+    ) -> Result<u64, ProgramError> {
+        let index = self.next_index;
+        if height > TREE_DEPTH || index + (1 << height) > 1 << TREE_DEPTH {
+            return Err(HandCrankedError::TreeFull.into());
+        }
+        if !index.is_multiple_of(1 << height) {
+            return Err(HandCrankedError::InvalidBatch.into());
+        }
+
+        let root = self.append(subtree_root, height)?;
+        self.push_root(root, slot);
+
+        Ok(index)
+    }
+
+    /// Hash the subtree `node` of height `height`, at the aligned
+    /// `next_index`, into the rightmost nodes and return the resulting
+    /// root without recording it.
+    fn append(&mut self, node: &[u8; 32], height: usize) -> Result<[u8; 32], ProgramError> {
+        let mut node = *node;
+        let mut node_index = self.next_index >> height;
+        for (level, zero) in ZERO_HASHES.iter().enumerate().take(TREE_DEPTH).skip(height) {
+            node = if node_index.is_multiple_of(2) {
+                self.filled_subtrees[level] = node;
+                hash_pair(&node, zero)?
+            } else {
+                hash_pair(&self.filled_subtrees[level], &node)?
+            };
+            node_index /= 2;
+        }
+        self.next_index += 1 << height;
+        Ok(node)
+    }
+
+    /// Make `root` current as of `slot`.
+    fn push_root(&mut self, root: [u8; 32], slot: u64) {
+        self.current_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE as u64;
+        self.roots[self.current_root_index as usize] = root;
+        self.root_slots[self.current_root_index as usize] = slot;
+        self.record_checkpoint(root, slot);
+    }
+
+    /// Make `root`, current since `slot`, the checkpoint of the epoch of
+    /// `slot`, starting a new checkpoint if the latest one is of an
+    /// earlier epoch.
+    fn record_checkpoint(&mut self, root: [u8; 32], slot: u64) {
+        let latest = self.checkpoint_slots[self.current_checkpoint_index as usize];
+        if self.checkpoint_roots[self.current_checkpoint_index as usize] != ZERO_LEAF
+            && latest / AGE_CHECKPOINT_INTERVAL != slot / AGE_CHECKPOINT_INTERVAL
+        {
+            self.current_checkpoint_index =
+                (self.current_checkpoint_index + 1) % AGE_CHECKPOINTS as u64;
+        }
+        self.checkpoint_roots[self.current_checkpoint_index as usize] = root;
+        self.checkpoint_slots[self.current_checkpoint_index as usize] = slot;
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.roots[self.current_root_index as usize]
+    }
+
+    /// Whether `root` is one of the last `ROOT_HISTORY_SIZE` roots or an
+    /// age checkpoint.
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        self.root_slot(root).is_some()
+    }
+
+    /// Slot at which `root` first became current, if it is one of the last
+    /// `ROOT_HISTORY_SIZE` roots or an age checkpoint. Every leaf under
+    /// `root` was in the tree by then.
+    pub fn root_slot(&self, root: &[u8; 32]) -> Option<u64> {
+        if root == &ZERO_LEAF {
+            return None;
+        }
+        self.roots
+            .iter()
+            .zip(self.root_slots.iter())
+            .chain(
+                self.checkpoint_roots
+                    .iter()
+                    .zip(self.checkpoint_slots.iter()),
+            )
+            .filter(|(r, _)| *r == root)
+            .map(|(_, slot)| *slot)
+            .min()
+    }
+}
+
+/// Root of a complete subtree over `leaves`, whose length must be a
+/// power of two.
+pub fn subtree_root(leaves: &[[u8; 32]]) -> Result<[u8; 32], ProgramError> {
+    if !leaves.len().is_power_of_two() {
+        return Err(HandCrankedError::InvalidBatch.into());
+    }
+    let mut nodes = leaves.to_vec();
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(nodes[0])
+}
+
+/// Leaf for a public deposit: the amount is hashed in on-chain so the
+/// note cannot claim more than was paid into the vault.
+pub fn deposit_leaf(amount: u64, commitment: &[u8; 32]) -> Result<[u8; 32], ProgramError> {
+    hash_pair(&crate::zk::public_inputs::u64_to_field(amount), commitment)
+}
+
+/// Leaf for a public deposit into a multi-asset pool: the asset id is
+/// hashed in on top of `deposit_leaf`, so a note can only be spent
+/// against the vault it was paid into.
+pub fn asset_deposit_leaf(
+    asset_id: &[u8; 32],
+    amount: u64,
+    commitment: &[u8; 32],
+) -> Result<[u8; 32], ProgramError> {
+    hash_pair(asset_id, &deposit_leaf(amount, commitment)?)
+}
+
+/// Full in-memory Merkle tree, used off-chain to rebuild the pool and
+/// produce authentication paths.
+#[cfg(not(target_arch = "bpf"))]
+pub struct MerkleTree {
+    zeros: [[u8; 32]; TREE_DEPTH + 1],
+    /// `levels[0]` are the leaves, `levels[TREE_DEPTH]` holds the root once
+    /// at least one leaf is present.
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+#[cfg(not(target_arch = "bpf"))]
+impl MerkleTree {
+    pub fn new() -> Result<Self, ProgramError> {
+        Ok(Self {
+            zeros: ZERO_HASHES,
+            levels: vec![Vec::new(); TREE_DEPTH + 1],
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.levels[0]
+    }
+
+    /// Append a leaf and return its index.
+    pub fn insert(&mut self, leaf: [u8; 32]) -> Result<u64, ProgramError> {
+        let index = self.levels[0].len();
+        if index >= 1 << TREE_DEPTH {
+            return Err(HandCrankedError::TreeFull.into());
+        }
+        self.levels[0].push(leaf);
+
+        let mut node_index = index;
+        for level in 0..TREE_DEPTH {
+            let parent_index = node_index / 2;
+            let left = self.node(level, parent_index * 2);
+            let right = self.node(level, parent_index * 2 + 1);
+            let parent = hash_pair(&left, &right)?;
+
+            let parents = &mut self.levels[level + 1];
+            if parent_index < parents.len() {
+                parents[parent_index] = parent;
+            } else {
+                parents.push(parent);
+            }
+            node_index = parent_index;
+        }
+
+        Ok(index as u64)
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.levels[TREE_DEPTH]
+            .first()
+            .copied()
+            .unwrap_or(self.zeros[TREE_DEPTH])
+    }
+
+    /// Sibling nodes from the leaf up to (excluding) the root.
+    pub fn path(&self, index: u64) -> Option<[[u8; 32]; TREE_DEPTH]> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut path = [ZERO_LEAF; TREE_DEPTH];
+        let mut node_index = index as usize;
+        for (level, sibling) in path.iter_mut().enumerate() {
+            *sibling = self.node(level, node_index ^ 1);
+            node_index /= 2;
+        }
+        Some(path)
+    }
+
+    fn node(&self, level: usize, index: usize) -> [u8; 32] {
+        self.levels[level]
+            .get(index)
+            .copied()
+            .unwrap_or(self.zeros[level])
+    }
+}
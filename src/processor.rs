@@ -1,73 +1,394 @@
+//! Account-model confidential mode, alongside the note pool.
+//!
+//! Hidden balances are twisted ElGamal ciphertexts. Every proof (pubkey
+//! validity, withdraw, transfer) is verified by the zk-token proof program
+//! into a context state account beforehand; these handlers only check that
+//! the verified context matches the accounts and apply the homomorphic
+//! balance updates. Contexts must have the owner as their
+//! `context_state_authority` and are closed to the owner once consumed, so
+//! each verified proof is used exactly once.
+
+use crate::{error::HandCrankedError, state::ConfidentialAccount, utils::assert_rent_exempt};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
 };
 use spl_zk_token_sdk::{
-    instruction::{ZkTokenInstruction, ConfidentialTransferInstruction},
+    instruction::{
+        ProofType, PubkeyValidityProofContext, TransferProofContext, WithdrawProofContext,
+    },
+    zk_token_elgamal::{ops, pod},
+    zk_token_proof_instruction::{close_context_state, ContextStateInfo},
     zk_token_proof_program,
+    zk_token_proof_state::ProofContextState,
 };
-use ark_groth16::{Proof, VerifyingKey};
-use ark_bn254::Bn254;
-
-// Simplified zk circuit for private transfer (amount hidden)
-mod circuits {
-    use ark_std::UniformRand;
-    use ark_groth16::{create_random_proof, prepare_verifying_key, verify_proof};
-    use ark_bn254::{Bn254, Fr};
-
-    pub struct PrivateTransferCircuit {
-        // Define simple circuit: prove ownership and transfer without revealing amount
-        pub amount: Fr,
-        pub nullifier: Fr,  // Prevent double-spend
+
+pub const CONFIDENTIAL_SEED: &[u8] = b"confidential";
+pub const CONFIDENTIAL_VAULT_SEED: &[u8] = b"confidential-vault";
+
+/// Index of each party's decryption handle in a transfer amount ciphertext.
+const SOURCE_HANDLE: usize = 0;
+const DESTINATION_HANDLE: usize = 1;
+
+pub struct ConfidentialProcessor;
+
+impl ConfidentialProcessor {
+    pub fn process_configure_account(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let confidential_ai = next_account_info(account_info_iter)?;
+        let owner_ai = next_account_info(account_info_iter)?;
+        let proof_context_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let proof_program_ai = next_account_info(account_info_iter)?;
+
+        if !owner_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        let context: PubkeyValidityProofContext =
+            Self::proof_context(proof_context_ai, owner_ai, ProofType::PubkeyValidity)?;
+
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[CONFIDENTIAL_SEED, owner_ai.key.as_ref()], program_id);
+        if expected_pda != *confidential_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        if confidential_ai.lamports() == 0 {
+            let space = ConfidentialAccount::LEN;
+            let rent = Rent::get()?;
+            let lamports = rent.minimum_balance(space);
+
+            let create_ix = system_instruction::create_account(
+                owner_ai.key,
+                confidential_ai.key,
+                lamports,
+                space as u64,
+                program_id,
+            );
+
+            invoke_signed(
+                &create_ix,
+                &[
+                    owner_ai.clone(),
+                    confidential_ai.clone(),
+                    system_program_ai.clone(),
+                ],
+                &[&[CONFIDENTIAL_SEED, owner_ai.key.as_ref(), &[bump]]],
+            )?;
+        }
+
+        let mut account = ConfidentialAccount::try_from_slice(&confidential_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if account.is_initialized {
+            return Err(HandCrankedError::AlreadyInitialized.into());
+        }
+
+        account.is_initialized = true;
+        account.owner = *owner_ai.key;
+        account.elgamal_pubkey = context.pubkey.0;
+        account.available_balance = [0u8; 64];
+        account.pending_balance = [0u8; 64];
+
+        assert_rent_exempt(confidential_ai, ConfidentialAccount::LEN)?;
+        account.serialize(&mut &mut confidential_ai.data.borrow_mut()[..])?;
+
+        Self::fund_vault(program_id, vault_ai, owner_ai, system_program_ai)?;
+        Self::close_proof_context(proof_context_ai, owner_ai, proof_program_ai)
     }
 
-    impl PrivateTransferCircuit {
-        pub fn generate_proof(&self) -> Proof<Bn254> {
-            // Placeholder: In real impl, use R1CS or similar
-            let rng = &mut ark_std::test_rng();
-            // ... setup proving key ...
-            create_random_proof(/* circuit */, /* pk */, rng).unwrap()
+    pub fn process_deposit(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let confidential_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let owner_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        let mut account = Self::load_owned(program_id, confidential_ai, owner_ai)?;
+        Self::check_vault(program_id, vault_ai)?;
+
+        invoke(
+            &system_instruction::transfer(owner_ai.key, vault_ai.key, amount),
+            &[
+                owner_ai.clone(),
+                vault_ai.clone(),
+                system_program_ai.clone(),
+            ],
+        )?;
+
+        account.pending_balance =
+            ops::add_to(&pod::ElGamalCiphertext(account.pending_balance), amount)
+                .ok_or(HandCrankedError::BalanceMismatch)?
+                .0;
+        account.serialize(&mut &mut confidential_ai.data.borrow_mut()[..])?;
+
+        msg!("Confidential deposit of {} lamports", amount);
+        Ok(())
+    }
+
+    pub fn process_withdraw(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let confidential_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let owner_ai = next_account_info(account_info_iter)?;
+        let proof_context_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let proof_program_ai = next_account_info(account_info_iter)?;
+
+        let mut account = Self::load_owned(program_id, confidential_ai, owner_ai)?;
+        let bump = Self::check_vault(program_id, vault_ai)?;
+
+        let context: WithdrawProofContext =
+            Self::proof_context(proof_context_ai, owner_ai, ProofType::Withdraw)?;
+        if context.pubkey.0 != account.elgamal_pubkey {
+            return Err(HandCrankedError::InvalidProof.into());
         }
 
-        pub fn verify(proof: &Proof<Bn254>, vk: &VerifyingKey<Bn254>) -> bool {
-            let pvk = prepare_verifying_key(vk);
-            verify_proof(&pvk, proof, &[/* public inputs */]).unwrap()
+        // The proof shows `final_ciphertext` encrypts a non-negative value;
+        // it must be exactly the current balance minus `amount`.
+        let new_available =
+            ops::subtract_from(&pod::ElGamalCiphertext(account.available_balance), amount)
+                .ok_or(HandCrankedError::BalanceMismatch)?;
+        if new_available != context.final_ciphertext {
+            return Err(HandCrankedError::BalanceMismatch.into());
         }
+
+        account.available_balance = new_available.0;
+        account.serialize(&mut &mut confidential_ai.data.borrow_mut()[..])?;
+
+        invoke_signed(
+            &system_instruction::transfer(vault_ai.key, owner_ai.key, amount),
+            &[
+                vault_ai.clone(),
+                owner_ai.clone(),
+                system_program_ai.clone(),
+            ],
+            &[&[CONFIDENTIAL_VAULT_SEED, &[bump]]],
+        )?;
+
+        msg!("Confidential withdraw of {} lamports", amount);
+        Self::close_proof_context(proof_context_ai, owner_ai, proof_program_ai)
     }
-}
 
-pub struct Processor;
+    pub fn process_transfer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_ai = next_account_info(account_info_iter)?;
+        let destination_ai = next_account_info(account_info_iter)?;
+        let owner_ai = next_account_info(account_info_iter)?;
+        let proof_context_ai = next_account_info(account_info_iter)?;
+        let proof_program_ai = next_account_info(account_info_iter)?;
+
+        if source_ai.key == destination_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
 
-impl Processor {
-    pub fn process(_program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+        let mut source = Self::load_owned(program_id, source_ai, owner_ai)?;
+        let mut destination = Self::load(program_id, destination_ai)?;
+
+        let context: TransferProofContext =
+            Self::proof_context(proof_context_ai, owner_ai, ProofType::Transfer)?;
+        let pubkeys = &context.transfer_pubkeys;
+        if pubkeys.source.0 != source.elgamal_pubkey
+            || pubkeys.destination.0 != destination.elgamal_pubkey
+            || pubkeys.auditor != pod::ElGamalPubkey::default()
+        {
+            return Err(HandCrankedError::InvalidProof.into());
+        }
+
+        let source_lo = Self::handle_ciphertext(&context.ciphertext_lo, SOURCE_HANDLE);
+        let source_hi = Self::handle_ciphertext(&context.ciphertext_hi, SOURCE_HANDLE);
+        let new_source = ops::subtract_with_lo_hi(
+            &pod::ElGamalCiphertext(source.available_balance),
+            &source_lo,
+            &source_hi,
+        )
+        .ok_or(HandCrankedError::BalanceMismatch)?;
+        if new_source != context.new_source_ciphertext {
+            return Err(HandCrankedError::BalanceMismatch.into());
+        }
+
+        let destination_lo = Self::handle_ciphertext(&context.ciphertext_lo, DESTINATION_HANDLE);
+        let destination_hi = Self::handle_ciphertext(&context.ciphertext_hi, DESTINATION_HANDLE);
+        let new_pending = ops::add_with_lo_hi(
+            &pod::ElGamalCiphertext(destination.pending_balance),
+            &destination_lo,
+            &destination_hi,
+        )
+        .ok_or(HandCrankedError::BalanceMismatch)?;
+
+        source.available_balance = new_source.0;
+        destination.pending_balance = new_pending.0;
+        source.serialize(&mut &mut source_ai.data.borrow_mut()[..])?;
+        destination.serialize(&mut &mut destination_ai.data.borrow_mut()[..])?;
+
+        msg!("Confidential transfer executed");
+        Self::close_proof_context(proof_context_ai, owner_ai, proof_program_ai)
+    }
+
+    pub fn process_apply_pending_balance(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let zk_proof_account = next_account_info(account_info_iter)?;
-
-        // Decode instruction (e.g., ConfidentialTransfer)
-        let instruction = ZkTokenInstruction::decode_instruction_type(instruction_data)?;
-        match instruction {
-            ZkTokenInstruction::ConfidentialTransfer => {
-                // Extract proof from data
-                let proof_data = &instruction_data[1..];  // Skip type byte
-                let proof: Proof<Bn254> = bincode::deserialize(proof_data).map_err(|_| ProgramError::InvalidInstructionData)?;
-
-                // Verify zkSNARK proof on-chain
-                let vk = VerifyingKey::default();  // Load from trusted setup
-                if !circuits::PrivateTransferCircuit::verify(&proof, &vk) {
-                    msg!("Invalid zkSNARK proof");
-                    return Err(ProgramError::InvalidArgument);
-                }
-
-                // Proceed with confidential transfer using spl-zk-token-sdk
-                ConfidentialTransferInstruction::transfer(/* params */)?;
-
-                msg!("Private transfer verified and executed");
-                Ok(())
-            }
-            _ => Err(ProgramError::InvalidInstructionData),
+        let confidential_ai = next_account_info(account_info_iter)?;
+        let owner_ai = next_account_info(account_info_iter)?;
+
+        let mut account = Self::load_owned(program_id, confidential_ai, owner_ai)?;
+
+        account.available_balance = ops::add(
+            &pod::ElGamalCiphertext(account.available_balance),
+            &pod::ElGamalCiphertext(account.pending_balance),
+        )
+        .ok_or(HandCrankedError::BalanceMismatch)?
+        .0;
+        account.pending_balance = [0u8; 64];
+        account.serialize(&mut &mut confidential_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn load(
+        program_id: &Pubkey,
+        confidential_ai: &AccountInfo,
+    ) -> Result<ConfidentialAccount, ProgramError> {
+        if confidential_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let account = ConfidentialAccount::try_from_slice(&confidential_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !account.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(account)
+    }
+
+    fn load_owned(
+        program_id: &Pubkey,
+        confidential_ai: &AccountInfo,
+        owner_ai: &AccountInfo,
+    ) -> Result<ConfidentialAccount, ProgramError> {
+        let account = Self::load(program_id, confidential_ai)?;
+        if !owner_ai.is_signer || account.owner != *owner_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        Ok(account)
+    }
+
+    fn check_vault(program_id: &Pubkey, vault_ai: &AccountInfo) -> Result<u8, ProgramError> {
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[CONFIDENTIAL_VAULT_SEED], program_id);
+        if expected_pda != *vault_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(bump)
+    }
+
+    /// Top the vault up to rent exemption so the first deposits into it,
+    /// and withdrawals that empty it, are not rejected by the runtime.
+    fn fund_vault<'info>(
+        program_id: &Pubkey,
+        vault_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+        system_program_ai: &AccountInfo<'info>,
+    ) -> ProgramResult {
+        Self::check_vault(program_id, vault_ai)?;
+        let shortfall = Rent::get()?
+            .minimum_balance(0)
+            .saturating_sub(vault_ai.lamports());
+        if shortfall == 0 {
+            return Ok(());
+        }
+        invoke(
+            &system_instruction::transfer(payer_ai.key, vault_ai.key, shortfall),
+            &[
+                payer_ai.clone(),
+                vault_ai.clone(),
+                system_program_ai.clone(),
+            ],
+        )
+    }
+
+    /// Read a verified proof context of the expected type whose
+    /// `context_state_authority` is `authority_ai`.
+    fn proof_context<T: Pod>(
+        proof_context_ai: &AccountInfo,
+        authority_ai: &AccountInfo,
+        proof_type: ProofType,
+    ) -> Result<T, ProgramError> {
+        if proof_context_ai.owner != &zk_token_proof_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let data = proof_context_ai.data.borrow();
+        let state = bytemuck::try_from_bytes::<ProofContextState<T>>(&data)
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if state.context_state_authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
         }
+        if state.proof_type != proof_type.into() {
+            return Err(HandCrankedError::InvalidProof.into());
+        }
+        Ok(state.proof_context)
+    }
+
+    /// Close a consumed proof context, returning its rent to the
+    /// authority, so the same context cannot be applied twice.
+    fn close_proof_context<'info>(
+        proof_context_ai: &AccountInfo<'info>,
+        authority_ai: &AccountInfo<'info>,
+        proof_program_ai: &AccountInfo<'info>,
+    ) -> ProgramResult {
+        if *proof_program_ai.key != zk_token_proof_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        invoke(
+            &close_context_state(
+                ContextStateInfo {
+                    context_state_account: proof_context_ai.key,
+                    context_state_authority: authority_ai.key,
+                },
+                authority_ai.key,
+            ),
+            &[
+                proof_context_ai.clone(),
+                authority_ai.clone(),
+                proof_program_ai.clone(),
+            ],
+        )
+    }
+
+    /// Ciphertext of a transfer amount under one party's key: the shared
+    /// Pedersen commitment followed by that party's decryption handle.
+    fn handle_ciphertext(
+        ciphertext: &pod::TransferAmountCiphertext,
+        handle: usize,
+    ) -> pod::ElGamalCiphertext {
+        let grouped = &ciphertext.0 .0;
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&grouped[..32]);
+        bytes[32..].copy_from_slice(&grouped[32 + 32 * handle..64 + 32 * handle]);
+        pod::ElGamalCiphertext(bytes)
     }
 }
@@ -1,159 +1,587 @@
 use crate::{
     error::HandCrankedError,
-    instruction::{HandCrankedInstruction, ProofSystem},
-    state::{GlobalState, NoteState},
-    utils::assert_rent_exempt,
-    zk::{DefaultVerifier, ProofVerifier},
+    events::{
+        AuditorMemoEvent, DepositEvent, HandCrankedEvent, NewCommitmentEvent, NullifierSpentEvent,
+        QueueCrankedEvent, RewardClaimedEvent, SwapEvent, WithdrawalEvent, WithdrawalExecutedEvent,
+        WithdrawalRequestedEvent,
+    },
+    instruction::{
+        AssociationProof, HandCrankedInstruction, ProofSource, ProofSystem, WithdrawCallback,
+    },
+    processor::ConfidentialProcessor,
+    merkle::{asset_deposit_leaf, deposit_leaf},
+    state::{
+        AssociationSet, DepositQueue, NoteState, NullifierState, PendingWithdrawal, PoolParams,
+        PoolState, PoolStats, ProofBuffer, RewardProgram, SwapReserve,
+        VaultStats, VerificationSession, VerifyingKeyState, NATIVE_ASSET,
+    },
+    token::{self, TokenAccounts},
+    utils::{assert_rent_exempt, create_pda_account},
+    zk::{
+        public_inputs::{
+            self, domain_tag, AssociationInputs, AuditBinding, AuditorMemo, BatchInsertInputs,
+            ExtData, PublicInputs, RewardInputs, SwapInputs,
+        },
+        session::{self, SessionStep, PREPARED_INPUTS_LEN},
+        Circuit, DefaultVerifier, ProofVerifier,
+    },
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
     msg,
+    instruction::{AccountMeta, Instruction},
     program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
     system_instruction,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
+pub const POOL_SEED: &[u8] = b"pool";
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const NOTE_SEED: &[u8] = b"note";
+pub const NULLIFIER_SEED: &[u8] = b"nullifier";
+pub const QUEUE_SEED: &[u8] = b"queue";
+pub const PENDING_SEED: &[u8] = b"pending";
+pub const ASSOCIATION_SEED: &[u8] = b"association";
+pub const PROOF_BUFFER_SEED: &[u8] = b"proof-buffer";
+pub const SESSION_SEED: &[u8] = b"session";
+pub const RESERVE_SEED: &[u8] = b"reserve";
+pub const STATS_SEED: &[u8] = b"stats";
+pub const REWARD_SEED: &[u8] = b"rewards";
+pub const VERIFYING_KEY_SEED: &[u8] = b"verifying-key";
+
 pub struct Processor;
 
+/// Signer seeds of a pool vault: `[b"vault", pool]`, or
+/// `[b"vault", pool, mint]` for one asset of a multi-asset pool.
+struct VaultSigner {
+    pool: Pubkey,
+    asset: Option<Pubkey>,
+    bump: [u8; 1],
+}
+
+impl VaultSigner {
+    fn seeds(&self) -> Vec<&[u8]> {
+        let mut seeds: Vec<&[u8]> = vec![VAULT_SEED, self.pool.as_ref()];
+        if let Some(asset) = &self.asset {
+            seeds.push(asset.as_ref());
+        }
+        seeds.push(&self.bump);
+        seeds
+    }
+}
+
 impl Processor {
     pub fn process(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         instruction_data: &[u8],
+    ) -> ProgramResult {
+        Self::process_with(program_id, accounts, instruction_data, &DefaultVerifier)
+    }
+
+    /// `process` with proofs checked by `verifier` instead of
+    /// `DefaultVerifier`.
+    pub fn process_with(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+        verifier: &dyn ProofVerifier,
     ) -> ProgramResult {
         let instruction = HandCrankedInstruction::unpack(instruction_data)
             .map_err(|_| HandCrankedError::InvalidInstruction)?;
 
         match instruction {
-            HandCrankedInstruction::Initialize => {
-                msg!("Instruction: Initialize");
-                Self::process_initialize(program_id, accounts)
+            HandCrankedInstruction::InitializePool {
+                pool_id,
+                asset,
+                denomination,
+                params,
+            } => {
+                msg!("Instruction: InitializePool");
+                Self::process_initialize_pool(
+                    program_id,
+                    accounts,
+                    pool_id,
+                    asset,
+                    denomination,
+                    params,
+                )
             }
-            HandCrankedInstruction::Deposit { commitment } => {
+            HandCrankedInstruction::Deposit {
+                amount,
+                commitment,
+                encrypted_note,
+            } => {
                 msg!("Instruction: Deposit");
-                Self::process_deposit(program_id, accounts, commitment)
+                Self::process_deposit(program_id, accounts, amount, commitment, encrypted_note)
             }
             HandCrankedInstruction::PrivateTransfer {
                 proof_system,
                 proof,
-                public_inputs_commitment,
-                nullifier,
+                root,
+                nullifier_1,
+                nullifier_2,
                 new_commitment_1,
                 new_commitment_2,
+                fee,
+                encrypted_note_1,
+                encrypted_note_2,
+                auditor_memo,
             } => {
                 msg!("Instruction: PrivateTransfer");
                 Self::process_private_transfer(
                     program_id,
                     accounts,
+                    verifier,
+                    proof_system,
+                    proof,
+                    &root,
+                    [nullifier_1, nullifier_2],
+                    [new_commitment_1, new_commitment_2],
+                    fee,
+                    [encrypted_note_1, encrypted_note_2],
+                    auditor_memo,
+                )
+            }
+            HandCrankedInstruction::Withdraw {
+                proof_system,
+                proof,
+                root,
+                nullifier,
+                amount,
+                fee,
+                association,
+                callback,
+            } => {
+                msg!("Instruction: Withdraw");
+                Self::process_withdraw(
+                    program_id,
+                    accounts,
+                    verifier,
+                    proof_system,
+                    proof,
+                    &root,
+                    &nullifier,
+                    amount,
+                    fee,
+                    association,
+                    callback,
+                    false,
+                )
+            }
+            HandCrankedInstruction::ConfigureConfidentialAccount => {
+                msg!("Instruction: ConfigureConfidentialAccount");
+                ConfidentialProcessor::process_configure_account(program_id, accounts)
+            }
+            HandCrankedInstruction::ConfidentialDeposit { amount } => {
+                msg!("Instruction: ConfidentialDeposit");
+                ConfidentialProcessor::process_deposit(program_id, accounts, amount)
+            }
+            HandCrankedInstruction::ConfidentialWithdraw { amount } => {
+                msg!("Instruction: ConfidentialWithdraw");
+                ConfidentialProcessor::process_withdraw(program_id, accounts, amount)
+            }
+            HandCrankedInstruction::ConfidentialTransfer => {
+                msg!("Instruction: ConfidentialTransfer");
+                ConfidentialProcessor::process_transfer(program_id, accounts)
+            }
+            HandCrankedInstruction::ApplyConfidentialPendingBalance => {
+                msg!("Instruction: ApplyConfidentialPendingBalance");
+                ConfidentialProcessor::process_apply_pending_balance(program_id, accounts)
+            }
+            HandCrankedInstruction::Crank { max_leaves } => {
+                msg!("Instruction: Crank");
+                Self::process_crank(program_id, accounts, max_leaves)
+            }
+            HandCrankedInstruction::BatchInsert {
+                proof_system,
+                proof,
+                count,
+                old_root,
+                new_root,
+                leaves_hash,
+                subtree_root,
+            } => {
+                msg!("Instruction: BatchInsert");
+                Self::process_batch_insert(
+                    program_id,
+                    accounts,
+                    verifier,
                     proof_system,
                     &proof,
-                    &public_inputs_commitment,
+                    count,
+                    old_root,
+                    new_root,
+                    leaves_hash,
+                    subtree_root,
+                )
+            }
+            HandCrankedInstruction::RequestWithdraw {
+                proof_system,
+                proof,
+                root,
+                nullifier,
+                amount,
+                fee,
+                association,
+            } => {
+                msg!("Instruction: RequestWithdraw");
+                Self::process_withdraw(
+                    program_id,
+                    accounts,
+                    verifier,
+                    proof_system,
+                    proof,
+                    &root,
                     &nullifier,
-                    &new_commitment_1,
-                    &new_commitment_2,
+                    amount,
+                    fee,
+                    association,
+                    None,
+                    true,
                 )
             }
-            HandCrankedInstruction::Withdraw { nullifier } => {
-                msg!("Instruction: Withdraw");
-                Self::process_withdraw(program_id, accounts, &nullifier)
+            HandCrankedInstruction::ExecuteWithdraw => {
+                msg!("Instruction: ExecuteWithdraw");
+                Self::process_execute_withdraw(program_id, accounts)
+            }
+            HandCrankedInstruction::AuditInvariant => {
+                msg!("Instruction: AuditInvariant");
+                Self::process_audit_invariant(program_id, accounts)
+            }
+            HandCrankedInstruction::PublishAssociationRoot { set_id, root } => {
+                msg!("Instruction: PublishAssociationRoot");
+                Self::process_publish_association_root(program_id, accounts, set_id, root)
+            }
+            HandCrankedInstruction::OpenProofBuffer { buffer_id, len } => {
+                msg!("Instruction: OpenProofBuffer");
+                Self::process_open_proof_buffer(program_id, accounts, buffer_id, len)
+            }
+            HandCrankedInstruction::WriteProofChunk { offset, data } => {
+                msg!("Instruction: WriteProofChunk");
+                Self::process_write_proof_chunk(program_id, accounts, offset, &data)
+            }
+            HandCrankedInstruction::CloseProofBuffer => {
+                msg!("Instruction: CloseProofBuffer");
+                Self::process_close_proof_buffer(program_id, accounts)
+            }
+            HandCrankedInstruction::OpenVerificationSession {
+                session_id,
+                proof_system,
+                circuit,
+                proof,
+                public_inputs,
+            } => {
+                msg!("Instruction: OpenVerificationSession");
+                Self::process_open_verification_session(
+                    program_id,
+                    accounts,
+                    session_id,
+                    proof_system,
+                    circuit,
+                    proof,
+                    public_inputs,
+                )
+            }
+            HandCrankedInstruction::StepVerificationSession { max_steps } => {
+                msg!("Instruction: StepVerificationSession");
+                Self::process_step_verification_session(program_id, accounts, max_steps)
+            }
+            HandCrankedInstruction::CloseVerificationSession => {
+                msg!("Instruction: CloseVerificationSession");
+                Self::process_close_verification_session(program_id, accounts)
+            }
+            HandCrankedInstruction::InitializeSwapReserve { fee_bps } => {
+                msg!("Instruction: InitializeSwapReserve");
+                Self::process_initialize_swap_reserve(program_id, accounts, fee_bps)
+            }
+            HandCrankedInstruction::AddSwapLiquidity { amount_a, amount_b } => {
+                msg!("Instruction: AddSwapLiquidity");
+                Self::process_swap_liquidity(program_id, accounts, [amount_a, amount_b], true)
+            }
+            HandCrankedInstruction::RemoveSwapLiquidity { amount_a, amount_b } => {
+                msg!("Instruction: RemoveSwapLiquidity");
+                Self::process_swap_liquidity(program_id, accounts, [amount_a, amount_b], false)
+            }
+            HandCrankedInstruction::PrivateSwap {
+                proof_system,
+                proof,
+                root,
+                nullifier,
+                amount_in,
+                fee,
+                min_amount_out,
+                output_commitment,
+                encrypted_note,
+                auditor_memo,
+            } => {
+                msg!("Instruction: PrivateSwap");
+                Self::process_private_swap(
+                    program_id,
+                    accounts,
+                    verifier,
+                    proof_system,
+                    proof,
+                    &root,
+                    &nullifier,
+                    amount_in,
+                    fee,
+                    min_amount_out,
+                    output_commitment,
+                    encrypted_note,
+                    auditor_memo,
+                )
+            }
+            HandCrankedInstruction::InitializeAssetVault => {
+                msg!("Instruction: InitializeAssetVault");
+                Self::process_initialize_asset_vault(program_id, accounts)
+            }
+            HandCrankedInstruction::InitializeRewardProgram {
+                rate,
+                checkpoint_interval,
+            } => {
+                msg!("Instruction: InitializeRewardProgram");
+                Self::process_initialize_reward_program(
+                    program_id,
+                    accounts,
+                    rate,
+                    checkpoint_interval,
+                )
+            }
+            HandCrankedInstruction::FundRewards { amount } => {
+                msg!("Instruction: FundRewards");
+                Self::process_fund_rewards(program_id, accounts, amount)
+            }
+            HandCrankedInstruction::RecordCheckpoint => {
+                msg!("Instruction: RecordCheckpoint");
+                Self::process_record_checkpoint(program_id, accounts)
+            }
+            HandCrankedInstruction::ClaimReward {
+                proof_system,
+                proof,
+                start_checkpoint,
+                end_checkpoint,
+                nullifier,
+                new_commitment,
+                encrypted_note,
+                fee,
+                reward_commitment,
+                encrypted_reward_note,
+            } => {
+                msg!("Instruction: ClaimReward");
+                Self::process_claim_reward(
+                    program_id,
+                    accounts,
+                    verifier,
+                    proof_system,
+                    proof,
+                    [start_checkpoint, end_checkpoint],
+                    &nullifier,
+                    fee,
+                    [new_commitment, reward_commitment],
+                    [encrypted_note, encrypted_reward_note],
+                )
+            }
+            HandCrankedInstruction::InitializeVerifyingKey {
+                proof_system,
+                circuit,
+                len,
+            } => {
+                msg!("Instruction: InitializeVerifyingKey");
+                Self::process_initialize_verifying_key(
+                    program_id,
+                    accounts,
+                    proof_system,
+                    circuit,
+                    len,
+                )
+            }
+            HandCrankedInstruction::WriteVerifyingKey { offset, data } => {
+                msg!("Instruction: WriteVerifyingKey");
+                Self::process_write_verifying_key(program_id, accounts, offset, &data)
+            }
+            HandCrankedInstruction::FinalizeVerifyingKey => {
+                msg!("Instruction: FinalizeVerifyingKey");
+                Self::process_finalize_verifying_key(program_id, accounts)
             }
         }
     }
 
-    fn process_initialize(
+    fn process_initialize_pool(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
+        pool_id: u64,
+        asset: Pubkey,
+        denomination: Option<u64>,
+        params: PoolParams,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let global_state_ai = next_account_info(account_info_iter)?;
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
         let authority_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
         let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
 
         if !authority_ai.is_signer {
             return Err(HandCrankedError::Unauthorized.into());
         }
 
-        if global_state_ai.owner != program_id && global_state_ai.lamports() == 0 {
+        if denomination == Some(0) {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+        params.check_withdraw_window()?;
+        params.check_min_note_age()?;
 
-            let (expected_pda, bump) = Pubkey::find_program_address(&[b"global-state"], program_id);
-            if expected_pda != *global_state_ai.key {
-                return Err(HandCrankedError::InvalidAccountData.into());
-            }
+        let pool_id_bytes = pool_id.to_le_bytes();
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[POOL_SEED, &pool_id_bytes], program_id);
+        if expected_pda != *pool_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
 
-            let space = std::mem::size_of::<GlobalState>();
-            let rent = solana_program::rent::Rent::get()?;
-            let lamports = rent.minimum_balance(space);
+        create_pda_account(
+            authority_ai,
+            pool_ai,
+            PoolState::LEN,
+            program_id,
+            system_program_ai,
+            &[POOL_SEED, &pool_id_bytes, &[bump]],
+        )?;
 
-            let create_ix = system_instruction::create_account(
-                authority_ai.key,
-                global_state_ai.key,
-                lamports,
-                space as u64,
-                program_id,
-            );
+        let (expected_queue, queue_bump) =
+            Pubkey::find_program_address(&[QUEUE_SEED, pool_ai.key.as_ref()], program_id);
+        if expected_queue != *queue_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        create_pda_account(
+            authority_ai,
+            queue_ai,
+            DepositQueue::LEN,
+            program_id,
+            system_program_ai,
+            &[QUEUE_SEED, pool_ai.key.as_ref(), &[queue_bump]],
+        )?;
+        DepositQueue::new(*pool_ai.key).serialize(&mut &mut queue_ai.data.borrow_mut()[..])?;
 
-            invoke_signed(
-                &create_ix,
-                &[authority_ai.clone(), global_state_ai.clone(), system_program_ai.clone()],
-                &[&[b"global-state", &[bump]]],
+        let pool = PoolState::new(pool_id, *authority_ai.key, asset, denomination, params)?;
+
+        // Multi-asset pools get their vaults from `InitializeAssetVault`.
+        if !pool.is_multi_asset() {
+            let token = TokenAccounts::next(&pool, account_info_iter)?;
+            if let Some(token) = &token {
+                token::validate_mint(token.mint)?;
+            }
+            let vault = Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
+            Self::create_vault(&vault, vault_ai, authority_ai, system_program_ai, token.as_ref())?;
+            Self::create_stats(
+                program_id,
+                pool_ai.key,
+                &asset,
+                stats_ai,
+                authority_ai,
+                system_program_ai,
             )?;
         }
 
-  
-        let mut state: GlobalState = if global_state_ai.data_is_empty() {
-            GlobalState::default()
-        } else {
-            GlobalState::try_from_slice(&global_state_ai.data.borrow())?
-        };
+        assert_rent_exempt(pool_ai, PoolState::LEN)?;
+
+        pool.serialize(&mut &mut pool_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Create the vault of one asset of a multi-asset pool. Permissionless;
+    /// the payer funds the vault's rent.
+    fn process_initialize_asset_vault(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let payer_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
 
-        if state.is_initialized {
-            return Err(HandCrankedError::AlreadyInitialized.into());
+        if !payer_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
         }
 
-        state.is_initialized = true;
-        state.version = GlobalState::VERSION;
-        state.authority = *authority_ai.key;
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
 
-        assert_rent_exempt(global_state_ai, std::mem::size_of::<GlobalState>())?;
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        if !pool.is_multi_asset() {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
 
-        state.serialize(&mut &mut global_state_ai.data.borrow_mut()[..])?;
+        let token = TokenAccounts::next(&pool, account_info_iter)?;
+        if let Some(token) = &token {
+            token::validate_mint(token.mint)?;
+        }
 
-        Ok(())
+        let asset = token::asset(token.as_ref());
+        let vault = Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
+        Self::create_stats(
+            program_id,
+            pool_ai.key,
+            &asset,
+            stats_ai,
+            payer_ai,
+            system_program_ai,
+        )?;
+        Self::create_vault(&vault, vault_ai, payer_ai, system_program_ai, token.as_ref())
     }
 
     fn process_deposit(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
+        amount: u64,
         commitment: [u8; 32],
+        encrypted_note: Vec<u8>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let global_state_ai = next_account_info(account_info_iter)?;
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
         let user_ai = next_account_info(account_info_iter)?;
         let note_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
         let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
 
         if !user_ai.is_signer {
             return Err(HandCrankedError::Unauthorized.into());
         }
 
-        if global_state_ai.owner != program_id {
+        if pool_ai.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
 
-        let _global_state: GlobalState = GlobalState::try_from_slice(&global_state_ai.data.borrow())
-            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let mut queue = Self::load_queue(program_id, pool_ai.key, queue_ai)?;
+        let token = TokenAccounts::next(&pool, account_info_iter)?;
+        let source_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => user_ai,
+        };
 
-        let (expected_pda, bump) = Pubkey::find_program_address(&[b"note", &commitment], program_id);
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[NOTE_SEED, pool_ai.key.as_ref(), &commitment],
+            program_id,
+        );
         if expected_pda != *note_ai.key {
             return Err(HandCrankedError::InvalidAccountData.into());
         }
 
         if note_ai.lamports() == 0 {
             let space = std::mem::size_of::<NoteState>();
-            let rent = solana_program::rent::Rent::get()?;
+            let rent = Rent::get()?;
             let lamports = rent.minimum_balance(space);
 
             let create_ix = system_instruction::create_account(
@@ -167,7 +595,7 @@ impl Processor {
             invoke_signed(
                 &create_ix,
                 &[user_ai.clone(), note_ai.clone(), system_program_ai.clone()],
-                &[&[b"note", &commitment, &[bump]]],
+                &[&[NOTE_SEED, pool_ai.key.as_ref(), &commitment, &[bump]]],
             )?;
         }
 
@@ -182,7 +610,6 @@ impl Processor {
             return Err(HandCrankedError::DuplicateCommitment.into());
         }
 
-
         note_state.is_initialized = true;
         note_state.commitment = commitment;
         note_state.nullifier = [0u8; 32];
@@ -191,171 +618,2537 @@ impl Processor {
         assert_rent_exempt(note_ai, std::mem::size_of::<NoteState>())?;
         note_state.serialize(&mut &mut note_ai.data.borrow_mut()[..])?;
 
+        let asset = token::asset(token.as_ref());
+        Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
+        let received = Self::fund_vault(
+            vault_ai,
+            source_ai,
+            user_ai,
+            amount,
+            system_program_ai,
+            token.as_ref(),
+        )?;
+        pool.check_denomination(received)?;
+
+        let leaf = Self::deposit_leaf(&pool, &asset, received, &commitment)?;
+        let leaf_index = queue.push(leaf)?;
+        Self::charge_crank_reward(&pool, &mut queue, queue_ai, user_ai, system_program_ai, 1)?;
+        queue.serialize(&mut &mut queue_ai.data.borrow_mut()[..])?;
+
+        let mut stats = Self::load_stats(program_id, pool_ai.key, &asset, stats_ai)?;
+        stats.stats.record_deposit(received)?;
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+
+        HandCrankedEvent::Deposit(DepositEvent {
+            pool: *pool_ai.key,
+            leaf_index,
+            commitment: leaf,
+            ciphertext: encrypted_note,
+        })
+        .emit()?;
+
         Ok(())
     }
 
+    /// Private 2-in-2-out transfer.
+    ///
+    /// The proof is bound to the root, both nullifiers, both output
+    /// commitments, the fee, the relayer and the hash of the note
+    /// ciphertexts, so nothing in the instruction can be swapped by a relayer.
+    ///
+    /// In pools with an auditor the proof also covers `auditor_memo`, which
+    /// is then logged next to the new commitments.
     #[allow(clippy::too_many_arguments)]
     fn process_private_transfer(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
+        verifier: &dyn ProofVerifier,
         proof_system: ProofSystem,
-        proof: &[u8],
-        public_inputs_commitment: &[u8; 32],
-        nullifier: &[u8; 32],
-        new_commitment_1: &[u8; 32],
-        new_commitment_2: &[u8; 32],
+        proof: ProofSource,
+        root: &[u8; 32],
+        nullifiers: [[u8; 32]; 2],
+        new_commitments: [[u8; 32]; 2],
+        fee: u64,
+        encrypted_notes: [Vec<u8>; 2],
+        auditor_memo: Option<AuditorMemo>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let global_state_ai = next_account_info(account_info_iter)?;
-        let spent_note_ai = next_account_info(account_info_iter)?;
-        let new_note1_ai = next_account_info(account_info_iter)?;
-        let new_note2_ai = next_account_info(account_info_iter)?;
-        let prover_ai = next_account_info(account_info_iter)?;
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
+        let nullifier1_ai = next_account_info(account_info_iter)?;
+        let nullifier2_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let relayer_ai = next_account_info(account_info_iter)?;
         let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
 
-        if !prover_ai.is_signer {
+        if !relayer_ai.is_signer {
             return Err(HandCrankedError::Unauthorized.into());
         }
 
-        if global_state_ai.owner != program_id {
+        if pool_ai.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
 
+        if nullifiers[0] == nullifiers[1] {
+            return Err(HandCrankedError::NullifierAlreadySpent.into());
+        }
 
-        let _global_state: GlobalState =
-            GlobalState::try_from_slice(&global_state_ai.data.borrow())
-                .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        let proof_ai = match proof {
+            ProofSource::Inline(_) => None,
+            ProofSource::Buffer | ProofSource::Session => {
+                Some(next_account_info(account_info_iter)?)
+            }
+        };
+        let key_ai = match proof {
+            ProofSource::Session => None,
+            _ => Some(next_account_info(account_info_iter)?),
+        };
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let mut queue = Self::load_queue(program_id, pool_ai.key, queue_ai)?;
+        let token = TokenAccounts::next(&pool, account_info_iter)?;
+        let relayer_destination_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => relayer_ai,
+        };
 
-        let mut spent_note: NoteState = NoteState::try_from_slice(&spent_note_ai.data.borrow())
-            .map_err(|_| HandCrankedError::InvalidAccountData)?;
-        if !spent_note.is_initialized || spent_note.spent {
-            return Err(HandCrankedError::NullifierAlreadySpent.into());
+        // Splitting or merging notes would break the fixed denomination.
+        if pool.denomination.is_some() {
+            return Err(HandCrankedError::InvalidDenomination.into());
         }
 
-        // Verify zk proof off-chain or in a dedicated verifier program.
-        DefaultVerifier::verify(
-            proof_system,
-            proof,
-            public_inputs_commitment,
-            nullifier,
-        )?;
+        let root_slot = pool
+            .tree
+            .root_slot(root)
+            .ok_or(HandCrankedError::UnknownRoot)?;
+
+        let (circuit, audit) = match (pool.params.auditor, &auditor_memo) {
+            (Some(key), Some(memo)) => (
+                Circuit::AuditedJoinSplit,
+                Some(AuditBinding {
+                    key,
+                    memo: memo.clone(),
+                }),
+            ),
+            (None, None) => (pool.circuit(), None),
+            _ => return Err(HandCrankedError::InvalidInstruction.into()),
+        };
 
+        let asset = token::asset(token.as_ref());
+        let vault = Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
 
-        spent_note.spent = true;
-        spent_note.nullifier = *nullifier;
-        spent_note.serialize(&mut &mut spent_note_ai.data.borrow_mut()[..])?;
+        let [encrypted_note_1, encrypted_note_2] = encrypted_notes;
+        let ext_data = ExtData {
+            recipient: Pubkey::default(),
+            relayer: *relayer_ai.key,
+            fee,
+            encrypted_outputs: vec![encrypted_note_1, encrypted_note_2],
+            callback: None,
+        };
 
+        Self::verify_proof_source(
+            program_id,
+            verifier,
+            pool_ai.key,
+            proof_system,
+            circuit,
+            proof,
+            proof_ai,
+            key_ai,
+            relayer_ai,
+            &PublicInputs {
+                domain: domain_tag(program_id, pool_ai.key, pool.params.cluster),
+                root: *root,
+                root_slot,
+                nullifiers: nullifiers.to_vec(),
+                output_commitments: new_commitments.to_vec(),
+                public_amount: 0,
+                fee,
+                relayer: *relayer_ai.key,
+                ext_data_hash: ext_data.hash()?,
+                audit,
+                asset_id: Self::asset_id(&pool, &asset),
+            }
+            .to_field_elements(),
+        )?;
 
-        Self::create_or_init_note(
+        Self::spend_nullifier(
             program_id,
-            new_note1_ai,
-            new_commitment_1,
+            pool_ai.key,
+            nullifier1_ai,
+            &nullifiers[0],
             system_program_ai,
-            prover_ai,
+            relayer_ai,
+        )?;
+        Self::spend_nullifier(
+            program_id,
+            pool_ai.key,
+            nullifier2_ai,
+            &nullifiers[1],
+            system_program_ai,
+            relayer_ai,
         )?;
 
+        let first_leaf_index = queue.next_leaf_index;
+        for (commitment, ciphertext) in new_commitments.iter().zip(ext_data.encrypted_outputs) {
+            Self::emit_new_commitment(pool_ai.key, &mut queue, commitment, ciphertext)?;
+        }
+        if let Some(memo) = auditor_memo {
+            HandCrankedEvent::AuditorMemo(AuditorMemoEvent {
+                leaf_index: first_leaf_index,
+                memo,
+            })
+            .emit()?;
+        }
+        Self::charge_crank_reward(
+            &pool,
+            &mut queue,
+            queue_ai,
+            relayer_ai,
+            system_program_ai,
+            new_commitments.len() as u64,
+        )?;
+        queue.serialize(&mut &mut queue_ai.data.borrow_mut()[..])?;
 
-        if new_commitment_2 != &[0u8; 32] {
-            Self::create_or_init_note(
-                program_id,
-                new_note2_ai,
-                new_commitment_2,
+        let mut stats = Self::load_stats(program_id, pool_ai.key, &asset, stats_ai)?;
+        stats.stats.record_transfer(fee)?;
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+
+        if fee > 0 {
+            Self::pay_from_vault(
+                &vault,
+                vault_ai,
+                relayer_destination_ai,
+                fee,
                 system_program_ai,
-                prover_ai,
+                token.as_ref(),
             )?;
         }
 
         Ok(())
     }
 
-    fn create_or_init_note(
+    /// Move up to `max_leaves` queued leaves into the tree and pay the
+    /// caller `crank_reward` per leaf out of the queue's fee balance.
+    fn process_crank(
         program_id: &Pubkey,
-        note_ai: &AccountInfo,
-        commitment: &[u8; 32],
-        system_program_ai: &AccountInfo,
-        payer_ai: &AccountInfo,
+        accounts: &[AccountInfo],
+        max_leaves: u8,
     ) -> ProgramResult {
-        let (expected_pda, bump) =
-            Pubkey::find_program_address(&[b"note", commitment], program_id);
-        if expected_pda != *note_ai.key {
-            return Err(HandCrankedError::InvalidAccountData.into());
-        }
-
-        if note_ai.lamports() == 0 {
-            let space = std::mem::size_of::<NoteState>();
-            let rent = solana_program::rent::Rent::get()?;
-            let lamports = rent.minimum_balance(space);
-
-            let create_ix = system_instruction::create_account(
-                payer_ai.key,
-                note_ai.key,
-                lamports,
-                space as u64,
-                program_id,
-            );
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
+        let cranker_ai = next_account_info(account_info_iter)?;
 
-            invoke_signed(
-                &create_ix,
-                &[payer_ai.clone(), note_ai.clone(), system_program_ai.clone()],
-                &[&[b"note", commitment, &[bump]]],
-            )?;
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
 
-        let mut note_state: NoteState = if note_ai.data_is_empty() {
-            NoteState::default()
-        } else {
-            NoteState::try_from_slice(&note_ai.data.borrow())
-                .map_err(|_| HandCrankedError::InvalidAccountData)?
-        };
+        let mut pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let mut queue = Self::load_queue(program_id, pool_ai.key, queue_ai)?;
 
-        if note_state.is_initialized {
-            return Err(HandCrankedError::DuplicateCommitment.into());
+        let count = queue.pending.len().min(max_leaves as usize);
+        if count == 0 {
+            return Ok(());
         }
 
-        note_state.is_initialized = true;
-        note_state.commitment = *commitment;
-        note_state.nullifier = [0u8; 32];
-        note_state.spent = false;
+        let leaves: Vec<[u8; 32]> = queue.pending.drain(..count).collect();
+        let first_leaf_index = pool.tree.insert_leaves(&leaves, Clock::get()?.slot)?;
 
-        assert_rent_exempt(note_ai, std::mem::size_of::<NoteState>())?;
-        note_state.serialize(&mut &mut note_ai.data.borrow_mut()[..])?;
-        Ok(())
+        Self::finish_crank(
+            &pool,
+            pool_ai,
+            &mut queue,
+            queue_ai,
+            cranker_ai,
+            first_leaf_index,
+            count as u64,
+        )
     }
 
-    fn process_withdraw(
+    /// Absorb the first `count` queued leaves in one step, trusting a proof
+    /// for the subtree they form instead of hashing them on-chain.
+    #[allow(clippy::too_many_arguments)]
+    fn process_batch_insert(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        nullifier: &[u8; 32],
+        verifier: &dyn ProofVerifier,
+        proof_system: ProofSystem,
+        proof: &[u8],
+        count: u16,
+        old_root: [u8; 32],
+        new_root: [u8; 32],
+        leaves_hash: [u8; 32],
+        subtree_root: [u8; 32],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let global_state_ai = next_account_info(account_info_iter)?;
-        let note_ai = next_account_info(account_info_iter)?;
-        let recipient_ai = next_account_info(account_info_iter)?;
-        let system_program_ai = next_account_info(account_info_iter)?;
-
-        if !recipient_ai.is_signer {
-            return Err(HandCrankedError::Unauthorized.into());
-        }
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
+        let cranker_ai = next_account_info(account_info_iter)?;
+        let key_ai = next_account_info(account_info_iter)?;
 
-        if global_state_ai.owner != program_id {
+        if pool_ai.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
 
+        let mut pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let mut queue = Self::load_queue(program_id, pool_ai.key, queue_ai)?;
 
-        let mut note_state: NoteState = NoteState::try_from_slice(&note_ai.data.borrow())
-            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        let count = count as usize;
+        if !count.is_power_of_two() || count > queue.pending.len() {
+            return Err(HandCrankedError::InvalidBatch.into());
+        }
 
-        if note_state.spent || &note_state.nullifier != nullifier {
-            return Err(HandCrankedError::NullifierAlreadySpent.into());
+        if pool.tree.root() != old_root {
+            return Err(HandCrankedError::UnknownRoot.into());
         }
 
-        note_state.spent = true;
-        note_state.serialize(&mut &mut note_ai.data.borrow_mut()[..])?;
+        if public_inputs::leaves_hash(&queue.pending[..count]) != leaves_hash {
+            return Err(HandCrankedError::InvalidBatch.into());
+        }
 
+        let first_leaf_index = pool.tree.next_index;
+        Self::verify_proof(
+            program_id,
+            verifier,
+            pool_ai.key,
+            proof_system,
+            Circuit::BatchInsert,
+            key_ai,
+            proof,
+            &BatchInsertInputs {
+                domain: domain_tag(program_id, pool_ai.key, pool.params.cluster),
+                old_root,
+                new_root,
+                leaves_hash,
+                start_index: first_leaf_index,
+                subtree_root,
+            }
+            .to_field_elements(),
+        )?;
+
+        pool.tree.insert_subtree(
+            &subtree_root,
+            count.trailing_zeros() as usize,
+            Clock::get()?.slot,
+        )?;
+        if pool.tree.root() != new_root {
+            return Err(HandCrankedError::InvalidProof.into());
+        }
+        queue.pending.drain(..count);
+
+        Self::finish_crank(
+            &pool,
+            pool_ai,
+            &mut queue,
+            queue_ai,
+            cranker_ai,
+            first_leaf_index,
+            count as u64,
+        )
+    }
+
+    /// Pay the crank reward for `count` leaves, save both accounts and log
+    /// the new root.
+    fn finish_crank(
+        pool: &PoolState,
+        pool_ai: &AccountInfo,
+        queue: &mut DepositQueue,
+        queue_ai: &AccountInfo,
+        cranker_ai: &AccountInfo,
+        first_leaf_index: u64,
+        count: u64,
+    ) -> ProgramResult {
+        let reward = pool
+            .params
+            .crank_reward
+            .saturating_mul(count)
+            .min(queue.fee_balance);
+        queue.fee_balance -= reward;
+        **queue_ai.try_borrow_mut_lamports()? -= reward;
+        **cranker_ai.try_borrow_mut_lamports()? += reward;
+
+        pool.serialize(&mut &mut pool_ai.data.borrow_mut()[..])?;
+        queue.serialize(&mut &mut queue_ai.data.borrow_mut()[..])?;
+
+        HandCrankedEvent::QueueCranked(QueueCrankedEvent {
+            pool: *pool_ai.key,
+            first_leaf_index,
+            count,
+            root: pool.tree.root(),
+        })
+        .emit()
+    }
+
+    /// Deserialize the deposit queue of `pool`, checking its address.
+    fn load_queue(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        queue_ai: &AccountInfo,
+    ) -> Result<DepositQueue, ProgramError> {
+        if queue_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (expected_pda, _) =
+            Pubkey::find_program_address(&[QUEUE_SEED, pool.as_ref()], program_id);
+        if expected_pda != *queue_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let queue = DepositQueue::unpack(&queue_ai.data.borrow())?;
+        if queue.pool != *pool {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(queue)
+    }
+
+    /// Create the stats account of the `asset` vault of `pool`.
+    fn create_stats<'info>(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        asset: &Pubkey,
+        stats_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+        system_program_ai: &AccountInfo<'info>,
+    ) -> ProgramResult {
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[STATS_SEED, pool.as_ref(), asset.as_ref()],
+            program_id,
+        );
+        if expected_pda != *stats_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        create_pda_account(
+            payer_ai,
+            stats_ai,
+            VaultStats::LEN,
+            program_id,
+            system_program_ai,
+            &[STATS_SEED, pool.as_ref(), asset.as_ref(), &[bump]],
+        )?;
+        let stats = VaultStats {
+            is_initialized: true,
+            pool: *pool,
+            asset: *asset,
+            stats: PoolStats::default(),
+        };
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Deserialize the stats of the `asset` vault of `pool`, checking its
+    /// address.
+    fn load_stats(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        asset: &Pubkey,
+        stats_ai: &AccountInfo,
+    ) -> Result<VaultStats, ProgramError> {
+        if stats_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (expected_pda, _) = Pubkey::find_program_address(
+            &[STATS_SEED, pool.as_ref(), asset.as_ref()],
+            program_id,
+        );
+        if expected_pda != *stats_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let stats = VaultStats::unpack(&stats_ai.data.borrow())?;
+        if stats.pool != *pool || stats.asset != *asset {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(stats)
+    }
+
+    /// Collect the crank reward for `leaves` queued leaves from `payer_ai`.
+    fn charge_crank_reward<'info>(
+        pool: &PoolState,
+        queue: &mut DepositQueue,
+        queue_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+        system_program_ai: &AccountInfo<'info>,
+        leaves: u64,
+    ) -> ProgramResult {
+        let reward = pool
+            .params
+            .crank_reward
+            .checked_mul(leaves)
+            .ok_or(HandCrankedError::InvalidInstruction)?;
+        if reward == 0 {
+            return Ok(());
+        }
+        invoke(
+            &system_instruction::transfer(payer_ai.key, queue_ai.key, reward),
+            &[payer_ai.clone(), queue_ai.clone(), system_program_ai.clone()],
+        )?;
+        queue.fee_balance += reward;
+        Ok(())
+    }
+
+    /// Move `amount` of the pool asset from `source_ai` (owned by
+    /// `authority_ai`) into the vault and return what the vault received.
+    fn fund_vault<'info>(
+        vault_ai: &AccountInfo<'info>,
+        source_ai: &AccountInfo<'info>,
+        authority_ai: &AccountInfo<'info>,
+        amount: u64,
+        system_program_ai: &AccountInfo<'info>,
+        token: Option<&TokenAccounts<'_, 'info>>,
+    ) -> Result<u64, ProgramError> {
+        match token {
+            Some(token) => {
+                // Credit what the vault actually received, net of any
+                // Token-2022 transfer fee.
+                let before = token::balance(vault_ai)?;
+                token::transfer(token, source_ai, vault_ai, authority_ai, amount, &[])?;
+                Ok(token::balance(vault_ai)?
+                    .checked_sub(before)
+                    .ok_or(HandCrankedError::InvalidAccountData)?)
+            }
+            None => {
+                invoke(
+                    &system_instruction::transfer(authority_ai.key, vault_ai.key, amount),
+                    &[authority_ai.clone(), vault_ai.clone(), system_program_ai.clone()],
+                )?;
+                Ok(amount)
+            }
+        }
+    }
+
+    /// Move `amount` of the vault's asset out of the vault, returning what
+    /// `destination_ai` receives net of any Token-2022 transfer fee.
+    fn pay_from_vault<'info>(
+        vault: &VaultSigner,
+        vault_ai: &AccountInfo<'info>,
+        destination_ai: &AccountInfo<'info>,
+        amount: u64,
+        system_program_ai: &AccountInfo<'info>,
+        token: Option<&TokenAccounts<'_, 'info>>,
+    ) -> Result<u64, ProgramError> {
+        let vault_seeds = vault.seeds();
+        match token {
+            Some(token) => token::transfer(
+                token,
+                vault_ai,
+                destination_ai,
+                vault_ai,
+                amount,
+                &[&vault_seeds],
+            ),
+            None => {
+                invoke_signed(
+                    &system_instruction::transfer(vault_ai.key, destination_ai.key, amount),
+                    &[vault_ai.clone(), destination_ai.clone(), system_program_ai.clone()],
+                    &[&vault_seeds],
+                )?;
+                Ok(amount)
+            }
+        }
+    }
+
+    /// Create the vault behind `vault`: for SOL a system account funded up
+    /// to rent exemption, so the first deposit can be of any size,
+    /// otherwise a token account of `token.mint`.
+    fn create_vault<'info>(
+        vault: &VaultSigner,
+        vault_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+        system_program_ai: &AccountInfo<'info>,
+        token: Option<&TokenAccounts<'_, 'info>>,
+    ) -> ProgramResult {
+        match token {
+            Some(token) => {
+                create_pda_account(
+                    payer_ai,
+                    vault_ai,
+                    token::vault_len(token.mint)?,
+                    token.token_program.key,
+                    system_program_ai,
+                    &vault.seeds(),
+                )?;
+                token::initialize_vault(token, vault_ai)
+            }
+            None => {
+                let vault_rent =
+                    Rent::get()?.minimum_balance(0).saturating_sub(vault_ai.lamports());
+                if vault_rent > 0 {
+                    invoke(
+                        &system_instruction::transfer(payer_ai.key, vault_ai.key, vault_rent),
+                        &[payer_ai.clone(), vault_ai.clone(), system_program_ai.clone()],
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Queue `commitment` for the tree of `pool` and log it for indexers.
+    fn emit_new_commitment(
+        pool: &Pubkey,
+        queue: &mut DepositQueue,
+        commitment: &[u8; 32],
+        ciphertext: Vec<u8>,
+    ) -> ProgramResult {
+        let leaf_index = queue.push(*commitment)?;
+
+        HandCrankedEvent::NewCommitment(NewCommitmentEvent {
+            pool: *pool,
+            leaf_index,
+            commitment: *commitment,
+            ciphertext,
+        })
+        .emit()
+    }
+
+    /// Create the nullifier marker account; fails if it already exists.
+    ///
+    /// Only an initialized marker owned by the program counts as spent:
+    /// nullifiers are public before they are spent (failed transactions,
+    /// proof buffers, session inputs), so lamports sent to the address must
+    /// not freeze the note.
+    fn spend_nullifier<'info>(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        nullifier_ai: &AccountInfo<'info>,
+        nullifier: &[u8; 32],
+        system_program_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+    ) -> ProgramResult {
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[NULLIFIER_SEED, pool.as_ref(), nullifier], program_id);
+        if expected_pda != *nullifier_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        if nullifier_ai.owner == program_id {
+            let spent = NullifierState::try_from_slice(&nullifier_ai.data.borrow())
+                .map(|state| state.is_initialized)
+                .unwrap_or(false);
+            return Err(if spent {
+                HandCrankedError::NullifierAlreadySpent.into()
+            } else {
+                HandCrankedError::InvalidAccountData.into()
+            });
+        }
+
+        create_pda_account(
+            payer_ai,
+            nullifier_ai,
+            NullifierState::LEN,
+            program_id,
+            system_program_ai,
+            &[NULLIFIER_SEED, pool.as_ref(), nullifier, &[bump]],
+        )?;
+
+        let state = NullifierState {
+            is_initialized: true,
+            nullifier: *nullifier,
+        };
+        state.serialize(&mut &mut nullifier_ai.data.borrow_mut()[..])?;
+
+        HandCrankedEvent::NullifierSpent(NullifierSpentEvent {
+            pool: *pool,
+            nullifier: *nullifier,
+        })
+        .emit()
+    }
+
+    /// Check `vault_ai` is the vault of `asset` in `pool` and return its
+    /// signer seeds. `asset` only picks the vault in multi-asset pools.
+    fn vault(
+        program_id: &Pubkey,
+        pool_key: &Pubkey,
+        pool: &PoolState,
+        asset: &Pubkey,
+        vault_ai: &AccountInfo,
+    ) -> Result<VaultSigner, ProgramError> {
+        let asset = pool.is_multi_asset().then_some(*asset);
+        let mut seeds: Vec<&[u8]> = vec![VAULT_SEED, pool_key.as_ref()];
+        if let Some(asset) = &asset {
+            seeds.push(asset.as_ref());
+        }
+        let (expected_pda, bump) = Pubkey::find_program_address(&seeds, program_id);
+        if expected_pda != *vault_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(VaultSigner {
+            pool: *pool_key,
+            asset,
+            bump: [bump],
+        })
+    }
+
+    /// `asset_id` public input of a join-split against `pool`.
+    fn asset_id(pool: &PoolState, asset: &Pubkey) -> Option<[u8; 32]> {
+        pool.is_multi_asset().then(|| public_inputs::asset_id(asset))
+    }
+
+    /// Tree leaf for a deposit of `amount` of `asset` into `pool`.
+    fn deposit_leaf(
+        pool: &PoolState,
+        asset: &Pubkey,
+        amount: u64,
+        commitment: &[u8; 32],
+    ) -> Result<[u8; 32], ProgramError> {
+        match Self::asset_id(pool, asset) {
+            Some(asset_id) => asset_deposit_leaf(&asset_id, amount, commitment),
+            None => deposit_leaf(amount, commitment),
+        }
+    }
+
+    /// Withdraw (unshield) `amount` lamports of a note.
+    ///
+    /// The proof shows the caller knows a note in the tree under `root`
+    /// whose nullifier is `nullifier`. Amount, fee and relayer are public
+    /// inputs and the recipient is bound through the ext data hash, so none
+    /// of them can be changed without a new proof.
+    ///
+    /// With `delayed` the relayer is paid at once but the recipient's share
+    /// is parked in a `PendingWithdrawal` until `ExecuteWithdraw`.
+    ///
+    /// An `association` proof additionally shows the note is in a curator's
+    /// association set; the set id is recorded in the event.
+    ///
+    /// The proof is bound to the slot `root` became current at. In pools
+    /// with a `min_note_age` that slot must be at least that old, so the
+    /// note must be proven against an older root.
+    #[allow(clippy::too_many_arguments)]
+    fn process_withdraw(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        verifier: &dyn ProofVerifier,
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        root: &[u8; 32],
+        nullifier: &[u8; 32],
+        amount: u64,
+        fee: u64,
+        association: Option<AssociationProof>,
+        callback: Option<WithdrawCallback>,
+        delayed: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let nullifier_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let recipient_ai = next_account_info(account_info_iter)?;
+        let relayer_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
+        let pending_ai = match delayed {
+            true => Some(next_account_info(account_info_iter)?),
+            false => None,
+        };
+        let association_ais = match association {
+            Some(_) => Some((
+                next_account_info(account_info_iter)?,
+                next_account_info(account_info_iter)?,
+            )),
+            None => None,
+        };
+        let proof_ai = match proof {
+            ProofSource::Inline(_) => None,
+            ProofSource::Buffer | ProofSource::Session => {
+                Some(next_account_info(account_info_iter)?)
+            }
+        };
+        let key_ai = match proof {
+            ProofSource::Session => None,
+            _ => Some(next_account_info(account_info_iter)?),
+        };
+
+        if !relayer_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        if fee > amount {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        pool.check_denomination(amount)?;
+        let token = TokenAccounts::next(&pool, account_info_iter)?;
+        let relayer_destination_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => relayer_ai,
+        };
+        let callback_ais = match &callback {
+            Some(callback) => {
+                // The callback program, then one account per callback account.
+                let count = callback.accounts.len() + 1;
+                Some(account_info_iter.by_ref().take(count).cloned().collect::<Vec<_>>())
+            }
+            None => None,
+        };
+
+        if delayed && callback.is_some() {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let root_slot = pool
+            .tree
+            .root_slot(root)
+            .ok_or(HandCrankedError::UnknownRoot)?;
+        pool.params.check_note_age(root_slot, Clock::get()?.slot)?;
+
+        let asset = token::asset(token.as_ref());
+        let vault = Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
+
+        let ext_data = ExtData {
+            recipient: *recipient_ai.key,
+            relayer: *relayer_ai.key,
+            fee,
+            encrypted_outputs: vec![],
+            callback,
+        };
+
+        Self::verify_proof_source(
+            program_id,
+            verifier,
+            pool_ai.key,
+            proof_system,
+            pool.circuit(),
+            proof,
+            proof_ai,
+            key_ai,
+            relayer_ai,
+            &PublicInputs {
+                domain: domain_tag(program_id, pool_ai.key, pool.params.cluster),
+                root: *root,
+                root_slot,
+                nullifiers: vec![*nullifier],
+                output_commitments: vec![],
+                public_amount: amount,
+                fee,
+                relayer: *relayer_ai.key,
+                ext_data_hash: ext_data.hash()?,
+                audit: None,
+                asset_id: Self::asset_id(&pool, &asset),
+            }
+            .to_field_elements(),
+        )?;
+
+        let association_set = match (&association, association_ais) {
+            (Some(association), Some((association_ai, key_ai))) => Some(Self::verify_association(
+                program_id,
+                verifier,
+                pool_ai.key,
+                &pool,
+                association_ai,
+                key_ai,
+                association,
+                nullifier,
+            )?),
+            _ => None,
+        };
+
+        Self::spend_nullifier(
+            program_id,
+            pool_ai.key,
+            nullifier_ai,
+            nullifier,
+            system_program_ai,
+            relayer_ai,
+        )?;
+
+        if fee > 0 {
+            Self::pay_from_vault(
+                &vault,
+                vault_ai,
+                relayer_destination_ai,
+                fee,
+                system_program_ai,
+                token.as_ref(),
+            )?;
+        }
+
+        let pending_amount = if delayed { amount - fee } else { 0 };
+        let mut stats = Self::load_stats(program_id, pool_ai.key, &asset, stats_ai)?;
+        stats.stats.record_withdraw(amount, pending_amount)?;
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+
+        match pending_ai {
+            Some(pending_ai) => {
+                let release_slot = pool.params.release_slot(nullifier, Clock::get()?.slot)?;
+                Self::create_pending_withdrawal(
+                    program_id,
+                    pool_ai.key,
+                    pending_ai,
+                    nullifier,
+                    PendingWithdrawal {
+                        is_initialized: true,
+                        pool: *pool_ai.key,
+                        recipient: *recipient_ai.key,
+                        amount: pending_amount,
+                        release_slot,
+                        asset,
+                    },
+                    system_program_ai,
+                    relayer_ai,
+                )?;
+
+                HandCrankedEvent::WithdrawalRequested(WithdrawalRequestedEvent {
+                    nullifier: *nullifier,
+                    pending: *pending_ai.key,
+                    fee,
+                    release_slot,
+                    association_set,
+                })
+                .emit()?;
+            }
+            None => {
+                let received = Self::pay_from_vault(
+                    &vault,
+                    vault_ai,
+                    recipient_ai,
+                    amount - fee,
+                    system_program_ai,
+                    token.as_ref(),
+                )?;
+
+                HandCrankedEvent::Withdrawal(WithdrawalEvent {
+                    nullifier: *nullifier,
+                    recipient: *recipient_ai.key,
+                    amount,
+                    fee,
+                    received,
+                    association_set,
+                })
+                .emit()?;
+
+                if let (Some(callback), Some(callback_ais)) = (ext_data.callback, callback_ais) {
+                    Self::invoke_callback(callback, &callback_ais)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Invoke a withdraw callback with exactly the accounts the proof
+    /// committed to. `callback_ais` is the callback program followed by
+    /// its accounts.
+    fn invoke_callback(callback: WithdrawCallback, callback_ais: &[AccountInfo]) -> ProgramResult {
+        let (program_ai, account_ais) = callback_ais
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if *program_ai.key != callback.program_id {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        if account_ais.len() != callback.accounts.len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        let mut metas = Vec::with_capacity(callback.accounts.len());
+        for (account, account_ai) in callback.accounts.iter().zip(account_ais) {
+            if account.pubkey != *account_ai.key {
+                return Err(HandCrankedError::InvalidAccountData.into());
+            }
+            metas.push(match account.is_writable {
+                true => AccountMeta::new(account.pubkey, false),
+                false => AccountMeta::new_readonly(account.pubkey, false),
+            });
+        }
+
+        invoke(
+            &Instruction {
+                program_id: callback.program_id,
+                accounts: metas,
+                data: callback.data,
+            },
+            callback_ais,
+        )
+    }
+
+    /// Check an association proof for the note spent under `nullifier` and
+    /// return the set id.
+    #[allow(clippy::too_many_arguments)]
+    fn verify_association(
+        program_id: &Pubkey,
+        verifier: &dyn ProofVerifier,
+        pool: &Pubkey,
+        pool_state: &PoolState,
+        association_ai: &AccountInfo,
+        key_ai: &AccountInfo,
+        association: &AssociationProof,
+        nullifier: &[u8; 32],
+    ) -> Result<u64, ProgramError> {
+        if association_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (expected_pda, _) = Pubkey::find_program_address(
+            &[ASSOCIATION_SEED, pool.as_ref(), &association.set_id.to_le_bytes()],
+            program_id,
+        );
+        if expected_pda != *association_ai.key {
+            return Err(HandCrankedError::InvalidAssociationSet.into());
+        }
+        let set = AssociationSet::try_from_slice(&association_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !set.is_initialized || set.pool != *pool || set.set_id != association.set_id {
+            return Err(HandCrankedError::InvalidAssociationSet.into());
+        }
+        if !set.is_known_root(&association.root) {
+            return Err(HandCrankedError::UnknownRoot.into());
+        }
+
+        Self::verify_proof(
+            program_id,
+            verifier,
+            pool,
+            association.proof_system,
+            Circuit::Association,
+            key_ai,
+            &association.proof,
+            &AssociationInputs {
+                domain: domain_tag(program_id, pool, pool_state.params.cluster),
+                association_root: association.root,
+                nullifier: *nullifier,
+            }
+            .to_field_elements(),
+        )?;
+
+        Ok(set.set_id)
+    }
+
+    /// Create the `PendingWithdrawal` record for a `RequestWithdraw`.
+    #[allow(clippy::too_many_arguments)]
+    fn create_pending_withdrawal<'info>(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        pending_ai: &AccountInfo<'info>,
+        nullifier: &[u8; 32],
+        pending: PendingWithdrawal,
+        system_program_ai: &AccountInfo<'info>,
+        payer_ai: &AccountInfo<'info>,
+    ) -> ProgramResult {
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[PENDING_SEED, pool.as_ref(), nullifier], program_id);
+        if expected_pda != *pending_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        // Keyed on the nullifier too, so it must survive pre-funding.
+        create_pda_account(
+            payer_ai,
+            pending_ai,
+            PendingWithdrawal::LEN,
+            program_id,
+            system_program_ai,
+            &[PENDING_SEED, pool.as_ref(), nullifier, &[bump]],
+        )?;
+
+        pending.serialize(&mut &mut pending_ai.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Pay out a `PendingWithdrawal` whose release slot has passed and
+    /// close it, returning its rent to the recipient. Permissionless.
+    fn process_execute_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let pending_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let recipient_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
+
+        if pool_ai.owner != program_id || pending_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let token = TokenAccounts::next(&pool, account_info_iter)?;
+
+        let pending = PendingWithdrawal::try_from_slice(&pending_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !pending.is_initialized
+            || pending.pool != *pool_ai.key
+            || pending.recipient != *recipient_ai.key
+            || pending.asset != token::asset(token.as_ref())
+        {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        if Clock::get()?.slot < pending.release_slot {
+            return Err(HandCrankedError::WithdrawalNotReleased.into());
+        }
+
+        let mut stats = Self::load_stats(program_id, pool_ai.key, &pending.asset, stats_ai)?;
+        stats.stats.record_payout(pending.amount)?;
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+
+        let vault = Self::vault(program_id, pool_ai.key, &pool, &pending.asset, vault_ai)?;
+        let received = Self::pay_from_vault(
+            &vault,
+            vault_ai,
+            recipient_ai,
+            pending.amount,
+            system_program_ai,
+            token.as_ref(),
+        )?;
+
+        // For token pools the recipient is a token account, which can take
+        // the rent lamports like any other account.
+        let rent_lamports = pending_ai.lamports();
+        **pending_ai.try_borrow_mut_lamports()? = 0;
+        **recipient_ai.try_borrow_mut_lamports()? += rent_lamports;
+        pending_ai.data.borrow_mut().fill(0);
+
+        HandCrankedEvent::WithdrawalExecuted(WithdrawalExecutedEvent {
+            pending: *pending_ai.key,
+            recipient: pending.recipient,
+            amount: pending.amount,
+            received,
+        })
+        .emit()?;
+
+        Ok(())
+    }
+
+    /// Fail if the vault holds less than the pool's tracked liability.
+    /// Permissionless; meant to be run by monitors after every slot.
+    fn process_audit_invariant(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
+
+        if pool_ai.owner != program_id || stats_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let asset = VaultStats::unpack(&stats_ai.data.borrow())?.asset;
+        let stats = Self::load_stats(program_id, pool_ai.key, &asset, stats_ai)?;
+        Self::vault(program_id, pool_ai.key, &pool, &asset, vault_ai)?;
+
+        let balance = Self::vault_balance(&asset, vault_ai)?;
+        let liability = stats.stats.liability();
+        msg!("vault balance {}, liability {}", balance, liability);
+        if balance < liability {
+            return Err(HandCrankedError::InvariantViolated.into());
+        }
+
+        Ok(())
+    }
+
+    /// Balance of the `asset` vault, not counting the rent of a SOL vault.
+    fn vault_balance(asset: &Pubkey, vault_ai: &AccountInfo) -> Result<u64, ProgramError> {
+        if *asset == NATIVE_ASSET {
+            // SOL vaults are funded up to rent exemption when created.
+            Ok(vault_ai
+                .lamports()
+                .saturating_sub(Rent::get()?.minimum_balance(0)))
+        } else {
+            token::balance(vault_ai)
+        }
+    }
+
+    /// Record a new association set root published by the pool curator.
+    fn process_publish_association_root(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        set_id: u64,
+        root: [u8; 32],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let association_ai = next_account_info(account_info_iter)?;
+        let curator_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        if !curator_ai.is_signer
+            || pool.params.curator == Pubkey::default()
+            || *curator_ai.key != pool.params.curator
+        {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        let set_id_bytes = set_id.to_le_bytes();
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[ASSOCIATION_SEED, pool_ai.key.as_ref(), &set_id_bytes],
+            program_id,
+        );
+        if expected_pda != *association_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        // Lamports sent to the address beforehand do not make it a set.
+        let mut set = if association_ai.owner == program_id {
+            let set = AssociationSet::try_from_slice(&association_ai.data.borrow())
+                .map_err(|_| HandCrankedError::InvalidAccountData)?;
+            if !set.is_initialized || set.pool != *pool_ai.key || set.set_id != set_id {
+                return Err(HandCrankedError::InvalidAssociationSet.into());
+            }
+            set
+        } else {
+            create_pda_account(
+                curator_ai,
+                association_ai,
+                AssociationSet::LEN,
+                program_id,
+                system_program_ai,
+                &[ASSOCIATION_SEED, pool_ai.key.as_ref(), &set_id_bytes, &[bump]],
+            )?;
+            AssociationSet::new(*pool_ai.key, set_id)
+        };
+
+        set.push_root(root);
+        set.serialize(&mut &mut association_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_open_proof_buffer(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        buffer_id: u64,
+        len: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let buffer_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        if !authority_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if len == 0 || len > ProofBuffer::MAX_PROOF_LEN {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let buffer_id_bytes = buffer_id.to_le_bytes();
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[PROOF_BUFFER_SEED, authority_ai.key.as_ref(), &buffer_id_bytes],
+            program_id,
+        );
+        if expected_pda != *buffer_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        // A program can only create accounts of up to
+        // `MAX_PERMITTED_DATA_INCREASE` bytes; `WriteProofChunk` grows the
+        // buffer from there, on rent paid for the full size now.
+        let full_len = ProofBuffer::LEN + len as usize;
+        let space = full_len.min(MAX_PERMITTED_DATA_INCREASE);
+        create_pda_account(
+            authority_ai,
+            buffer_ai,
+            space,
+            program_id,
+            system_program_ai,
+            &[
+                PROOF_BUFFER_SEED,
+                authority_ai.key.as_ref(),
+                &buffer_id_bytes,
+                &[bump],
+            ],
+        )?;
+        let rent = Rent::get()?;
+        let top_up = rent
+            .minimum_balance(full_len)
+            .saturating_sub(buffer_ai.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(authority_ai.key, buffer_ai.key, top_up),
+                &[authority_ai.clone(), buffer_ai.clone(), system_program_ai.clone()],
+            )?;
+        }
+
+        ProofBuffer {
+            is_initialized: true,
+            authority: *authority_ai.key,
+            buffer_id,
+            len,
+        }
+        .serialize(&mut &mut buffer_ai.data.borrow_mut()[..ProofBuffer::LEN])?;
+
+        Ok(())
+    }
+
+    fn process_write_proof_chunk(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        offset: u32,
+        data: &[u8],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let buffer_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+
+        let header = Self::check_proof_buffer(program_id, buffer_ai, authority_ai)?;
+
+        let start = offset as usize;
+        let end = start
+            .checked_add(data.len())
+            .filter(|end| *end <= header.len as usize)
+            .ok_or(HandCrankedError::InvalidInstruction)?;
+        // An account grows by at most `MAX_PERMITTED_DATA_INCREASE` bytes
+        // per instruction, so the buffer only grows by appending: a chunk
+        // may not start past its current end.
+        if start > buffer_ai.data_len() - ProofBuffer::LEN {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+        if buffer_ai.data_len() < ProofBuffer::LEN + end {
+            buffer_ai.realloc(ProofBuffer::LEN + end, true)?;
+        }
+        buffer_ai.data.borrow_mut()[ProofBuffer::LEN + start..ProofBuffer::LEN + end]
+            .copy_from_slice(data);
+
+        Ok(())
+    }
+
+    fn process_close_proof_buffer(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let buffer_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+
+        Self::check_proof_buffer(program_id, buffer_ai, authority_ai)?;
+        Self::close_account(buffer_ai, authority_ai)
+    }
+
+    /// The buffer must be ours, at the address of its header and
+    /// `authority_ai` its signing authority. Returns the header.
+    fn check_proof_buffer(
+        program_id: &Pubkey,
+        buffer_ai: &AccountInfo,
+        authority_ai: &AccountInfo,
+    ) -> Result<ProofBuffer, ProgramError> {
+        if buffer_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let header = ProofBuffer::unpack_header(&buffer_ai.data.borrow())?;
+        let (expected_pda, _) = Pubkey::find_program_address(
+            &[
+                PROOF_BUFFER_SEED,
+                header.authority.as_ref(),
+                &header.buffer_id.to_le_bytes(),
+            ],
+            program_id,
+        );
+        if expected_pda != *buffer_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        if !authority_ai.is_signer || header.authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        Ok(header)
+    }
+
+    fn process_initialize_verifying_key(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        len: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let key_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        Self::check_pool_authority(program_id, pool_ai, authority_ai)?;
+
+        // The PLONK verifier only exists off-chain.
+        if proof_system == ProofSystem::Plonk {
+            return Err(HandCrankedError::UnsupportedProofSystem.into());
+        }
+
+        if len == 0 || len > VerifyingKeyState::MAX_KEY_LEN {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let (expected_pda, bump) =
+            Self::verifying_key_address(program_id, pool_ai.key, proof_system, circuit);
+        if expected_pda != *key_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        create_pda_account(
+            authority_ai,
+            key_ai,
+            VerifyingKeyState::LEN + len as usize,
+            program_id,
+            system_program_ai,
+            &[
+                VERIFYING_KEY_SEED,
+                pool_ai.key.as_ref(),
+                &[proof_system as u8],
+                &[circuit as u8],
+                &[bump],
+            ],
+        )?;
+
+        VerifyingKeyState {
+            is_initialized: true,
+            pool: *pool_ai.key,
+            proof_system,
+            circuit,
+            len,
+            finalized: false,
+        }
+        .serialize(&mut &mut key_ai.data.borrow_mut()[..VerifyingKeyState::LEN])?;
+
+        Ok(())
+    }
+
+    fn process_write_verifying_key(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        offset: u32,
+        data: &[u8],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let key_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+
+        let header = Self::unfinalized_verifying_key(program_id, pool_ai, key_ai, authority_ai)?;
+
+        let start = VerifyingKeyState::LEN + offset as usize;
+        let end = start
+            .checked_add(data.len())
+            .filter(|end| *end <= VerifyingKeyState::LEN + header.len as usize)
+            .ok_or(HandCrankedError::InvalidInstruction)?;
+        key_ai.data.borrow_mut()[start..end].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    fn process_finalize_verifying_key(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let key_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+
+        let mut header =
+            Self::unfinalized_verifying_key(program_id, pool_ai, key_ai, authority_ai)?;
+        header.finalized = true;
+        header.serialize(&mut &mut key_ai.data.borrow_mut()[..VerifyingKeyState::LEN])?;
+
+        Ok(())
+    }
+
+    /// The header of the pool's verifying key in `key_ai`, which the pool
+    /// authority may still change because it is not finalized.
+    fn unfinalized_verifying_key(
+        program_id: &Pubkey,
+        pool_ai: &AccountInfo,
+        key_ai: &AccountInfo,
+        authority_ai: &AccountInfo,
+    ) -> Result<VerifyingKeyState, ProgramError> {
+        Self::check_pool_authority(program_id, pool_ai, authority_ai)?;
+
+        if key_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (header, _) = VerifyingKeyState::unpack(&key_ai.data.borrow())?;
+        let (expected_pda, _) = Self::verifying_key_address(
+            program_id,
+            pool_ai.key,
+            header.proof_system,
+            header.circuit,
+        );
+        if header.pool != *pool_ai.key || expected_pda != *key_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        if header.finalized {
+            return Err(HandCrankedError::VerifyingKeyFinalized.into());
+        }
+        Ok(header)
+    }
+
+    /// The pool must be ours and `authority_ai` its signing authority.
+    fn check_pool_authority(
+        program_id: &Pubkey,
+        pool_ai: &AccountInfo,
+        authority_ai: &AccountInfo,
+    ) -> ProgramResult {
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        if !authority_ai.is_signer || pool.authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        Ok(())
+    }
+
+    fn verifying_key_address(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                VERIFYING_KEY_SEED,
+                pool.as_ref(),
+                &[proof_system as u8],
+                &[circuit as u8],
+            ],
+            program_id,
+        )
+    }
+
+    /// Zero a program account and move its lamports to `refund_ai`.
+    fn close_account(account_ai: &AccountInfo, refund_ai: &AccountInfo) -> ProgramResult {
+        let lamports = account_ai.lamports();
+        **account_ai.try_borrow_mut_lamports()? = 0;
+        **refund_ai.try_borrow_mut_lamports()? += lamports;
+        account_ai.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    /// Verify a proof after rejecting non-canonical public inputs, so no
+    /// `ProofVerifier` implementation has to repeat the check. The key is
+    /// read from `key_ai`, which must be the pool's verifying key account
+    /// for `proof_system` and `circuit`.
+    #[allow(clippy::too_many_arguments)]
+    fn verify_proof(
+        program_id: &Pubkey,
+        verifier: &dyn ProofVerifier,
+        pool: &Pubkey,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        key_ai: &AccountInfo,
+        proof: &[u8],
+        public_inputs: &[[u8; 32]],
+    ) -> ProgramResult {
+        public_inputs::check_canonical(public_inputs)?;
+        let data = key_ai.data.borrow();
+        let key = Self::verifying_key(program_id, pool, proof_system, circuit, key_ai, &data)?;
+        verifier.verify(proof_system, circuit, key, proof, public_inputs)
+    }
+
+    /// The key bytes of `key_ai`, after checking it is the finalized
+    /// verifying key account of `pool` for `proof_system` and `circuit`.
+    /// `data` is the account's borrowed data.
+    fn verifying_key<'a>(
+        program_id: &Pubkey,
+        pool: &Pubkey,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        key_ai: &AccountInfo,
+        data: &'a [u8],
+    ) -> Result<&'a [u8], ProgramError> {
+        if key_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (expected_pda, _) =
+            Self::verifying_key_address(program_id, pool, proof_system, circuit);
+        if expected_pda != *key_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let (header, key) = VerifyingKeyState::unpack(data)?;
+        if header.pool != *pool || header.proof_system != proof_system || header.circuit != circuit
+        {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        if !header.finalized {
+            return Err(HandCrankedError::VerifyingKeyNotFinalized.into());
+        }
+        Ok(key)
+    }
+
+    /// Check a transfer or withdraw proof from any `ProofSource`. Buffers
+    /// and sessions must belong to `relayer_ai` and are closed to it; a
+    /// session must be at its address and not have timed out. Inline and
+    /// buffered proofs are checked against the key in `key_ai`; a session
+    /// was checked against it step by step.
+    #[allow(clippy::too_many_arguments)]
+    fn verify_proof_source(
+        program_id: &Pubkey,
+        verifier: &dyn ProofVerifier,
+        pool: &Pubkey,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        source: ProofSource,
+        proof_ai: Option<&AccountInfo>,
+        key_ai: Option<&AccountInfo>,
+        relayer_ai: &AccountInfo,
+        public_inputs: &[[u8; 32]],
+    ) -> ProgramResult {
+        if source != ProofSource::Session {
+            let proof = Self::load_proof(program_id, source, proof_ai, relayer_ai)?;
+            let key_ai = key_ai.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            return Self::verify_proof(
+                program_id,
+                verifier,
+                pool,
+                proof_system,
+                circuit,
+                key_ai,
+                &proof,
+                public_inputs,
+            );
+        }
+
+        let session_ai = proof_ai.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let session = Self::load_session(program_id, session_ai)?;
+        if session.prover != *relayer_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        if session.is_expired(Clock::get()?.slot) {
+            return Err(HandCrankedError::SessionExpired.into());
+        }
+        if session.pool != *pool
+            || session.proof_system != proof_system
+            || session.circuit != circuit
+            || session.public_inputs != public_inputs
+            || session.step != SessionStep::Verified
+        {
+            return Err(HandCrankedError::InvalidProof.into());
+        }
+        Self::close_account(session_ai, relayer_ai)
+    }
+
+    /// The verification session in `session_ai`. Any program-owned account
+    /// can parse as a session, so it is only trusted at the address derived
+    /// from its own fields.
+    fn load_session(
+        program_id: &Pubkey,
+        session_ai: &AccountInfo,
+    ) -> Result<VerificationSession, ProgramError> {
+        if session_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let session = VerificationSession::unpack(&session_ai.data.borrow())?;
+        let (expected_pda, _) = Pubkey::find_program_address(
+            &[SESSION_SEED, session.prover.as_ref(), &session.session_id.to_le_bytes()],
+            program_id,
+        );
+        if expected_pda != *session_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(session)
+    }
+
+    /// Resolve an inline or buffered `ProofSource` to proof bytes. A
+    /// buffer must belong to `authority_ai` and is closed to it once read.
+    fn load_proof(
+        program_id: &Pubkey,
+        source: ProofSource,
+        buffer_ai: Option<&AccountInfo>,
+        authority_ai: &AccountInfo,
+    ) -> Result<Vec<u8>, ProgramError> {
+        match (source, buffer_ai) {
+            (ProofSource::Inline(proof), _) => Ok(proof),
+            (ProofSource::Buffer, Some(buffer_ai)) => {
+                Self::check_proof_buffer(program_id, buffer_ai, authority_ai)?;
+                let proof = ProofBuffer::unpack(&buffer_ai.data.borrow())?.1.to_vec();
+                Self::close_account(buffer_ai, authority_ai)?;
+                Ok(proof)
+            }
+            (ProofSource::Buffer, None) => Err(ProgramError::NotEnoughAccountKeys),
+            (ProofSource::Session, _) => Err(HandCrankedError::InvalidInstruction.into()),
+        }
+    }
+
+    fn process_open_verification_session(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        session_id: u64,
+        proof_system: ProofSystem,
+        circuit: Circuit,
+        proof: ProofSource,
+        public_inputs: Vec<[u8; 32]>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let session_ai = next_account_info(account_info_iter)?;
+        let prover_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let proof_buffer_ai = match proof {
+            ProofSource::Buffer => Some(next_account_info(account_info_iter)?),
+            _ => None,
+        };
+
+        if !prover_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        PoolState::unpack(&pool_ai.data.borrow())?;
+
+        if public_inputs.len() > VerificationSession::MAX_PUBLIC_INPUTS {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+        public_inputs::check_canonical(&public_inputs)?;
+
+        let session_id_bytes = session_id.to_le_bytes();
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[SESSION_SEED, prover_ai.key.as_ref(), &session_id_bytes],
+            program_id,
+        );
+        if expected_pda != *session_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let proof = Self::load_proof(program_id, proof, proof_buffer_ai, prover_ai)?;
+
+        create_pda_account(
+            prover_ai,
+            session_ai,
+            VerificationSession::len(proof.len(), public_inputs.len()),
+            program_id,
+            system_program_ai,
+            &[SESSION_SEED, prover_ai.key.as_ref(), &session_id_bytes, &[bump]],
+        )?;
+
+        VerificationSession {
+            is_initialized: true,
+            prover: *prover_ai.key,
+            pool: *pool_ai.key,
+            session_id,
+            opened_slot: Clock::get()?.slot,
+            proof_system,
+            circuit,
+            step: SessionStep::PrepareInputs(0),
+            prepared_inputs: [0; PREPARED_INPUTS_LEN],
+            public_inputs,
+            proof,
+        }
+        .serialize(&mut &mut session_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_step_verification_session(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_steps: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let session_ai = next_account_info(account_info_iter)?;
+        let key_ai = next_account_info(account_info_iter)?;
+
+        let mut session = Self::load_session(program_id, session_ai)?;
+        if session.is_expired(Clock::get()?.slot) {
+            return Err(HandCrankedError::SessionExpired.into());
+        }
+        let key_data = key_ai.data.borrow();
+        let key = Self::verifying_key(
+            program_id,
+            &session.pool,
+            session.proof_system,
+            session.circuit,
+            key_ai,
+            &key_data,
+        )?;
+
+        for _ in 0..max_steps {
+            if session.step == SessionStep::Verified {
+                break;
+            }
+            session.step = session::advance(
+                session.proof_system,
+                key,
+                &session.proof,
+                &session.public_inputs,
+                session.step,
+                &mut session.prepared_inputs,
+            )?;
+        }
+        msg!("Verification session step: {:?}", session.step);
+
+        session.serialize(&mut &mut session_ai.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    fn process_close_verification_session(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let session_ai = next_account_info(account_info_iter)?;
+        let prover_ai = next_account_info(account_info_iter)?;
+        let caller_ai = next_account_info(account_info_iter)?;
+
+        if !caller_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        let session = Self::load_session(program_id, session_ai)?;
+        if session.prover != *prover_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        if caller_ai.key != prover_ai.key && !session.is_expired(Clock::get()?.slot) {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        Self::close_account(session_ai, prover_ai)
+    }
+
+    fn process_initialize_swap_reserve(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        fee_bps: u16,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_a_ai = next_account_info(account_info_iter)?;
+        let pool_b_ai = next_account_info(account_info_iter)?;
+        let reserve_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        if !authority_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_a_ai.owner != program_id || pool_b_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        if pool_a_ai.key == pool_b_ai.key || fee_bps >= SwapReserve::MAX_FEE_BPS {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let pool_a = PoolState::unpack(&pool_a_ai.data.borrow())?;
+        let pool_b = PoolState::unpack(&pool_b_ai.data.borrow())?;
+        if pool_a.authority != *authority_ai.key || pool_b.authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        // A reserve prices one asset against another.
+        if pool_a.is_multi_asset() || pool_b.is_multi_asset() {
+            return Err(HandCrankedError::UnsupportedAsset.into());
+        }
+
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[RESERVE_SEED, pool_a_ai.key.as_ref(), pool_b_ai.key.as_ref()],
+            program_id,
+        );
+        if expected_pda != *reserve_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        create_pda_account(
+            authority_ai,
+            reserve_ai,
+            SwapReserve::LEN,
+            program_id,
+            system_program_ai,
+            &[
+                RESERVE_SEED,
+                pool_a_ai.key.as_ref(),
+                pool_b_ai.key.as_ref(),
+                &[bump],
+            ],
+        )?;
+
+        SwapReserve {
+            is_initialized: true,
+            authority: *authority_ai.key,
+            pool_a: *pool_a_ai.key,
+            pool_b: *pool_b_ai.key,
+            reserve_a: 0,
+            reserve_b: 0,
+            fee_bps,
+        }
+        .serialize(&mut &mut reserve_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Add (`add`) or remove reserve liquidity of both pools.
+    fn process_swap_liquidity(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amounts: [u64; 2],
+        add: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_a_ai = next_account_info(account_info_iter)?;
+        let pool_b_ai = next_account_info(account_info_iter)?;
+        let reserve_ai = next_account_info(account_info_iter)?;
+        let vault_a_ai = next_account_info(account_info_iter)?;
+        let vault_b_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        let mut reserve = Self::load_swap_reserve(program_id, reserve_ai)?;
+        if !authority_ai.is_signer || reserve.authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        if reserve.pool_a != *pool_a_ai.key || reserve.pool_b != *pool_b_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let sides = [
+            (pool_a_ai, vault_a_ai, &mut reserve.reserve_a, amounts[0]),
+            (pool_b_ai, vault_b_ai, &mut reserve.reserve_b, amounts[1]),
+        ];
+        for (pool_ai, vault_ai, balance, amount) in sides {
+            let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+            let token = TokenAccounts::next(&pool, account_info_iter)?;
+            let authority_account_ai = match token {
+                Some(_) => next_account_info(account_info_iter)?,
+                None => authority_ai,
+            };
+            if amount == 0 {
+                continue;
+            }
+
+            let vault = Self::vault(program_id, pool_ai.key, &pool, &pool.asset, vault_ai)?;
+            if add {
+                let received = Self::fund_vault(
+                    vault_ai,
+                    authority_account_ai,
+                    authority_ai,
+                    amount,
+                    system_program_ai,
+                    token.as_ref(),
+                )?;
+                *balance = balance
+                    .checked_add(received)
+                    .ok_or(HandCrankedError::InvalidInstruction)?;
+            } else {
+                *balance = balance
+                    .checked_sub(amount)
+                    .ok_or(HandCrankedError::InsufficientLiquidity)?;
+                Self::pay_from_vault(
+                    &vault,
+                    vault_ai,
+                    authority_account_ai,
+                    amount,
+                    system_program_ai,
+                    token.as_ref(),
+                )?;
+            }
+        }
+
+        reserve.serialize(&mut &mut reserve_ai.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    fn load_swap_reserve(
+        program_id: &Pubkey,
+        reserve_ai: &AccountInfo,
+    ) -> Result<SwapReserve, ProgramError> {
+        if reserve_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let reserve = SwapReserve::try_from_slice(&reserve_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !reserve.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let (expected_pda, _) = Pubkey::find_program_address(
+            &[RESERVE_SEED, reserve.pool_a.as_ref(), reserve.pool_b.as_ref()],
+            program_id,
+        );
+        if expected_pda != *reserve_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(reserve)
+    }
+
+    /// Swap a note of one pool for a note of another through their swap
+    /// reserve.
+    ///
+    /// The spend is proven like a withdrawal; the output note's amount is
+    /// only known once the reserve prices the trade, so the proof commits
+    /// to `output_commitment` and `min_amount_out` and the leaf is built
+    /// on-chain as for a deposit.
+    ///
+    /// If either pool has an auditor the proof also covers `auditor_memo`,
+    /// which is logged next to the output leaf.
+    #[allow(clippy::too_many_arguments)]
+    fn process_private_swap(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        verifier: &dyn ProofVerifier,
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        root: &[u8; 32],
+        nullifier: &[u8; 32],
+        amount_in: u64,
+        fee: u64,
+        min_amount_out: u64,
+        output_commitment: [u8; 32],
+        encrypted_note: Vec<u8>,
+        auditor_memo: Option<AuditorMemo>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_in_ai = next_account_info(account_info_iter)?;
+        let stats_in_ai = next_account_info(account_info_iter)?;
+        let nullifier_ai = next_account_info(account_info_iter)?;
+        let vault_in_ai = next_account_info(account_info_iter)?;
+        let pool_out_ai = next_account_info(account_info_iter)?;
+        let queue_out_ai = next_account_info(account_info_iter)?;
+        let reserve_ai = next_account_info(account_info_iter)?;
+        let relayer_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_out_ai = next_account_info(account_info_iter)?;
+        let proof_ai = match proof {
+            ProofSource::Inline(_) => None,
+            ProofSource::Buffer | ProofSource::Session => {
+                Some(next_account_info(account_info_iter)?)
+            }
+        };
+        let key_ai = match proof {
+            ProofSource::Session => None,
+            _ => Some(next_account_info(account_info_iter)?),
+        };
+
+        if !relayer_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_in_ai.owner != program_id || pool_out_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        if fee > amount_in {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let pool_in = PoolState::unpack(&pool_in_ai.data.borrow())?;
+        pool_in.check_denomination(amount_in)?;
+        let pool_out = PoolState::unpack(&pool_out_ai.data.borrow())?;
+        // The output amount is set by the reserve, not by the pool.
+        if pool_out.denomination.is_some() {
+            return Err(HandCrankedError::InvalidDenomination.into());
+        }
+
+        // Value leaving or entering an audited pool is audited as a
+        // transfer would be; two audited pools must share the auditor.
+        let auditor = match (pool_in.params.auditor, pool_out.params.auditor) {
+            (Some(a), Some(b)) if a != b => {
+                return Err(HandCrankedError::InvalidInstruction.into())
+            }
+            (a, b) => a.or(b),
+        };
+        let (circuit, audit) = match (auditor, &auditor_memo) {
+            (Some(key), Some(memo)) => (
+                Circuit::AuditedSwap,
+                Some(AuditBinding {
+                    key,
+                    memo: memo.clone(),
+                }),
+            ),
+            (None, None) => (Circuit::Swap, None),
+            _ => return Err(HandCrankedError::InvalidInstruction.into()),
+        };
+
+        let mut stats_in =
+            Self::load_stats(program_id, pool_in_ai.key, &pool_in.asset, stats_in_ai)?;
+        let mut stats_out =
+            Self::load_stats(program_id, pool_out_ai.key, &pool_out.asset, stats_out_ai)?;
+        let mut queue_out = Self::load_queue(program_id, pool_out_ai.key, queue_out_ai)?;
+        let mut reserve = Self::load_swap_reserve(program_id, reserve_ai)?;
+        let pools = [reserve.pool_a, reserve.pool_b];
+        if !pools.contains(pool_in_ai.key)
+            || !pools.contains(pool_out_ai.key)
+            || pool_in_ai.key == pool_out_ai.key
+        {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let token = TokenAccounts::next(&pool_in, account_info_iter)?;
+        let relayer_destination_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => relayer_ai,
+        };
+
+        let root_slot = pool_in
+            .tree
+            .root_slot(root)
+            .ok_or(HandCrankedError::UnknownRoot)?;
+        pool_in.params.check_note_age(root_slot, Clock::get()?.slot)?;
+
+        let vault =
+            Self::vault(program_id, pool_in_ai.key, &pool_in, &pool_in.asset, vault_in_ai)?;
+
+        let ext_data = ExtData {
+            recipient: Pubkey::default(),
+            relayer: *relayer_ai.key,
+            fee,
+            encrypted_outputs: vec![encrypted_note.clone()],
+            callback: None,
+        };
+
+        Self::verify_proof_source(
+            program_id,
+            verifier,
+            pool_in_ai.key,
+            proof_system,
+            circuit,
+            proof,
+            proof_ai,
+            key_ai,
+            relayer_ai,
+            &SwapInputs {
+                domain: domain_tag(program_id, pool_in_ai.key, pool_in.params.cluster),
+                root: *root,
+                root_slot,
+                nullifier: *nullifier,
+                amount_in,
+                fee,
+                relayer: *relayer_ai.key,
+                output_domain: domain_tag(program_id, pool_out_ai.key, pool_out.params.cluster),
+                output_commitment,
+                min_amount_out,
+                ext_data_hash: ext_data.hash()?,
+                audit,
+            }
+            .to_field_elements(),
+        )?;
+
+        let amount_out = reserve.swap(pool_in_ai.key, amount_in - fee)?;
+        if amount_out < min_amount_out {
+            return Err(HandCrankedError::SlippageExceeded.into());
+        }
+
+        Self::spend_nullifier(
+            program_id,
+            pool_in_ai.key,
+            nullifier_ai,
+            nullifier,
+            system_program_ai,
+            relayer_ai,
+        )?;
+
+        if fee > 0 {
+            Self::pay_from_vault(
+                &vault,
+                vault_in_ai,
+                relayer_destination_ai,
+                fee,
+                system_program_ai,
+                token.as_ref(),
+            )?;
+        }
+
+        stats_in.stats.record_withdraw(amount_in, 0)?;
+        stats_in.serialize(&mut &mut stats_in_ai.data.borrow_mut()[..])?;
+        stats_out.stats.record_deposit(amount_out)?;
+        stats_out.serialize(&mut &mut stats_out_ai.data.borrow_mut()[..])?;
+
+        let leaf = deposit_leaf(amount_out, &output_commitment)?;
+        let leaf_index = queue_out.push(leaf)?;
+        Self::charge_crank_reward(
+            &pool_out,
+            &mut queue_out,
+            queue_out_ai,
+            relayer_ai,
+            system_program_ai,
+            1,
+        )?;
+        queue_out.serialize(&mut &mut queue_out_ai.data.borrow_mut()[..])?;
+
+        reserve.serialize(&mut &mut reserve_ai.data.borrow_mut()[..])?;
+
+        HandCrankedEvent::Deposit(DepositEvent {
+            pool: *pool_out_ai.key,
+            leaf_index,
+            commitment: leaf,
+            ciphertext: encrypted_note,
+        })
+        .emit()?;
+        if let Some(memo) = auditor_memo {
+            HandCrankedEvent::AuditorMemo(AuditorMemoEvent { leaf_index, memo }).emit()?;
+        }
+        HandCrankedEvent::Swap(SwapEvent {
+            nullifier: *nullifier,
+            pool_in: *pool_in_ai.key,
+            pool_out: *pool_out_ai.key,
+            amount_in,
+            amount_out,
+            fee,
+            leaf_index,
+        })
+        .emit()?;
+
+        Ok(())
+    }
+
+    fn process_initialize_reward_program(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        rate: u64,
+        checkpoint_interval: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let reward_pool_ai = next_account_info(account_info_iter)?;
+        let rewards_ai = next_account_info(account_info_iter)?;
+        let authority_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+
+        if !authority_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_ai.owner != program_id || reward_pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        // Checkpoints closer together would let the ring be cycled, and
+        // unclaimed checkpoints evicted, in little time.
+        if pool_ai.key == reward_pool_ai.key
+            || rate == 0
+            || checkpoint_interval < RewardProgram::MIN_CHECKPOINT_INTERVAL
+        {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let reward_pool = PoolState::unpack(&reward_pool_ai.data.borrow())?;
+        if pool.authority != *authority_ai.key {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+        // Amounts of different assets earn at different real rates, and
+        // reward notes need a single vault.
+        if pool.is_multi_asset() || reward_pool.is_multi_asset() {
+            return Err(HandCrankedError::UnsupportedAsset.into());
+        }
+        if reward_pool.denomination.is_some() {
+            return Err(HandCrankedError::InvalidDenomination.into());
+        }
+
+        let (expected_pda, bump) =
+            Pubkey::find_program_address(&[REWARD_SEED, pool_ai.key.as_ref()], program_id);
+        if expected_pda != *rewards_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        create_pda_account(
+            authority_ai,
+            rewards_ai,
+            RewardProgram::LEN,
+            program_id,
+            system_program_ai,
+            &[REWARD_SEED, pool_ai.key.as_ref(), &[bump]],
+        )?;
+
+        RewardProgram::new(
+            *authority_ai.key,
+            *pool_ai.key,
+            *reward_pool_ai.key,
+            rate,
+            checkpoint_interval,
+        )
+        .serialize(&mut &mut rewards_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Add to the reward budget of a reward program.
+    fn process_fund_rewards(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let rewards_ai = next_account_info(account_info_iter)?;
+        let reward_pool_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let funder_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
+
+        if !funder_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        let mut rewards = Self::load_reward_program(program_id, rewards_ai)?;
+        if rewards.reward_pool != *reward_pool_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let reward_pool = PoolState::unpack(&reward_pool_ai.data.borrow())?;
+        let token = TokenAccounts::next(&reward_pool, account_info_iter)?;
+        let source_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => funder_ai,
+        };
+
+        Self::vault(
+            program_id,
+            reward_pool_ai.key,
+            &reward_pool,
+            &reward_pool.asset,
+            vault_ai,
+        )?;
+        let received = Self::fund_vault(
+            vault_ai,
+            source_ai,
+            funder_ai,
+            amount,
+            system_program_ai,
+            token.as_ref(),
+        )?;
+        rewards.budget = rewards
+            .budget
+            .checked_add(received)
+            .ok_or(HandCrankedError::InvalidInstruction)?;
+        rewards.serialize(&mut &mut rewards_ai.data.borrow_mut()[..])?;
+
+        // Claims issue the budget as notes of hidden value, so it is owed
+        // as shielded value as soon as it is in the vault.
+        let mut stats =
+            Self::load_stats(program_id, reward_pool_ai.key, &reward_pool.asset, stats_ai)?;
+        stats.stats.record_deposit(received)?;
+        stats.serialize(&mut &mut stats_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Checkpoint the pool's current root for reward claims.
+    fn process_record_checkpoint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let rewards_ai = next_account_info(account_info_iter)?;
+        let stats_ai = next_account_info(account_info_iter)?;
+
+        if pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let mut rewards = Self::load_reward_program(program_id, rewards_ai)?;
+        if rewards.pool != *pool_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let stats = Self::load_stats(program_id, pool_ai.key, &pool.asset, stats_ai)?;
+        let number = rewards.push_checkpoint(
+            pool.tree.root(),
+            Clock::get()?.slot,
+            stats.stats.total_shielded,
+        )?;
+        msg!("checkpoint {}", number);
+        rewards.serialize(&mut &mut rewards_ai.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn load_reward_program(
+        program_id: &Pubkey,
+        rewards_ai: &AccountInfo,
+    ) -> Result<RewardProgram, ProgramError> {
+        if rewards_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let rewards = RewardProgram::try_from_slice(&rewards_ai.data.borrow())
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !rewards.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let (expected_pda, _) =
+            Pubkey::find_program_address(&[REWARD_SEED, rewards.pool.as_ref()], program_id);
+        if expected_pda != *rewards_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(rewards)
+    }
+
+    /// Reward a note that was in the tree at a checkpoint, and is still
+    /// unspent: the note is spent for a note of the same value in the pool
+    /// and a note of the reward pool.
+    ///
+    /// The reward leaf is a commitment whose value the proof fixes from
+    /// the note amount, the rewarded slots and the rate; it was reserved
+    /// out of the budget by the checkpoints, so only the fee shows up in
+    /// the reward pool's value tracking.
+    #[allow(clippy::too_many_arguments)]
+    fn process_claim_reward(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        verifier: &dyn ProofVerifier,
+        proof_system: ProofSystem,
+        proof: ProofSource,
+        checkpoints: [u64; 2],
+        nullifier: &[u8; 32],
+        fee: u64,
+        commitments: [[u8; 32]; 2],
+        encrypted_notes: [Vec<u8>; 2],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_ai = next_account_info(account_info_iter)?;
+        let queue_ai = next_account_info(account_info_iter)?;
+        let nullifier_ai = next_account_info(account_info_iter)?;
+        let rewards_ai = next_account_info(account_info_iter)?;
+        let reward_pool_ai = next_account_info(account_info_iter)?;
+        let reward_queue_ai = next_account_info(account_info_iter)?;
+        let vault_ai = next_account_info(account_info_iter)?;
+        let relayer_ai = next_account_info(account_info_iter)?;
+        let system_program_ai = next_account_info(account_info_iter)?;
+        let reward_stats_ai = next_account_info(account_info_iter)?;
+        let proof_ai = match proof {
+            ProofSource::Inline(_) => None,
+            ProofSource::Buffer | ProofSource::Session => {
+                Some(next_account_info(account_info_iter)?)
+            }
+        };
+        let key_ai = match proof {
+            ProofSource::Session => None,
+            _ => Some(next_account_info(account_info_iter)?),
+        };
+
+        if !relayer_ai.is_signer {
+            return Err(HandCrankedError::Unauthorized.into());
+        }
+
+        if pool_ai.owner != program_id || reward_pool_ai.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let rewards = Self::load_reward_program(program_id, rewards_ai)?;
+        if rewards.pool != *pool_ai.key || rewards.reward_pool != *reward_pool_ai.key {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+
+        let pool = PoolState::unpack(&pool_ai.data.borrow())?;
+        let mut queue = Self::load_queue(program_id, pool_ai.key, queue_ai)?;
+        let reward_pool = PoolState::unpack(&reward_pool_ai.data.borrow())?;
+        let mut reward_queue = Self::load_queue(program_id, reward_pool_ai.key, reward_queue_ai)?;
+
+        let token = TokenAccounts::next(&reward_pool, account_info_iter)?;
+        let relayer_destination_ai = match token {
+            Some(_) => next_account_info(account_info_iter)?,
+            None => relayer_ai,
+        };
+
+        let [start, end] = checkpoints.map(|number| rewards.checkpoint(number));
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if checkpoints[0] < checkpoints[1] => (start, end),
+            (Some(_), Some(_)) => return Err(HandCrankedError::InvalidInstruction.into()),
+            _ => return Err(HandCrankedError::UnknownCheckpoint.into()),
+        };
+        pool.params.check_note_age(start.slot, Clock::get()?.slot)?;
+
+        let vault = Self::vault(
+            program_id,
+            reward_pool_ai.key,
+            &reward_pool,
+            &reward_pool.asset,
+            vault_ai,
+        )?;
+
+        let [new_commitment, reward_commitment] = commitments;
+        let [encrypted_note, encrypted_reward_note] = encrypted_notes;
+        let ext_data = ExtData {
+            recipient: Pubkey::default(),
+            relayer: *relayer_ai.key,
+            fee,
+            encrypted_outputs: vec![encrypted_note.clone(), encrypted_reward_note.clone()],
+            callback: None,
+        };
+
+        Self::verify_proof_source(
+            program_id,
+            verifier,
+            pool_ai.key,
+            proof_system,
+            Circuit::Reward,
+            proof,
+            proof_ai,
+            key_ai,
+            relayer_ai,
+            &RewardInputs {
+                domain: domain_tag(program_id, pool_ai.key, pool.params.cluster),
+                start_root: start.root,
+                start_slot: start.rewarded_slots,
+                end_slot: end.rewarded_slots,
+                rate: rewards.rate,
+                nullifier: *nullifier,
+                new_commitment,
+                reward_domain: domain_tag(
+                    program_id,
+                    reward_pool_ai.key,
+                    reward_pool.params.cluster,
+                ),
+                reward_commitment,
+                fee,
+                relayer: *relayer_ai.key,
+                ext_data_hash: ext_data.hash()?,
+            }
+            .to_field_elements(),
+        )?;
+
+        // Spending the note now means it was unspent from `start` on: the
+        // note only earns while it sits in the pool.
+        Self::spend_nullifier(
+            program_id,
+            pool_ai.key,
+            nullifier_ai,
+            nullifier,
+            system_program_ai,
+            relayer_ai,
+        )?;
+
+        Self::emit_new_commitment(pool_ai.key, &mut queue, &new_commitment, encrypted_note)?;
+        Self::charge_crank_reward(&pool, &mut queue, queue_ai, relayer_ai, system_program_ai, 1)?;
+        queue.serialize(&mut &mut queue_ai.data.borrow_mut()[..])?;
+
+        let leaf_index = reward_queue.next_leaf_index;
+        Self::emit_new_commitment(
+            reward_pool_ai.key,
+            &mut reward_queue,
+            &reward_commitment,
+            encrypted_reward_note,
+        )?;
+        Self::charge_crank_reward(
+            &reward_pool,
+            &mut reward_queue,
+            reward_queue_ai,
+            relayer_ai,
+            system_program_ai,
+            1,
+        )?;
+        reward_queue.serialize(&mut &mut reward_queue_ai.data.borrow_mut()[..])?;
+
+        if fee > 0 {
+            let mut reward_stats = Self::load_stats(
+                program_id,
+                reward_pool_ai.key,
+                &reward_pool.asset,
+                reward_stats_ai,
+            )?;
+            reward_stats.stats.record_transfer(fee)?;
+            reward_stats.serialize(&mut &mut reward_stats_ai.data.borrow_mut()[..])?;
+
+            Self::pay_from_vault(
+                &vault,
+                vault_ai,
+                relayer_destination_ai,
+                fee,
+                system_program_ai,
+                token.as_ref(),
+            )?;
+        }
 
-        msg!("Withdraw executed (dummy; hook SPL transfer here)");
+        HandCrankedEvent::RewardClaimed(RewardClaimedEvent {
+            reward_pool: *reward_pool_ai.key,
+            start_checkpoint: checkpoints[0],
+            end_checkpoint: checkpoints[1],
+            fee,
+            leaf_index,
+        })
+        .emit()?;
 
-        let _ = system_program_ai;
         Ok(())
     }
 }
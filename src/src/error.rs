@@ -29,6 +29,72 @@ pub enum HandCrankedError {
 
     #[error("Unsupported proof system")]
     UnsupportedProofSystem,
+
+    #[error("Commitment is not a valid field element")]
+    InvalidCommitment,
+
+    #[error("Merkle tree is full")]
+    TreeFull,
+
+    #[error("Proof does not match the confidential balance")]
+    BalanceMismatch,
+
+    #[error("Unknown Merkle root")]
+    UnknownRoot,
+
+    #[error("Pool asset is not supported")]
+    UnsupportedAsset,
+
+    #[error("Amount does not match the pool denomination")]
+    InvalidDenomination,
+
+    #[error("Deposit queue is full")]
+    QueueFull,
+
+    #[error("Batch does not match the queued leaves")]
+    InvalidBatch,
+
+    #[error("Withdrawal release slot has not passed")]
+    WithdrawalNotReleased,
+
+    #[error("Vault holds less than the pool owes")]
+    InvariantViolated,
+
+    #[error("Association set does not belong to this pool")]
+    InvalidAssociationSet,
+
+    #[error("Verification session has timed out")]
+    SessionExpired,
+
+    #[error("Swap reserve cannot cover the trade")]
+    InsufficientLiquidity,
+
+    #[error("Swap output is below the minimum amount")]
+    SlippageExceeded,
+
+    #[error("Checkpoint interval has not passed")]
+    CheckpointTooSoon,
+
+    #[error("Root is not a known checkpoint")]
+    UnknownCheckpoint,
+
+    #[error("Reward budget cannot cover the claim")]
+    RewardBudgetExceeded,
+
+    #[error("Note is younger than the pool's minimum note age")]
+    NoteTooYoung,
+
+    #[error("Public input is not a canonical field element")]
+    NonCanonicalInput,
+
+    #[error("Account layout version is not supported by this program")]
+    UnsupportedVersion,
+
+    #[error("Verifying key is finalized and cannot be changed")]
+    VerifyingKeyFinalized,
+
+    #[error("Verifying key is not finalized")]
+    VerifyingKeyNotFinalized,
 }
 
 impl From<HandCrankedError> for ProgramError {
@@ -1,19 +1,645 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::pubkey::Pubkey;
+use solana_program::{hash::hashv, program_error::ProgramError, pubkey::Pubkey};
 
-/// Global state account (single instance, PDA).
+use crate::{
+    error::HandCrankedError,
+    merkle::{IncrementalMerkleTree, MAX_NOTE_AGE, ROOT_HISTORY_SIZE, TREE_DEPTH},
+    instruction::ProofSystem,
+    zk::{
+        public_inputs::AuditorKey,
+        session::{SessionStep, PREPARED_INPUTS_LEN},
+        Circuit,
+    },
+};
+
+/// Cluster a pool is deployed on; part of every proof's domain tag so a
+/// proof made for one deployment cannot be replayed on another.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cluster {
+    Mainnet = 0,
+    Devnet = 1,
+    Testnet = 2,
+    Localnet = 3,
+}
+
+/// `PoolState::asset` value for pools holding native SOL.
+pub const NATIVE_ASSET: Pubkey = Pubkey::new_from_array([0u8; 32]);
+
+/// `PoolState::asset` value for pools holding any asset: notes commit to
+/// their asset id and each asset has its own vault
+/// (`[b"vault", pool, mint]`, with `NATIVE_ASSET` as the mint for SOL).
+pub const MULTI_ASSET: Pubkey = Pubkey::new_from_array([0xffu8; 32]);
+
+/// Pool parameters fixed at `InitializePool`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PoolParams {
+    pub cluster: Cluster,
+    /// Lamports paid to the `Crank` caller per leaf moved into the tree.
+    /// Charged to whoever queues the leaf.
+    pub crank_reward: u64,
+    /// Window, in slots after the request, from which the release slot of
+    /// a `RequestWithdraw` is drawn.
+    pub min_withdraw_delay: u64,
+    pub max_withdraw_delay: u64,
+    /// Key allowed to publish association set roots (`Pubkey::default()`
+    /// disables association sets).
+    pub curator: Pubkey,
+    /// When set, every private transfer and every swap into or out of the
+    /// pool must carry an `AuditorMemo` encrypted to this key, enforced by
+    /// `Circuit::AuditedJoinSplit` and `Circuit::AuditedSwap`.
+    pub auditor: Option<AuditorKey>,
+    /// When set, withdrawals, swaps and reward claims must prove
+    /// membership under a root that became current at least this many
+    /// slots ago, so a deposit cannot leave right after it lands. At most
+    /// `MAX_NOTE_AGE`, the age the tree's checkpoints can serve.
+    pub min_note_age: Option<u64>,
+}
+
+impl PoolParams {
+    /// Maximum serialized size (`auditor` and `min_note_age` set).
+    pub const LEN: usize = 1 + 8 + 8 + 8 + 32 + (1 + 64) + (1 + 8);
+
+    /// Longest allowed `max_withdraw_delay`, about a week of slots.
+    pub const MAX_WITHDRAW_DELAY: u64 = 1_512_000;
+
+    /// Reject a withdraw window that is inverted or longer than
+    /// `MAX_WITHDRAW_DELAY`.
+    pub fn check_withdraw_window(&self) -> Result<(), ProgramError> {
+        if self.min_withdraw_delay > self.max_withdraw_delay
+            || self.max_withdraw_delay > Self::MAX_WITHDRAW_DELAY
+        {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+        Ok(())
+    }
+
+    /// Reject a `min_note_age` older than the tree keeps roots for.
+    pub fn check_min_note_age(&self) -> Result<(), ProgramError> {
+        match self.min_note_age {
+            Some(min_note_age) if min_note_age > MAX_NOTE_AGE => {
+                Err(HandCrankedError::InvalidInstruction.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject, at `slot`, a proof against a root that became current at
+    /// `root_slot` if the root is younger than `min_note_age`.
+    pub fn check_note_age(&self, root_slot: u64, slot: u64) -> Result<(), ProgramError> {
+        match self.min_note_age {
+            Some(min_note_age) if slot < root_slot.saturating_add(min_note_age) => {
+                Err(HandCrankedError::NoteTooYoung.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Release slot for a delayed withdrawal requested at `slot`.
+    ///
+    /// Spread over the window by hashing the nullifier with the request
+    /// slot. This is not unchoosable: a requester can wait for a
+    /// submission slot whose hash lands where they want, so any release
+    /// slot in the window is reachable. The guarantee is only that every
+    /// release is between `min_withdraw_delay` and `max_withdraw_delay`
+    /// slots after its request.
+    pub fn release_slot(&self, nullifier: &[u8; 32], slot: u64) -> Result<u64, ProgramError> {
+        self.check_withdraw_window()?;
+        let span = self.max_withdraw_delay - self.min_withdraw_delay + 1;
+        let hash = hashv(&[nullifier, &slot.to_le_bytes()]).to_bytes();
+        let mut offset = [0u8; 8];
+        offset.copy_from_slice(&hash[..8]);
+        slot.checked_add(self.min_withdraw_delay + u64::from_le_bytes(offset) % span)
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+}
+
+/// A shielded pool (PDA `[b"pool", pool_id]`).
+///
+/// Each pool has its own tree, vault (`[b"vault", pool]`, or one per
+/// asset in a multi-asset pool) and nullifier
+/// namespace (`[b"nullifier", pool, nullifier]`); notes never move between
+/// pools.
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
-pub struct GlobalState {
+pub struct PoolState {
     pub is_initialized: bool,
     pub version: u8,
+    pub pool_id: u64,
+    pub authority: Pubkey,
+    /// Asset held by the pool: `NATIVE_ASSET` for SOL, `MULTI_ASSET` for
+    /// a pool whose notes carry their own asset, otherwise an `spl-token`
+    /// or Token-2022 mint.
+    pub asset: Pubkey,
+    /// Fixed deposit/withdraw amount, or `None` for arbitrary amounts.
+    ///
+    /// Fixed-denomination pools only accept `Deposit` and `Withdraw` of
+    /// exactly this amount and do not support `PrivateTransfer`, so every
+    /// note in the pool is indistinguishable by value.
+    pub denomination: Option<u64>,
+    pub params: PoolParams,
+    /// Note commitment tree; `tree.next_index` is the next leaf index.
+    pub tree: IncrementalMerkleTree,
+}
+
+/// Leaves waiting to be cranked into a pool's tree (PDA `[b"queue", pool]`).
+///
+/// `Deposit` and `PrivateTransfer` only append here, so they never write
+/// the pool account; `Crank` drains the queue in order. Leaf indices are
+/// assigned at enqueue time and stay valid because the queue is FIFO and
+/// is the only way leaves enter the tree.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct DepositQueue {
+    pub is_initialized: bool,
+    pub pool: Pubkey,
+    /// Tree index the next enqueued leaf will get.
+    pub next_leaf_index: u64,
+    /// Lamports held by this account for crank rewards (above rent).
+    pub fee_balance: u64,
+    pub pending: Vec<[u8; 32]>,
+}
+
+/// Value and activity counters of one pool vault (PDA
+/// `[b"stats", pool, asset]`, `asset` being the mint or `NATIVE_ASSET`).
+///
+/// A separate account per vault, so every asset of a multi-asset pool is
+/// held against its own vault balance, and the pool and queue layouts stay
+/// as they were.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VaultStats {
+    pub is_initialized: bool,
+    pub pool: Pubkey,
+    pub asset: Pubkey,
+    pub stats: PoolStats,
+}
+
+/// Value and activity counters of a vault.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct PoolStats {
+    /// Value of all unspent notes (queued or in the tree).
+    pub total_shielded: u64,
+    /// Value owed to pending delayed withdrawals.
+    pub total_pending: u64,
+    pub deposit_count: u64,
+    pub transfer_count: u64,
+    pub withdraw_count: u64,
+}
+
+impl PoolStats {
+    pub const LEN: usize = 8 * 5;
+
+    /// What the vault must hold at least.
+    pub fn liability(&self) -> u64 {
+        self.total_shielded.saturating_add(self.total_pending)
+    }
+
+    /// `amount` was shielded.
+    pub fn record_deposit(&mut self, amount: u64) -> Result<(), ProgramError> {
+        self.total_shielded = self
+            .total_shielded
+            .checked_add(amount)
+            .ok_or(HandCrankedError::InvariantViolated)?;
+        self.deposit_count += 1;
+        Ok(())
+    }
+
+    /// A private transfer paid `fee` out of the vault.
+    pub fn record_transfer(&mut self, fee: u64) -> Result<(), ProgramError> {
+        self.total_shielded = self
+            .total_shielded
+            .checked_sub(fee)
+            .ok_or(HandCrankedError::InvariantViolated)?;
+        self.transfer_count += 1;
+        Ok(())
+    }
+
+    /// A note of `amount` was withdrawn, `pending` of it to be paid later.
+    pub fn record_withdraw(&mut self, amount: u64, pending: u64) -> Result<(), ProgramError> {
+        self.total_shielded = self
+            .total_shielded
+            .checked_sub(amount)
+            .ok_or(HandCrankedError::InvariantViolated)?;
+        self.total_pending = self
+            .total_pending
+            .checked_add(pending)
+            .ok_or(HandCrankedError::InvariantViolated)?;
+        self.withdraw_count += 1;
+        Ok(())
+    }
+
+    /// A pending withdrawal of `amount` was paid out.
+    pub fn record_payout(&mut self, amount: u64) -> Result<(), ProgramError> {
+        self.total_pending = self
+            .total_pending
+            .checked_sub(amount)
+            .ok_or(HandCrankedError::InvariantViolated)?;
+        Ok(())
+    }
+}
+
+/// Payout waiting for `ExecuteWithdraw` (PDA `[b"pending", pool, nullifier]`).
+///
+/// Holds only what the payout needs; the fee and relayer were settled
+/// when the withdrawal was requested.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PendingWithdrawal {
+    pub is_initialized: bool,
+    pub pool: Pubkey,
+    pub recipient: Pubkey,
+    /// Amount owed to the recipient (fee already deducted).
+    pub amount: u64,
+    pub release_slot: u64,
+    /// Asset owed: the pool asset, or the note's asset in a multi-asset
+    /// pool.
+    pub asset: Pubkey,
+}
+
+impl PendingWithdrawal {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 32;
+}
+
+/// Constant-product liquidity for swaps between two pools
+/// (PDA `[b"reserve", pool_a, pool_b]`).
+///
+/// The liquidity sits in the two pool vaults next to the shielded funds;
+/// the reserve only records how much of each vault belongs to it. A swap
+/// moves value from one pool's liability into `reserve_a`/`reserve_b` and
+/// out of the other reserve into a new note, so no tokens move.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SwapReserve {
+    pub is_initialized: bool,
+    /// May add and remove liquidity; authority of both pools.
+    pub authority: Pubkey,
+    pub pool_a: Pubkey,
+    pub pool_b: Pubkey,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    /// Swap fee in basis points of the input, left in the reserve.
+    pub fee_bps: u16,
+}
+
+impl SwapReserve {
+    pub const LEN: usize = 1 + 32 + 32 + 32 + 8 + 8 + 2;
+    pub const MAX_FEE_BPS: u16 = 10_000;
+
+    /// Swap `amount_in` of `pool_in` for the other pool's asset, updating
+    /// both reserves, and return the output amount.
+    pub fn swap(&mut self, pool_in: &Pubkey, amount_in: u64) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = if *pool_in == self.pool_a {
+            (&mut self.reserve_a, &mut self.reserve_b)
+        } else if *pool_in == self.pool_b {
+            (&mut self.reserve_b, &mut self.reserve_a)
+        } else {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        };
+
+        let after_fee = amount_in as u128 * (Self::MAX_FEE_BPS - self.fee_bps) as u128
+            / Self::MAX_FEE_BPS as u128;
+        let denominator = *reserve_in as u128 + after_fee;
+        if denominator == 0 {
+            return Err(HandCrankedError::InsufficientLiquidity.into());
+        }
+        // Below u64::MAX as it is less than `reserve_out`.
+        let amount_out = (*reserve_out as u128 * after_fee / denominator) as u64;
+        if amount_out == 0 {
+            return Err(HandCrankedError::InsufficientLiquidity.into());
+        }
+
+        *reserve_in = reserve_in
+            .checked_add(amount_in)
+            .ok_or(HandCrankedError::InsufficientLiquidity)?;
+        *reserve_out -= amount_out;
+        Ok(amount_out)
+    }
+}
+
+/// A pool root recorded by `RecordCheckpoint`, the slot it was recorded
+/// at and the pool's unspent value at that slot.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct RootCheckpoint {
+    pub root: [u8; 32],
+    pub slot: u64,
+    pub value: u64,
+    /// Slots rewarded from the first checkpoint up to this one: the slots
+    /// in between, less those the budget could not cover.
+    pub rewarded_slots: u64,
+}
+
+/// Anonymity mining for a pool (PDA `[b"rewards", pool]`).
+///
+/// Holders of a note that was in the tree at a checkpoint, and is still
+/// unspent, can spend it for a note of the same value and a shielded note
+/// of `reward_pool` worth
+/// `amount * slots * rate / RATE_SCALE`, where `slots` are the rewarded
+/// slots from that checkpoint to a later one. Claim amounts are never
+/// revealed, so each checkpoint instead sets aside from `budget` what every
+/// unspent note of the pool could claim for the span it closes; a span the
+/// budget cannot cover in full is only rewarded for the slots it can.
+/// Reserve a note's owner never claims stays in the reward vault.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RewardProgram {
+    pub is_initialized: bool,
     pub authority: Pubkey,
+    pub pool: Pubkey,
+    /// Pool reward notes are issued in.
+    pub reward_pool: Pubkey,
+    /// Reward per unit of note amount per slot, scaled by `RATE_SCALE`.
+    pub rate: u64,
+    /// Funded reward not yet reserved by a checkpoint, held in the reward
+    /// pool vault.
+    pub budget: u64,
+    /// Fewest slots between two checkpoints, at least
+    /// `MIN_CHECKPOINT_INTERVAL`.
+    pub checkpoint_interval: u64,
+    /// Ring of the last `REWARD_CHECKPOINTS` checkpoints; checkpoint `n`
+    /// is at `n % REWARD_CHECKPOINTS`.
+    pub checkpoints: [RootCheckpoint; REWARD_CHECKPOINTS],
+    /// Checkpoints recorded so far.
+    pub checkpoint_count: u64,
+}
+
+/// Checkpoints a `RewardProgram` remembers. With checkpoints at least
+/// `MIN_CHECKPOINT_INTERVAL` apart, a checkpoint stays claimable for at
+/// least a week however often `RecordCheckpoint` is called.
+pub const REWARD_CHECKPOINTS: usize = 168;
+
+impl RewardProgram {
+    pub const LEN: usize =
+        1 + 32 + 32 + 32 + 8 + 8 + 8 + (32 + 8 + 8 + 8) * REWARD_CHECKPOINTS + 8;
+    pub const RATE_SCALE: u128 = 1_000_000_000;
+    /// About an hour of slots.
+    pub const MIN_CHECKPOINT_INTERVAL: u64 = 9_000;
+
+    pub fn new(
+        authority: Pubkey,
+        pool: Pubkey,
+        reward_pool: Pubkey,
+        rate: u64,
+        checkpoint_interval: u64,
+    ) -> Self {
+        Self {
+            is_initialized: true,
+            authority,
+            pool,
+            reward_pool,
+            rate,
+            budget: 0,
+            checkpoint_interval,
+            checkpoints: [RootCheckpoint::default(); REWARD_CHECKPOINTS],
+            checkpoint_count: 0,
+        }
+    }
+
+    /// Record `root` as current at `slot` with the pool holding `value` in
+    /// unspent notes, evicting the oldest checkpoint, and return its
+    /// number. The reward of the span since the previous checkpoint is
+    /// reserved out of `budget`; if the budget cannot cover the whole span,
+    /// only as many slots as it covers are rewarded.
+    pub fn push_checkpoint(
+        &mut self,
+        root: [u8; 32],
+        slot: u64,
+        value: u64,
+    ) -> Result<u64, ProgramError> {
+        let mut rewarded_slots = 0;
+        if let Some(last) = self.checkpoint_count.checked_sub(1) {
+            let last = self.checkpoints[(last % REWARD_CHECKPOINTS as u64) as usize];
+            if slot < last.slot.saturating_add(self.checkpoint_interval) {
+                return Err(HandCrankedError::CheckpointTooSoon.into());
+            }
+            let slots = (slot - last.slot).min(self.affordable_slots(last.value));
+            self.budget = self
+                .reserve(last.value, slots)
+                .and_then(|reserve| self.budget.checked_sub(reserve))
+                .ok_or(HandCrankedError::RewardBudgetExceeded)?;
+            rewarded_slots = last
+                .rewarded_slots
+                .checked_add(slots)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        let number = self.checkpoint_count;
+        self.checkpoints[(number % REWARD_CHECKPOINTS as u64) as usize] = RootCheckpoint {
+            root,
+            slot,
+            value,
+            rewarded_slots,
+        };
+        self.checkpoint_count += 1;
+        Ok(number)
+    }
+
+    /// Checkpoint `number`, if it is one of the last `REWARD_CHECKPOINTS`.
+    pub fn checkpoint(&self, number: u64) -> Option<RootCheckpoint> {
+        if number >= self.checkpoint_count
+            || self.checkpoint_count - number > REWARD_CHECKPOINTS as u64
+        {
+            return None;
+        }
+        Some(self.checkpoints[(number % REWARD_CHECKPOINTS as u64) as usize])
+    }
+
+    /// Reward for a note of `amount` held for `slots`; the reward circuit
+    /// enforces the same formula.
+    pub fn reward(&self, amount: u64, slots: u64) -> u64 {
+        (amount as u128 * slots as u128)
+            .checked_mul(self.rate as u128)
+            .map_or(u64::MAX, |reward| {
+                (reward / Self::RATE_SCALE).min(u64::MAX as u128) as u64
+            })
+    }
+
+    /// Most slots the budget can reserve for notes worth `value` in total.
+    pub fn affordable_slots(&self, value: u64) -> u64 {
+        match value as u128 * self.rate as u128 {
+            0 => u64::MAX,
+            per_slot => {
+                let slots = self.budget as u128 * Self::RATE_SCALE / per_slot;
+                slots.min(u64::MAX as u128) as u64
+            }
+        }
+    }
+
+    /// Upper bound on the rewards of notes worth `value` in total held for
+    /// `slots`. Rounds up, so it covers claims that split the span across
+    /// checkpoints differently; `None` if it does not fit a `u64`.
+    pub fn reserve(&self, value: u64, slots: u64) -> Option<u64> {
+        let reserve = (value as u128 * slots as u128)
+            .checked_mul(self.rate as u128)?
+            .div_ceil(Self::RATE_SCALE);
+        u64::try_from(reserve).ok()
+    }
+}
+
+/// Roots of a curator-published association set
+/// (PDA `[b"association", pool, set_id]`).
+///
+/// An association set is a Merkle tree over an approved subset of the
+/// pool's deposits, built off-chain by the curator. Withdrawals may prove
+/// their note is in it without revealing which one.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AssociationSet {
+    pub is_initialized: bool,
+    pub pool: Pubkey,
+    pub set_id: u64,
+    pub roots: [[u8; 32]; ROOT_HISTORY_SIZE],
+    pub current_root_index: u64,
+}
+
+impl AssociationSet {
+    pub const LEN: usize = 1 + 32 + 8 + 32 * ROOT_HISTORY_SIZE + 8;
+
+    pub fn new(pool: Pubkey, set_id: u64) -> Self {
+        Self {
+            is_initialized: true,
+            pool,
+            set_id,
+            roots: [[0u8; 32]; ROOT_HISTORY_SIZE],
+            current_root_index: 0,
+        }
+    }
+
+    /// Record a newly published root, evicting the oldest.
+    pub fn push_root(&mut self, root: [u8; 32]) {
+        self.current_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE as u64;
+        self.roots[self.current_root_index as usize] = root;
+    }
+
+    /// Whether `root` is one of the last `ROOT_HISTORY_SIZE` published roots.
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        root != &[0u8; 32] && self.roots.iter().any(|r| r == root)
+    }
+}
+
+/// Header of a proof staging buffer
+/// (PDA `[b"proof-buffer", authority, buffer_id]`); the proof bytes follow
+/// it in the account data.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ProofBuffer {
+    pub is_initialized: bool,
+    pub authority: Pubkey,
+    pub buffer_id: u64,
+    /// Number of proof bytes after the header.
+    pub len: u32,
+}
+
+impl ProofBuffer {
+    pub const LEN: usize = 1 + 32 + 8 + 4;
+    /// Largest proof a buffer can hold.
+    pub const MAX_PROOF_LEN: u32 = 256 * 1024;
+
+    /// Parse the header only; the account may not have grown to hold the
+    /// whole proof yet.
+    pub fn unpack_header(data: &[u8]) -> Result<Self, ProgramError> {
+        let header = ProofBuffer::try_from_slice(&data[..Self::LEN.min(data.len())])
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !header.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(header)
+    }
+
+    /// Parse the header and return it with the proof bytes.
+    pub fn unpack(data: &[u8]) -> Result<(Self, &[u8]), ProgramError> {
+        let header = Self::unpack_header(data)?;
+        let proof = data
+            .get(Self::LEN..Self::LEN + header.len as usize)
+            .ok_or(HandCrankedError::InvalidAccountData)?;
+        Ok((header, proof))
+    }
+}
+
+/// Header of a verifying key account
+/// (PDA `[b"verifying-key", pool, proof_system, circuit]`); the key bytes
+/// follow it in the account data, in the encoding the proof system's
+/// verifier reads.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VerifyingKeyState {
+    pub is_initialized: bool,
+    pub pool: Pubkey,
+    pub proof_system: ProofSystem,
+    pub circuit: Circuit,
+    /// Number of key bytes after the header.
+    pub len: u32,
+    /// Set once the key is fully written; only finalized keys verify
+    /// proofs and they can no longer be written.
+    pub finalized: bool,
+}
+
+impl VerifyingKeyState {
+    pub const LEN: usize = 1 + 32 + 1 + 1 + 4 + 1;
+    /// Largest key an account can hold; it is created in one go.
+    pub const MAX_KEY_LEN: u32 = 8 * 1024;
+
+    /// Parse the header and return it with the key bytes.
+    pub fn unpack(data: &[u8]) -> Result<(Self, &[u8]), ProgramError> {
+        let header = VerifyingKeyState::try_from_slice(&data[..Self::LEN.min(data.len())])
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !header.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let key = data
+            .get(Self::LEN..Self::LEN + header.len as usize)
+            .ok_or(HandCrankedError::InvalidAccountData)?;
+        Ok((header, key))
+    }
+}
+
+/// A proof being checked over several transactions
+/// (PDA `[b"session", prover, session_id]`).
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VerificationSession {
+    pub is_initialized: bool,
+    /// Relayer that opened the session, paid its rent and may consume it.
+    pub prover: Pubkey,
+    pub pool: Pubkey,
+    pub session_id: u64,
+    pub opened_slot: u64,
+    pub proof_system: ProofSystem,
+    pub circuit: Circuit,
+    pub step: SessionStep,
+    pub prepared_inputs: [u8; PREPARED_INPUTS_LEN],
+    pub public_inputs: Vec<[u8; 32]>,
+    pub proof: Vec<u8>,
+}
+
+impl VerificationSession {
+    /// Slots after opening when anyone may close the session (~1 hour).
+    pub const TIMEOUT_SLOTS: u64 = 9_000;
+    /// Most public inputs any circuit takes.
+    pub const MAX_PUBLIC_INPUTS: usize = 32;
+
+    /// Account size for a session over `proof_len` proof bytes and
+    /// `inputs` public inputs.
+    pub fn len(proof_len: usize, inputs: usize) -> usize {
+        1 + 32
+            + 32
+            + 8
+            + 8
+            + 1
+            + 1
+            + 2
+            + PREPARED_INPUTS_LEN
+            + (4 + 32 * inputs)
+            + (4 + proof_len)
+    }
+
+    pub fn is_expired(&self, slot: u64) -> bool {
+        slot > self.opened_slot.saturating_add(Self::TIMEOUT_SLOTS)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let session = VerificationSession::deserialize(&mut &data[..])
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !session.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(session)
+    }
 }
 
 /// Per-note account: a commitment + nullifier + spent flag.
 ///
 /// This is intentionally generic: commitments are just 32-byte hashes
 /// of whatever you decide off-chain (amount, owner, randomness, etc.).
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Default)]
 pub struct NoteState {
     pub is_initialized: bool,
     pub commitment: [u8; 32],
@@ -21,27 +647,176 @@ pub struct NoteState {
     pub spent: bool,
 }
 
-impl GlobalState {
-    pub const VERSION: u8 = 1;
+/// Marker for a spent nullifier (PDA `[b"nullifier", pool, nullifier]`).
+///
+/// The account existing is what prevents a double spend; it carries no
+/// link to the note that was spent.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NullifierState {
+    pub is_initialized: bool,
+    pub nullifier: [u8; 32],
+}
+
+/// Account-model confidential balance (PDA `[b"confidential", owner]`).
+///
+/// Balances are twisted ElGamal ciphertexts under `elgamal_pubkey`, in the
+/// zk-token-sdk pod encoding. Incoming transfers land in `pending_balance`
+/// so they cannot invalidate proofs the owner built against
+/// `available_balance`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ConfidentialAccount {
+    pub is_initialized: bool,
+    pub owner: Pubkey,
+    pub elgamal_pubkey: [u8; 32],
+    pub available_balance: [u8; 64],
+    pub pending_balance: [u8; 64],
 }
 
-impl Default for GlobalState {
-    fn default() -> Self {
+impl PoolState {
+    pub const VERSION: u8 = 5;
+    /// Maximum serialized size (`denomination` set).
+    pub const LEN: usize =
+        1 + 1 + 8 + 32 + 32 + (1 + 8) + PoolParams::LEN + IncrementalMerkleTree::LEN;
+
+    pub fn new(
+        pool_id: u64,
+        authority: Pubkey,
+        asset: Pubkey,
+        denomination: Option<u64>,
+        params: PoolParams,
+    ) -> Result<Self, ProgramError> {
+        // Notes of different assets cannot share a denomination, and audit
+        // memos do not carry an asset.
+        if asset == MULTI_ASSET && (denomination.is_some() || params.auditor.is_some()) {
+            return Err(HandCrankedError::InvalidInstruction.into());
+        }
+        Ok(Self {
+            is_initialized: true,
+            version: PoolState::VERSION,
+            pool_id,
+            authority,
+            asset,
+            denomination,
+            params,
+            tree: IncrementalMerkleTree::new()?,
+        })
+    }
+
+    pub fn is_multi_asset(&self) -> bool {
+        self.asset == MULTI_ASSET
+    }
+
+    /// Circuit proofs against this pool are made for.
+    pub fn circuit(&self) -> Circuit {
+        match self.denomination {
+            Some(_) => Circuit::Membership,
+            None if self.is_multi_asset() => Circuit::MultiAssetJoinSplit,
+            None => Circuit::JoinSplit,
+        }
+    }
+
+    /// Reject `amount` unless it matches the pool denomination (if any).
+    pub fn check_denomination(&self, amount: u64) -> Result<(), ProgramError> {
+        match self.denomination {
+            Some(denomination) if denomination != amount => {
+                Err(HandCrankedError::InvalidDenomination.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deserialize from account data, which may carry trailing zero bytes
+    /// when `denomination` is `None`.
+    ///
+    /// Accounts written by an older layout are rejected with
+    /// `UnsupportedVersion` rather than read as garbage.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        match data.get(1) {
+            Some(&PoolState::VERSION) => {}
+            Some(_) if data[0] != 0 => return Err(HandCrankedError::UnsupportedVersion.into()),
+            _ => return Err(HandCrankedError::InvalidAccountData.into()),
+        }
+        let pool = PoolState::deserialize(&mut &data[..])
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !pool.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(pool)
+    }
+}
+
+impl DepositQueue {
+    /// Maximum number of leaves waiting at once (and so the largest
+    /// `BatchInsert`).
+    pub const CAPACITY: usize = 256;
+    /// Serialized size with a full queue.
+    pub const LEN: usize = 1 + 32 + 8 + 8 + (4 + 32 * Self::CAPACITY);
+
+    pub fn new(pool: Pubkey) -> Self {
         Self {
-            is_initialized: false,
-            version: GlobalState::VERSION,
-            authority: Pubkey::default(),
+            is_initialized: true,
+            pool,
+            next_leaf_index: 0,
+            fee_balance: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Append a leaf and return the tree index it will be inserted at.
+    pub fn push(&mut self, leaf: [u8; 32]) -> Result<u64, ProgramError> {
+        if self.next_leaf_index >= 1u64 << TREE_DEPTH {
+            return Err(HandCrankedError::TreeFull.into());
+        }
+        if self.pending.len() >= Self::CAPACITY {
+            return Err(HandCrankedError::QueueFull.into());
         }
+        self.pending.push(leaf);
+        let leaf_index = self.next_leaf_index;
+        self.next_leaf_index += 1;
+        Ok(leaf_index)
     }
+
+    /// Deserialize from account data (the queue is usually not full, so the
+    /// account carries trailing bytes).
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let queue = DepositQueue::deserialize(&mut &data[..])
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !queue.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(queue)
+    }
+}
+
+impl VaultStats {
+    pub const LEN: usize = 1 + 32 + 32 + PoolStats::LEN;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let stats = VaultStats::try_from_slice(data)
+            .map_err(|_| HandCrankedError::InvalidAccountData)?;
+        if !stats.is_initialized {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        Ok(stats)
+    }
+}
+
+impl NullifierState {
+    pub const LEN: usize = 1 + 32;
+}
+
+impl ConfidentialAccount {
+    pub const LEN: usize = 1 + 32 + 32 + 64 + 64;
 }
 
-impl Default for NoteState {
+impl Default for ConfidentialAccount {
     fn default() -> Self {
         Self {
             is_initialized: false,
-            commitment: [0u8; 32],
-            nullifier: [0u8; 32],
-            spent: false,
+            owner: Pubkey::default(),
+            elgamal_pubkey: [0u8; 32],
+            available_balance: [0u8; 64],
+            pending_balance: [0u8; 64],
         }
     }
 }
@@ -0,0 +1,214 @@
+//! SPL Token / Token-2022 vault helpers for pools holding a mint.
+//!
+//! Both token programs are driven through the Token-2022 instruction
+//! builders and state parsers, which accept legacy `spl-token` accounts
+//! unchanged.
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{instruction::transfer_checked_with_fee, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    state::{Account, Mint},
+};
+
+use crate::{
+    error::HandCrankedError,
+    state::{PoolState, MULTI_ASSET, NATIVE_ASSET},
+};
+
+/// Mint extensions that would let someone other than the pool move,
+/// block or reinterpret vault funds.
+const REJECTED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::PermanentDelegate,
+    ExtensionType::NonTransferable,
+    ExtensionType::TransferHook,
+    ExtensionType::DefaultAccountState,
+];
+
+/// Mint and token program accounts passed after the fixed accounts of an
+/// instruction when the pool holds a token rather than SOL.
+///
+/// Multi-asset pools always take an asset account there: the system
+/// program for SOL (with no token program after it), otherwise the mint.
+pub struct TokenAccounts<'a, 'info> {
+    pub mint: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TokenAccounts<'a, 'info> {
+    /// Read the mint and token program accounts if `pool` is a token pool,
+    /// or the asset account of a multi-asset pool.
+    pub fn next<I>(pool: &PoolState, iter: &mut I) -> Result<Option<Self>, ProgramError>
+    where
+        I: Iterator<Item = &'a AccountInfo<'info>>,
+    {
+        if pool.asset == NATIVE_ASSET {
+            return Ok(None);
+        }
+
+        let mint = next_account_info(iter)?;
+        if pool.asset == MULTI_ASSET {
+            if *mint.key == NATIVE_ASSET {
+                return Ok(None);
+            }
+        } else if *mint.key != pool.asset {
+            return Err(HandCrankedError::InvalidAccountData.into());
+        }
+        let token_program = next_account_info(iter)?;
+        check_token_program(mint, token_program)?;
+
+        Ok(Some(Self {
+            mint,
+            token_program,
+        }))
+    }
+}
+
+/// Asset moved by an instruction: the mint of `token`, or `NATIVE_ASSET`.
+pub fn asset(token: Option<&TokenAccounts>) -> Pubkey {
+    token.map_or(NATIVE_ASSET, |token| *token.mint.key)
+}
+
+/// The token program must be one of the two SPL token programs and must
+/// own the mint.
+pub fn check_token_program(
+    mint: &AccountInfo,
+    token_program: &AccountInfo,
+) -> Result<(), ProgramError> {
+    if *token_program.key != spl_token::id() && *token_program.key != spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if mint.owner != token_program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Reject mints whose extensions break pool invariants.
+///
+/// Transfer fees are fine (deposits credit what the vault actually
+/// received, payouts report what the recipient nets) and so is the confidential-transfer extension (the vault only
+/// ever holds a public balance).
+pub fn validate_mint(mint: &AccountInfo) -> Result<(), ProgramError> {
+    let data = mint.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data)?;
+    let extensions = state.get_extension_types()?;
+    if extensions
+        .iter()
+        .any(|extension| REJECTED_MINT_EXTENSIONS.contains(extension))
+    {
+        return Err(HandCrankedError::UnsupportedAsset.into());
+    }
+    Ok(())
+}
+
+/// Size of a vault token account for `mint`, including any account
+/// extensions the mint requires (e.g. `TransferFeeAmount`).
+pub fn vault_len(mint: &AccountInfo) -> Result<usize, ProgramError> {
+    let data = mint.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data)?;
+    let required =
+        ExtensionType::get_required_init_account_extensions(&state.get_extension_types()?);
+    ExtensionType::try_calculate_account_len::<Account>(&required)
+}
+
+/// Token balance of a token account.
+pub fn balance(account: &AccountInfo) -> Result<u64, ProgramError> {
+    let data = account.data.borrow();
+    Ok(StateWithExtensions::<Account>::unpack(&data)?.base.amount)
+}
+
+/// Initialize `vault` as a token account that is its own authority, so
+/// the program can move funds by signing with the vault seeds.
+pub fn initialize_vault<'info>(
+    token: &TokenAccounts<'_, 'info>,
+    vault: &AccountInfo<'info>,
+) -> Result<(), ProgramError> {
+    let ix = spl_token_2022::instruction::initialize_account3(
+        token.token_program.key,
+        vault.key,
+        token.mint.key,
+        vault.key,
+    )?;
+    invoke(
+        &ix,
+        &[
+            vault.clone(),
+            token.mint.clone(),
+            token.token_program.clone(),
+        ],
+    )
+}
+
+/// `transfer_checked` of `amount` from `source` to `destination`.
+///
+/// For mints with a Token-2022 transfer fee the fee for the current epoch
+/// is computed and passed to `transfer_checked_with_fee`, so the transfer
+/// fails rather than withhold a different amount. Returns what
+/// `destination` receives: `amount` less the withheld fee.
+pub fn transfer<'info>(
+    token: &TokenAccounts<'_, 'info>,
+    source: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64, ProgramError> {
+    let (decimals, fee) = {
+        let data = token.mint.data.borrow();
+        let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+        let fee = match mint.get_extension::<TransferFeeConfig>() {
+            Ok(config) => Some(
+                config
+                    .calculate_epoch_fee(Clock::get()?.epoch, amount)
+                    .ok_or(ProgramError::ArithmeticOverflow)?,
+            ),
+            Err(_) => None,
+        };
+        (mint.base.decimals, fee)
+    };
+    let ix = match fee {
+        Some(fee) => transfer_checked_with_fee(
+            token.token_program.key,
+            source.key,
+            token.mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+            fee,
+        )?,
+        None => spl_token_2022::instruction::transfer_checked(
+            token.token_program.key,
+            source.key,
+            token.mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            source.clone(),
+            token.mint.clone(),
+            destination.clone(),
+            authority.clone(),
+            token.token_program.clone(),
+        ],
+        signer_seeds,
+    )?;
+    Ok(amount - fee.unwrap_or(0))
+}
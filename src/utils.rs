@@ -1,10 +1,16 @@
 use solana_program::{
     account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
+    pubkey::Pubkey,
     rent::Rent,
+    system_instruction, system_program,
     sysvar::Sysvar,
 };
 
+use crate::error::HandCrankedError;
+
 /// Ensure an account is rent exempt.
 pub fn assert_rent_exempt(
     account_info: &AccountInfo,
@@ -16,3 +22,55 @@ pub fn assert_rent_exempt(
     }
     Ok(())
 }
+
+/// Create the PDA `account` (signed with `seeds`) with `space` bytes owned
+/// by `owner`, paid by `payer`.
+///
+/// `create_account` fails on an address that already holds lamports, and
+/// anyone can send lamports to a PDA they can predict. An address that is
+/// still an empty system account is therefore topped up to rent exemption
+/// and allocated and assigned in place instead.
+pub fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    system_program_ai: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let required = Rent::get()?.minimum_balance(space);
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                required,
+                space as u64,
+                owner,
+            ),
+            &[payer.clone(), account.clone(), system_program_ai.clone()],
+            &[seeds],
+        );
+    }
+
+    if *account.owner != system_program::id() || !account.data_is_empty() {
+        return Err(HandCrankedError::AlreadyInitialized.into());
+    }
+    let top_up = required.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program_ai.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program_ai.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account.clone(), system_program_ai.clone()],
+        &[seeds],
+    )
+}
@@ -1,69 +1,232 @@
 #![cfg(feature = "zk-groth16")]
 
-use ark_bn254::Bn254;
-use ark_groth16::{verify_proof, PreparedVerifyingKey, Proof};
+//! Groth16 verification over BN254 with the `alt_bn128` syscalls, so the
+//! check fits the compute budget on-chain. Off-chain, `solana-program`
+//! implements the same syscalls natively.
+//!
+//! Points use the syscalls' uncompressed big-endian encoding: G1 is
+//! `x || y`, G2 is `x.c1 || x.c0 || y.c1 || y.c0`, and all zero bytes is
+//! the point at infinity.
+
+use ark_bn254::{Bn254, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::VerifyingKey;
+#[cfg(feature = "test-utils")]
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, fields::fp::FpVar};
+#[cfg(feature = "test-utils")]
 use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
-use ark_r1cs_std::prelude::*;
-use ark_std::vec::Vec;
-use solana_program::program_error::ProgramError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    alt_bn128::{
+        compression::prelude::{
+            alt_bn128_g1_compress, alt_bn128_g1_decompress, alt_bn128_g2_compress,
+            alt_bn128_g2_decompress,
+        },
+        prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing},
+    },
+    program_error::ProgramError,
+};
+
+use crate::{
+    error::HandCrankedError,
+    zk::{groth16_encoding::GROTH16_PROOF_LEN, session::PREPARED_INPUTS_LEN},
+};
+
+pub const G1_LEN: usize = 64;
+pub const G2_LEN: usize = 128;
 
-use crate::error::HandCrankedError;
+/// BN254 base field modulus, big-endian.
+const BASE_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d, 0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
 
-pub struct PrivateTransferCircuit<F: ark_ff::Field> {
+/// Output of `alt_bn128_pairing` when the product of the pairings is one.
+const PAIRING_ONE: [u8; 32] = {
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    one
+};
+
+/// Toy circuit with two public inputs constrained to be equal, for
+/// producing real proofs in tests.
+#[cfg(feature = "test-utils")]
+pub struct PrivateTransferCircuit<F: PrimeField> {
     pub commitment: Option<F>,
     pub nullifier: Option<F>,
 }
 
-impl<F: ark_ff::Field> ConstraintSynthesizer<F> for PrivateTransferCircuit<F> {
+#[cfg(feature = "test-utils")]
+impl<F: PrimeField> ConstraintSynthesizer<F> for PrivateTransferCircuit<F> {
     fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
-        // Allocate public inputs
-        let commitment_var =
-            FpVar::<F>::new_input(cs.clone(), || self.commitment.ok_or(SynthesisError::AssignmentMissing))?;
-        let nullifier_var =
-            FpVar::<F>::new_input(cs.clone(), || self.nullifier.ok_or(SynthesisError::AssignmentMissing))?;
+        let missing = || SynthesisError::AssignmentMissing;
+        let commitment = FpVar::new_input(cs.clone(), || self.commitment.ok_or_else(missing))?;
+        let nullifier = FpVar::new_input(cs, || self.nullifier.ok_or_else(missing))?;
+        commitment.enforce_equal(&nullifier)
+    }
+}
 
-        commitment_var.enforce_equal(&nullifier_var)?;
+/// A verifying key in the syscall encoding; verifying key accounts hold
+/// it borsh-serialized.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Groth16VerifyingKey {
+    pub alpha_g1: [u8; G1_LEN],
+    pub beta_g2: [u8; G2_LEN],
+    pub gamma_g2: [u8; G2_LEN],
+    pub delta_g2: [u8; G2_LEN],
+    /// `gamma_abc_g1`: the constant term, then one point per public input.
+    pub ic: Vec<[u8; G1_LEN]>,
+}
 
-        Ok(())
+impl From<&VerifyingKey<Bn254>> for Groth16VerifyingKey {
+    fn from(vk: &VerifyingKey<Bn254>) -> Self {
+        Self {
+            alpha_g1: g1_bytes(&vk.alpha_g1),
+            beta_g2: g2_bytes(&vk.beta_g2),
+            gamma_g2: g2_bytes(&vk.gamma_g2),
+            delta_g2: g2_bytes(&vk.delta_g2),
+            ic: vk.gamma_abc_g1.iter().map(g1_bytes).collect(),
+        }
     }
 }
 
-static mut PREPARED_VK: Option<PreparedVerifyingKey<Bn254>> = None;
+/// A G1 point in the syscall encoding.
+pub fn g1_bytes(point: &G1Affine) -> [u8; G1_LEN] {
+    let mut out = [0u8; G1_LEN];
+    if let Some((x, y)) = point.xy() {
+        out[..32].copy_from_slice(&x.into_bigint().to_bytes_be());
+        out[32..].copy_from_slice(&y.into_bigint().to_bytes_be());
+    }
+    out
+}
 
-pub fn set_prepared_vk(pvk: PreparedVerifyingKey<Bn254>) {
-    unsafe {
-        PREPARED_VK = Some(pvk);
+/// A G2 point in the syscall encoding.
+pub fn g2_bytes(point: &G2Affine) -> [u8; G2_LEN] {
+    let mut out = [0u8; G2_LEN];
+    if let Some((x, y)) = point.xy() {
+        for (chunk, limb) in out.chunks_mut(32).zip([x.c1, x.c0, y.c1, y.c0]) {
+            chunk.copy_from_slice(&limb.into_bigint().to_bytes_be());
+        }
     }
+    out
 }
 
 pub fn verify_groth16(
+    vk: &Groth16VerifyingKey,
     proof_bytes: &[u8],
-    public_commitment: &[u8; 32],
-    nullifier_bytes: &[u8; 32],
+    public_inputs: &[[u8; 32]],
 ) -> Result<(), ProgramError> {
-    use ark_ff::PrimeField;
-
-    let proof: Proof<Bn254> = bincode::deserialize(proof_bytes)
-        .map_err(|_| HandCrankedError::InvalidProof)?;
-
-    let pvk = unsafe {
-        PREPARED_VK
-            .as_ref()
-            .ok_or(HandCrankedError::InvalidProof)?
-    };
-
-    let c = <Bn254 as ark_ec::pairing::Pairing>::ScalarField::from_le_bytes_mod_order(public_commitment);
-    let n = <Bn254 as ark_ec::pairing::Pairing>::ScalarField::from_le_bytes_mod_order(nullifier_bytes);
-
-    let public_inputs = vec![c, n];
-
-    verify_proof(pvk, &proof, &public_inputs)
-        .map_err(|_| HandCrankedError::InvalidProof.into())
-        .and_then(|ok| {
-            if ok {
-                Ok(())
-            } else {
-                Err(HandCrankedError::InvalidProof.into())
-            }
-        })
+    let prepared = prepare_inputs(vk, public_inputs)?;
+    pairing_check(vk, proof_bytes, &prepared)
+}
+
+/// The public inputs folded into one G1 point with the key's
+/// `gamma_abc_g1`.
+pub fn prepare_inputs(
+    vk: &Groth16VerifyingKey,
+    public_inputs: &[[u8; 32]],
+) -> Result<[u8; PREPARED_INPUTS_LEN], ProgramError> {
+    if vk.ic.len() != public_inputs.len() + 1 {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    let mut prepared = vk.ic[0];
+    fold_inputs(vk, 0, public_inputs, &mut prepared)?;
+    Ok(prepared)
+}
+
+/// Add `inputs[i] * ic[first + i + 1]` to `prepared` for every `i`, so
+/// the inputs can be folded in over several steps starting from `ic[0]`.
+/// Inputs are canonical little-endian scalars.
+pub fn fold_inputs(
+    vk: &Groth16VerifyingKey,
+    first: usize,
+    inputs: &[[u8; 32]],
+    prepared: &mut [u8; PREPARED_INPUTS_LEN],
+) -> Result<(), ProgramError> {
+    let points = vk
+        .ic
+        .get(first + 1..first + 1 + inputs.len())
+        .ok_or(HandCrankedError::InvalidProof)?;
+    for (input, point) in inputs.iter().zip(points) {
+        let mut scalar = *input;
+        scalar.reverse();
+        let product = alt_bn128_multiplication(&[&point[..], &scalar[..]].concat())
+            .map_err(|_| HandCrankedError::InvalidProof)?;
+        let sum = alt_bn128_addition(&[&prepared[..], &product[..]].concat())
+            .map_err(|_| HandCrankedError::InvalidProof)?;
+        prepared.copy_from_slice(&sum);
+    }
+    Ok(())
+}
+
+/// `e(-A, B) * e(alpha, beta) * e(inputs, gamma) * e(C, delta) == 1`,
+/// with the proof in the wire format of `groth16_encoding`.
+pub fn pairing_check(
+    vk: &Groth16VerifyingKey,
+    proof_bytes: &[u8],
+    prepared_inputs: &[u8; PREPARED_INPUTS_LEN],
+) -> Result<(), ProgramError> {
+    if proof_bytes.len() != GROTH16_PROOF_LEN {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    let a = decompress_g1(&proof_bytes[..32])?;
+    let b = decompress_g2(&proof_bytes[32..96])?;
+    let c = decompress_g1(&proof_bytes[96..])?;
+
+    let input = [
+        &negate_g1(&a)[..],
+        &b,
+        &vk.alpha_g1,
+        &vk.beta_g2,
+        prepared_inputs,
+        &vk.gamma_g2,
+        &c,
+        &vk.delta_g2,
+    ]
+    .concat();
+    let result = alt_bn128_pairing(&input).map_err(|_| HandCrankedError::InvalidProof)?;
+    if result[..] != PAIRING_ONE {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+/// Decompress a G1 point of the wire format. The syscalls take the
+/// arkworks encoding byte-reversed; only canonical encodings are accepted.
+fn decompress_g1(bytes: &[u8]) -> Result<[u8; G1_LEN], ProgramError> {
+    let mut compressed = [0u8; 32];
+    compressed.copy_from_slice(bytes);
+    compressed.reverse();
+    let point = alt_bn128_g1_decompress(&compressed).map_err(|_| HandCrankedError::InvalidProof)?;
+    if alt_bn128_g1_compress(&point).map_err(|_| HandCrankedError::InvalidProof)? != compressed {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    Ok(point)
+}
+
+fn decompress_g2(bytes: &[u8]) -> Result<[u8; G2_LEN], ProgramError> {
+    let mut compressed = [0u8; 64];
+    compressed.copy_from_slice(bytes);
+    compressed.reverse();
+    let point = alt_bn128_g2_decompress(&compressed).map_err(|_| HandCrankedError::InvalidProof)?;
+    if alt_bn128_g2_compress(&point).map_err(|_| HandCrankedError::InvalidProof)? != compressed {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    Ok(point)
+}
+
+/// `-P`, i.e. `(x, q - y)`; infinity is its own negation.
+fn negate_g1(point: &[u8; G1_LEN]) -> [u8; G1_LEN] {
+    if *point == [0; G1_LEN] {
+        return *point;
+    }
+    let mut out = *point;
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let diff = BASE_MODULUS[i] as i16 - point[32 + i] as i16 - borrow;
+        out[32 + i] = diff.rem_euclid(256) as u8;
+        borrow = (diff < 0) as i16;
+    }
+    out
 }
@@ -0,0 +1,62 @@
+//! Wire format of Groth16 proofs over BN254.
+//!
+//! A proof is exactly `GROTH16_PROOF_LEN` = 128 bytes:
+//!
+//! | bytes     | point | encoding                                         |
+//! |-----------|-------|--------------------------------------------------|
+//! | `0..32`   | `A`   | compressed G1: `x` little-endian                 |
+//! | `32..96`  | `B`   | compressed G2: `x.c0` then `x.c1`, little-endian |
+//! | `96..128` | `C`   | compressed G1                                    |
+//!
+//! The two most significant bits of the last byte of each point are flags:
+//! bit 7 is set when `y` is the lexicographically larger root, bit 6 marks
+//! the point at infinity (with all other bits zero). This is arkworks'
+//! `serialize_compressed`, so provers built on arkworks can use it as is.
+
+use ark_bn254::{Bn254, G1Affine, G2Affine};
+use ark_groth16::Proof;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use solana_program::program_error::ProgramError;
+
+use crate::error::HandCrankedError;
+
+pub const GROTH16_PROOF_LEN: usize = 128;
+const G1_LEN: usize = 32;
+const G2_LEN: usize = 64;
+
+/// Encode a proof in the 128-byte wire format.
+pub fn encode_groth16_proof(proof: &Proof<Bn254>) -> [u8; GROTH16_PROOF_LEN] {
+    let mut out = [0u8; GROTH16_PROOF_LEN];
+    let (a, rest) = out.split_at_mut(G1_LEN);
+    let (b, c) = rest.split_at_mut(G2_LEN);
+    // Writing a valid point into a buffer of its exact size cannot fail.
+    proof.a.serialize_compressed(a).expect("G1 encoding");
+    proof.b.serialize_compressed(b).expect("G2 encoding");
+    proof.c.serialize_compressed(c).expect("G1 encoding");
+    out
+}
+
+/// Decode a proof in the 128-byte wire format. Points must be on the
+/// curve, in the prime-order subgroup and canonically encoded.
+pub fn decode_groth16_proof(bytes: &[u8]) -> Result<Proof<Bn254>, ProgramError> {
+    if bytes.len() != GROTH16_PROOF_LEN {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    let proof = Proof {
+        a: G1Affine::deserialize_compressed(&bytes[..G1_LEN])
+            .map_err(|_| HandCrankedError::InvalidProof)?,
+        b: G2Affine::deserialize_compressed(&bytes[G1_LEN..G1_LEN + G2_LEN])
+            .map_err(|_| HandCrankedError::InvalidProof)?,
+        c: G1Affine::deserialize_compressed(&bytes[G1_LEN + G2_LEN..])
+            .map_err(|_| HandCrankedError::InvalidProof)?,
+    };
+
+    // The flags leave room for encodings that decode to the same point
+    // (e.g. infinity with stray bits); only the canonical one is accepted.
+    if encode_groth16_proof(&proof)[..] != *bytes {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    Ok(proof)
+}
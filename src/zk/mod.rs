@@ -1,65 +1,171 @@
 use crate::instruction::ProofSystem;
 use crate::error::HandCrankedError;
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_error::ProgramError;
 
-/// Generic verifier trait – implemented by Groth16/STARK modules off-chain.
+/// Which circuit (and so which verifying key) a proof was made for.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Circuit {
+    /// Arbitrary-amount join-split: membership, nullifiers and value balance.
+    JoinSplit = 0,
+    /// Fixed-denomination withdraw: membership and nullifier only. The
+    /// amount is implied by the pool, so `public_amount` is always the
+    /// pool denomination.
+    Membership = 1,
+    /// Root update for a `BatchInsert` of queued leaves.
+    BatchInsert = 2,
+    /// Membership of a withdrawn note in a curator's association set.
+    Association = 3,
+    /// Join-split that also proves an `AuditorMemo` was encrypted to the
+    /// pool auditor.
+    AuditedJoinSplit = 4,
+    /// Spend of a note in one pool for a note in another through a swap
+    /// reserve, with a minimum output amount.
+    Swap = 5,
+    /// Join-split in a multi-asset pool: every input and output note
+    /// commits to the same asset id, which is a public input, so transfers
+    /// reveal their asset.
+    MultiAssetJoinSplit = 6,
+    /// Anonymity mining claim: a note in the tree at a checkpoint is spent
+    /// for a note of the same value in its pool and a note of the reward
+    /// pool worth its reward since that checkpoint.
+    Reward = 7,
+    /// Swap that also proves an `AuditorMemo` was encrypted to the auditor
+    /// of the input or output pool.
+    AuditedSwap = 8,
+}
+
+/// Generic verifier trait – implemented by Groth16/STARK/PLONK modules off-chain.
+///
+/// Verifiers are passed to `Processor::process_with` as objects, so an
+/// implementation can carry its own verifying keys or stand in for real
+/// proofs in tests (`MockVerifier`).
+///
+/// `key` is the data of the pool's verifying key account for `system` and
+/// `circuit`, in the encoding that proof system's verifier reads.
+/// `public_inputs` is the circuit's field element vector, built with the
+/// `to_field_elements` of `PublicInputs`, `BatchInsertInputs` or
+/// `AssociationInputs`.
 pub trait ProofVerifier {
     fn verify(
+        &self,
         system: ProofSystem,
+        circuit: Circuit,
+        key: &[u8],
         proof: &[u8],
-        public_commitment: &[u8; 32],
-        nullifier: &[u8; 32],
+        public_inputs: &[[u8; 32]],
     ) -> Result<(), ProgramError>;
 }
 
+/// Dispatches on `ProofSystem` to the verifiers compiled in. Groth16 keys
+/// are a borsh `Groth16VerifyingKey`, PLONK keys `plonk::encode_plonk_key`.
+///
+/// Groth16 runs on the `alt_bn128` syscalls and so works on-chain; the
+/// arkworks PLONK and STARK verifiers are off-chain only, and on-chain
+/// those systems are `UnsupportedProofSystem`. The program also refuses to
+/// create PLONK verifying keys, so PLONK proofs are only ever checked by
+/// off-chain callers of this verifier.
 pub struct DefaultVerifier;
 
 impl ProofVerifier for DefaultVerifier {
     fn verify(
+        &self,
         system: ProofSystem,
+        _circuit: Circuit,
+        key: &[u8],
         proof: &[u8],
-        public_commitment: &[u8; 32],
-        nullifier: &[u8; 32],
+        public_inputs: &[[u8; 32]],
     ) -> Result<(), ProgramError> {
-        #[cfg(target_arch = "bpf")]
-        {
-   
-            let _ = (system, proof, public_commitment, nullifier);
-            return Err(HandCrankedError::UnsupportedProofSystem.into());
-        }
-
-        #[cfg(not(target_arch = "bpf"))]
-        {
-            match system {
-                ProofSystem::Groth16 => {
-                    #[cfg(feature = "zk-groth16")]
-                    {
-                        crate::zk::groth16_circuit::verify_groth16(proof, public_commitment, nullifier)
-                    }
-                    #[cfg(not(feature = "zk-groth16"))]
-                    {
-                        let _ = (proof, public_commitment, nullifier);
-                        Err(HandCrankedError::UnsupportedProofSystem.into())
-                    }
+        match system {
+            ProofSystem::Groth16 => {
+                #[cfg(feature = "zk-groth16")]
+                {
+                    use crate::zk::groth16_circuit::{verify_groth16, Groth16VerifyingKey};
+                    let vk = Groth16VerifyingKey::try_from_slice(key)
+                        .map_err(|_| HandCrankedError::InvalidProof)?;
+                    verify_groth16(&vk, proof, public_inputs)
+                }
+                #[cfg(not(feature = "zk-groth16"))]
+                {
+                    let _ = (key, proof, public_inputs);
+                    Err(HandCrankedError::UnsupportedProofSystem.into())
+                }
+            }
+            ProofSystem::Stark => {
+                #[cfg(all(feature = "zk-stark", not(target_os = "solana")))]
+                {
+                    let _ = key;
+                    crate::zk::stark::verify_stark(proof, public_inputs)
                 }
-                ProofSystem::Stark => {
-                    #[cfg(feature = "zk-stark")]
-                    {
-                        crate::zk::stark::verify_stark(proof, public_commitment, nullifier)
-                    }
-                    #[cfg(not(feature = "zk-stark"))]
-                    {
-                        let _ = (proof, public_commitment, nullifier);
-                        Err(HandCrankedError::UnsupportedProofSystem.into())
-                    }
+                #[cfg(not(all(feature = "zk-stark", not(target_os = "solana"))))]
+                {
+                    let _ = (key, proof, public_inputs);
+                    Err(HandCrankedError::UnsupportedProofSystem.into())
+                }
+            }
+            ProofSystem::Plonk => {
+                #[cfg(all(feature = "zk-plonk", not(target_os = "solana")))]
+                {
+                    crate::zk::plonk::verify_plonk(key, proof, public_inputs)
+                }
+                #[cfg(not(all(feature = "zk-plonk", not(target_os = "solana"))))]
+                {
+                    let _ = (key, proof, public_inputs);
+                    Err(HandCrankedError::UnsupportedProofSystem.into())
                 }
             }
         }
     }
 }
 
+/// Verifier that accepts or rejects every proof as configured, for
+/// exercising processor logic without real proofs.
+#[cfg(feature = "test-utils")]
+#[derive(Clone, Copy, Debug)]
+pub struct MockVerifier {
+    pub accept: bool,
+}
+
+#[cfg(feature = "test-utils")]
+impl MockVerifier {
+    pub const fn accepting() -> Self {
+        Self { accept: true }
+    }
+
+    pub const fn rejecting() -> Self {
+        Self { accept: false }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl ProofVerifier for MockVerifier {
+    fn verify(
+        &self,
+        _system: ProofSystem,
+        _circuit: Circuit,
+        _key: &[u8],
+        _proof: &[u8],
+        _public_inputs: &[[u8; 32]],
+    ) -> Result<(), ProgramError> {
+        if self.accept {
+            Ok(())
+        } else {
+            Err(HandCrankedError::InvalidProof.into())
+        }
+    }
+}
+
+pub mod public_inputs;
+pub mod session;
+
 #[cfg(feature = "zk-groth16")]
 pub mod groth16_circuit;
 
+#[cfg(feature = "zk-groth16")]
+pub mod groth16_encoding;
+
 #[cfg(feature = "zk-stark")]
 pub mod stark;
+
+#[cfg(feature = "zk-plonk")]
+pub mod plonk;
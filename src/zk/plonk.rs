@@ -0,0 +1,375 @@
+#![cfg(feature = "zk-plonk")]
+
+//! PLONK (KZG) verifier over BN254.
+//!
+//! Verification follows snarkjs' `plonk` verifier, so circuits set up with
+//! `snarkjs plonk setup` against the shared powers-of-tau file verify here:
+//! every circuit key carries its own selector and permutation commitments
+//! and the `[x]_2` of the ceremony. Keys live in verifying key accounts in
+//! the `PLONK_KEY_LEN`-byte encoding of `encode_plonk_key`. Fiat-Shamir
+//! challenges are Keccak-256 over the snarkjs transcript (uncompressed
+//! big-endian points, big-endian scalars).
+//!
+//! On the wire a proof is `PLONK_PROOF_LEN` = 480 bytes: the nine G1
+//! commitments `A, B, C, Z, T1, T2, T3, Wxi, Wxiw` compressed as in
+//! `groth16_encoding`, then the six evaluations `a, b, c, s1, s2, zw` as
+//! canonical 32-byte little-endian scalars.
+//!
+//! The verifier is off-chain only: its pairing and MSM run in arkworks,
+//! far beyond the compute budget of a transaction, and the program does
+//! not compile it for `target_os = "solana"`. Relayers and indexers use it
+//! to check proofs before submitting them; `InitializeVerifyingKey`
+//! rejects PLONK keys so no pool can be configured to expect them.
+
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, Field, One, PrimeField, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use solana_program::{keccak, program_error::ProgramError};
+
+use crate::error::HandCrankedError;
+
+pub const PLONK_PROOF_LEN: usize = 9 * 32 + 6 * 32;
+/// `power` and `n_public`, three scalars, eight G1 points and `[x]_2`.
+pub const PLONK_KEY_LEN: usize = 4 + 4 + 3 * 32 + 8 * 32 + 64;
+
+/// A PLONK verifying key: the `verification_key.json` of one circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlonkCircuitKey {
+    /// Domain size is `2^power`.
+    pub power: u32,
+    pub n_public: usize,
+    /// Coset shifts of the permutation argument.
+    pub k1: Fr,
+    pub k2: Fr,
+    /// Generator of the evaluation domain.
+    pub omega: Fr,
+    pub qm: G1Affine,
+    pub ql: G1Affine,
+    pub qr: G1Affine,
+    pub qo: G1Affine,
+    pub qc: G1Affine,
+    pub s1: G1Affine,
+    pub s2: G1Affine,
+    pub s3: G1Affine,
+    /// `[x]_2` of the powers-of-tau ceremony.
+    pub x_2: G2Affine,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlonkProof {
+    pub a: G1Affine,
+    pub b: G1Affine,
+    pub c: G1Affine,
+    pub z: G1Affine,
+    pub t1: G1Affine,
+    pub t2: G1Affine,
+    pub t3: G1Affine,
+    pub wxi: G1Affine,
+    pub wxiw: G1Affine,
+    pub eval_a: Fr,
+    pub eval_b: Fr,
+    pub eval_c: Fr,
+    pub eval_s1: Fr,
+    pub eval_s2: Fr,
+    pub eval_zw: Fr,
+}
+
+/// Encode a key in the `PLONK_KEY_LEN`-byte account format: `power` and
+/// `n_public` as little-endian `u32`s, then `k1, k2, omega`, the selector
+/// and permutation commitments `qm, ql, qr, qo, qc, s1, s2, s3` and `x_2`,
+/// compressed as in proofs.
+pub fn encode_plonk_key(key: &PlonkCircuitKey) -> Vec<u8> {
+    let mut out = Vec::with_capacity(PLONK_KEY_LEN);
+    out.extend(key.power.to_le_bytes());
+    out.extend((key.n_public as u32).to_le_bytes());
+    // Serializing into a `Vec` cannot fail.
+    for scalar in [&key.k1, &key.k2, &key.omega] {
+        scalar
+            .serialize_compressed(&mut out)
+            .expect("scalar encoding");
+    }
+    for point in [
+        &key.qm, &key.ql, &key.qr, &key.qo, &key.qc, &key.s1, &key.s2, &key.s3,
+    ] {
+        point.serialize_compressed(&mut out).expect("G1 encoding");
+    }
+    key.x_2.serialize_compressed(&mut out).expect("G2 encoding");
+    out
+}
+
+/// Decode a key in the account format, rejecting off-curve points and
+/// non-canonical encodings.
+pub fn decode_plonk_key(bytes: &[u8]) -> Result<PlonkCircuitKey, ProgramError> {
+    if bytes.len() != PLONK_KEY_LEN {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    let (header, rest) = bytes.split_at(8);
+    let (scalars, rest) = rest.split_at(3 * 32);
+    let (points, x_2) = rest.split_at(8 * 32);
+    let mut scalars = scalars.chunks_exact(32);
+    let mut scalar = || {
+        Fr::deserialize_compressed(scalars.next().unwrap())
+            .map_err(|_| HandCrankedError::InvalidProof)
+    };
+    let (k1, k2, omega) = (scalar()?, scalar()?, scalar()?);
+    let mut points = points.chunks_exact(32);
+    let mut point = || {
+        G1Affine::deserialize_compressed(points.next().unwrap())
+            .map_err(|_| HandCrankedError::InvalidProof)
+    };
+    let key = PlonkCircuitKey {
+        power: u32::from_le_bytes(header[..4].try_into().unwrap()),
+        n_public: u32::from_le_bytes(header[4..].try_into().unwrap()) as usize,
+        k1,
+        k2,
+        omega,
+        qm: point()?,
+        ql: point()?,
+        qr: point()?,
+        qo: point()?,
+        qc: point()?,
+        s1: point()?,
+        s2: point()?,
+        s3: point()?,
+        x_2: G2Affine::deserialize_compressed(x_2).map_err(|_| HandCrankedError::InvalidProof)?,
+    };
+
+    if encode_plonk_key(&key) != bytes {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    Ok(key)
+}
+
+/// Encode a proof in the 480-byte wire format.
+pub fn encode_plonk_proof(proof: &PlonkProof) -> [u8; PLONK_PROOF_LEN] {
+    let mut out = [0u8; PLONK_PROOF_LEN];
+    let points = [
+        &proof.a,
+        &proof.b,
+        &proof.c,
+        &proof.z,
+        &proof.t1,
+        &proof.t2,
+        &proof.t3,
+        &proof.wxi,
+        &proof.wxiw,
+    ];
+    let evals = [
+        &proof.eval_a,
+        &proof.eval_b,
+        &proof.eval_c,
+        &proof.eval_s1,
+        &proof.eval_s2,
+        &proof.eval_zw,
+    ];
+    let mut chunks = out.chunks_exact_mut(32);
+    // Writing valid points and scalars into 32-byte chunks cannot fail.
+    for (point, chunk) in points.into_iter().zip(&mut chunks) {
+        point.serialize_compressed(chunk).expect("G1 encoding");
+    }
+    for (eval, chunk) in evals.into_iter().zip(&mut chunks) {
+        eval.serialize_compressed(chunk).expect("scalar encoding");
+    }
+    out
+}
+
+/// Decode a proof in the 480-byte wire format, rejecting off-curve points
+/// and non-canonical encodings.
+pub fn decode_plonk_proof(bytes: &[u8]) -> Result<PlonkProof, ProgramError> {
+    if bytes.len() != PLONK_PROOF_LEN {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    let mut chunks = bytes.chunks_exact(32);
+    let mut point = || {
+        G1Affine::deserialize_compressed(chunks.next().unwrap())
+            .map_err(|_| HandCrankedError::InvalidProof)
+    };
+    let (a, b, c, z) = (point()?, point()?, point()?, point()?);
+    let (t1, t2, t3, wxi, wxiw) = (point()?, point()?, point()?, point()?, point()?);
+    let mut eval = || {
+        Fr::deserialize_compressed(chunks.next().unwrap())
+            .map_err(|_| HandCrankedError::InvalidProof)
+    };
+    let proof = PlonkProof {
+        a,
+        b,
+        c,
+        z,
+        t1,
+        t2,
+        t3,
+        wxi,
+        wxiw,
+        eval_a: eval()?,
+        eval_b: eval()?,
+        eval_c: eval()?,
+        eval_s1: eval()?,
+        eval_s2: eval()?,
+        eval_zw: eval()?,
+    };
+
+    if encode_plonk_proof(&proof)[..] != *bytes {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+
+    Ok(proof)
+}
+
+/// Verify a proof against a key in the account format.
+pub fn verify_plonk(
+    key_bytes: &[u8],
+    proof_bytes: &[u8],
+    public_inputs: &[[u8; 32]],
+) -> Result<(), ProgramError> {
+    let proof = decode_plonk_proof(proof_bytes)?;
+    let key = decode_plonk_key(key_bytes)?;
+    if public_inputs.len() != key.n_public {
+        return Err(HandCrankedError::InvalidProof.into());
+    }
+    let public_inputs: Vec<Fr> = public_inputs
+        .iter()
+        .map(|input| Fr::from_le_bytes_mod_order(input))
+        .collect();
+
+    if check(&key, key.x_2, &proof, &public_inputs).unwrap_or(false) {
+        Ok(())
+    } else {
+        Err(HandCrankedError::InvalidProof.into())
+    }
+}
+
+/// Fiat-Shamir transcript, reset after every challenge.
+#[derive(Default)]
+struct Transcript(Vec<u8>);
+
+impl Transcript {
+    fn point(&mut self, point: &G1Affine) {
+        match point.xy() {
+            Some((x, y)) => {
+                self.0.extend(x.into_bigint().to_bytes_be());
+                self.0.extend(y.into_bigint().to_bytes_be());
+            }
+            None => self.0.extend([0u8; 64]),
+        }
+    }
+
+    fn scalar(&mut self, scalar: &Fr) {
+        self.0.extend(scalar.into_bigint().to_bytes_be());
+    }
+
+    fn challenge(&mut self) -> Fr {
+        let hash = keccak::hash(&self.0);
+        self.0.clear();
+        Fr::from_be_bytes_mod_order(&hash.0)
+    }
+}
+
+/// The snarkjs PLONK check. `None` when a challenge hits a domain point.
+fn check(
+    key: &PlonkCircuitKey,
+    x_2: G2Affine,
+    proof: &PlonkProof,
+    public_inputs: &[Fr],
+) -> Option<bool> {
+    let mut transcript = Transcript::default();
+    for point in [
+        &key.qm, &key.ql, &key.qr, &key.qo, &key.qc, &key.s1, &key.s2, &key.s3,
+    ] {
+        transcript.point(point);
+    }
+    for input in public_inputs {
+        transcript.scalar(input);
+    }
+    for point in [&proof.a, &proof.b, &proof.c] {
+        transcript.point(point);
+    }
+    let beta = transcript.challenge();
+
+    transcript.scalar(&beta);
+    let gamma = transcript.challenge();
+
+    transcript.scalar(&beta);
+    transcript.scalar(&gamma);
+    transcript.point(&proof.z);
+    let alpha = transcript.challenge();
+
+    transcript.scalar(&alpha);
+    for point in [&proof.t1, &proof.t2, &proof.t3] {
+        transcript.point(point);
+    }
+    let xi = transcript.challenge();
+
+    transcript.scalar(&xi);
+    for eval in [
+        &proof.eval_a,
+        &proof.eval_b,
+        &proof.eval_c,
+        &proof.eval_s1,
+        &proof.eval_s2,
+        &proof.eval_zw,
+    ] {
+        transcript.scalar(eval);
+    }
+    let v1 = transcript.challenge();
+    let (v2, v3, v4, v5) = (v1 * v1, v1.pow([3]), v1.pow([4]), v1.pow([5]));
+
+    transcript.point(&proof.wxi);
+    transcript.point(&proof.wxiw);
+    let u = transcript.challenge();
+
+    // Vanishing polynomial and Lagrange bases at xi.
+    let n = 1u64 << key.power;
+    let xin = xi.pow([n]);
+    let zh = xin - Fr::one();
+    let n_inv = Fr::from(n).inverse()?;
+    let mut lagrange = Vec::with_capacity(public_inputs.len().max(1));
+    let mut w = Fr::one();
+    for _ in 0..public_inputs.len().max(1) {
+        lagrange.push(w * zh * n_inv * (xi - w).inverse()?);
+        w *= key.omega;
+    }
+    let l1 = lagrange[0];
+    let pi = -public_inputs
+        .iter()
+        .zip(&lagrange)
+        .map(|(input, l)| *input * l)
+        .sum::<Fr>();
+
+    let (a, b, c) = (proof.eval_a, proof.eval_b, proof.eval_c);
+    let (s1, s2, zw) = (proof.eval_s1, proof.eval_s2, proof.eval_zw);
+    let alpha2 = alpha * alpha;
+    let perm_ab = (a + beta * s1 + gamma) * (b + beta * s2 + gamma);
+
+    let r0 = pi - l1 * alpha2 - alpha * perm_ab * (c + gamma) * zw;
+
+    // Linearisation commitment D.
+    let beta_xi = beta * xi;
+    let z_coef = (a + beta_xi + gamma)
+        * (b + beta_xi * key.k1 + gamma)
+        * (c + beta_xi * key.k2 + gamma)
+        * alpha
+        + l1 * alpha2
+        + u;
+    let s3_coef = perm_ab * alpha * beta * zw;
+    let t: G1Projective = proof.t1.into_group() + proof.t2 * xin + proof.t3 * (xin * xin);
+    let d: G1Projective =
+        key.qm * (a * b) + key.ql * a + key.qr * b + key.qo * c + key.qc + proof.z * z_coef
+            - key.s3 * s3_coef
+            - t * zh;
+
+    let f = d + proof.a * v1 + proof.b * v2 + proof.c * v3 + key.s1 * v4 + key.s2 * v5;
+    let e = G1Affine::generator() * (-r0 + v1 * a + v2 * b + v3 * c + v4 * s1 + v5 * s2 + u * zw);
+
+    let w_sum: G1Projective = proof.wxi.into_group() + proof.wxiw * u;
+    let rhs = proof.wxi * xi + proof.wxiw * (u * xi * key.omega) + f - e;
+
+    let pairing = Bn254::multi_pairing(
+        [(-w_sum).into_affine(), rhs.into_affine()],
+        [x_2, G2Affine::generator()],
+    );
+    Some(pairing.is_zero())
+}
@@ -0,0 +1,381 @@
+//! Encoding of on-chain values as BN254 scalar field elements.
+//!
+//! Field elements are 32-byte little-endian, matching `verify_groth16`
+//! and the Poseidon tree hashing. Prover code must use the same helpers
+//! so both sides agree on the public input vector.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{hash::hashv, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{error::HandCrankedError, instruction::WithdrawCallback, state::Cluster};
+
+const DOMAIN_SEPARATOR: &[u8] = b"hand-cranked-privacy/v1";
+
+/// BN254 scalar field modulus `r`, little-endian.
+pub const SCALAR_MODULUS: [u8; 32] = [
+    0x01, 0x00, 0x00, 0xf0, 0x93, 0xf5, 0xe1, 0x43, 0x91, 0x70, 0xb9, 0x79, 0x48, 0xe8, 0x33, 0x28,
+    0x5d, 0x58, 0x81, 0x81, 0xb6, 0x45, 0x50, 0xb8, 0x29, 0xa0, 0x31, 0xe1, 0x72, 0x4e, 0x64, 0x30,
+];
+
+/// Whether `element` is the canonical encoding of a field element (`< r`).
+pub fn is_canonical(element: &[u8; 32]) -> bool {
+    element.iter().rev().lt(SCALAR_MODULUS.iter().rev())
+}
+
+/// Reject any public input `>= r`.
+///
+/// Verifiers reduce inputs modulo `r`, so `x` and `x + r` verify against
+/// the same proof while deriving different nullifier, note and root
+/// accounts. Every proof must go through this check before it is verified.
+pub fn check_canonical(inputs: &[[u8; 32]]) -> Result<(), ProgramError> {
+    if inputs.iter().all(is_canonical) {
+        Ok(())
+    } else {
+        Err(HandCrankedError::NonCanonicalInput.into())
+    }
+}
+
+/// A `u64` as a field element.
+pub fn u64_to_field(value: u64) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out[..8].copy_from_slice(&value.to_le_bytes());
+    out
+}
+
+/// Arbitrary bytes reduced into the field: SHA-256 with the most
+/// significant byte cleared (248 bits).
+pub fn hash_to_field(parts: &[&[u8]]) -> [u8; 32] {
+    let mut out = hashv(parts).to_bytes();
+    out[31] = 0;
+    out
+}
+
+/// A public key as a field element (keys are 256-bit, so they are hashed).
+pub fn pubkey_to_field(pubkey: &Pubkey) -> [u8; 32] {
+    hash_to_field(&[pubkey.as_ref()])
+}
+
+/// Asset id committed to by notes in a multi-asset pool: the mint, or
+/// `NATIVE_ASSET` for SOL, as a field element.
+pub fn asset_id(mint: &Pubkey) -> [u8; 32] {
+    pubkey_to_field(mint)
+}
+
+/// Domain tag binding a proof to one program, pool and cluster.
+///
+/// Always computed by the processor from the accounts it is executing
+/// with, never taken from instruction data.
+pub fn domain_tag(program_id: &Pubkey, pool: &Pubkey, cluster: Cluster) -> [u8; 32] {
+    hash_to_field(&[
+        DOMAIN_SEPARATOR,
+        program_id.as_ref(),
+        pool.as_ref(),
+        &[cluster as u8],
+    ])
+}
+
+/// Data that is not a circuit input itself but must not be changed by
+/// whoever submits the transaction; only its hash is a public input.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct ExtData {
+    /// Withdraw recipient (`Pubkey::default()` for transfers).
+    pub recipient: Pubkey,
+    pub relayer: Pubkey,
+    pub fee: u64,
+    /// Note ciphertexts for each output commitment, in order.
+    pub encrypted_outputs: Vec<Vec<u8>>,
+    /// Instruction invoked after a withdrawal pays out (`None` for
+    /// transfers and delayed withdrawals).
+    pub callback: Option<WithdrawCallback>,
+}
+
+impl ExtData {
+    pub fn hash(&self) -> Result<[u8; 32], ProgramError> {
+        let data = self
+            .try_to_vec()
+            .map_err(|_| HandCrankedError::InvalidInstruction)?;
+        Ok(hash_to_field(&[&data]))
+    }
+}
+
+/// Auditor public key: an affine BabyJubJub point, one field element per
+/// coordinate so the circuit can use it directly.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AuditorKey {
+    pub x: [u8; 32],
+    pub y: [u8; 32],
+}
+
+/// Transfer details encrypted to the pool auditor.
+///
+/// Poseidon-based ECIES over BabyJubJub: with ephemeral secret `r`,
+/// `ephemeral = r·G`, `s = r·auditor` and each message is masked as
+/// `m_i + Poseidon(s.x, i)`. The messages are the transferred amount and
+/// the sender and recipient note owner keys.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AuditorMemo {
+    pub ephemeral_x: [u8; 32],
+    pub ephemeral_y: [u8; 32],
+    pub amount: [u8; 32],
+    pub sender: [u8; 32],
+    pub recipient: [u8; 32],
+}
+
+/// Auditor key and the memo the proof must show was encrypted to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditBinding {
+    pub key: AuditorKey,
+    pub memo: AuditorMemo,
+}
+
+impl AuditBinding {
+    /// `[auditor.x, auditor.y, ephemeral_x, ephemeral_y, amount, sender,
+    /// recipient]`
+    pub fn to_field_elements(&self) -> [[u8; 32]; 7] {
+        [
+            self.key.x,
+            self.key.y,
+            self.memo.ephemeral_x,
+            self.memo.ephemeral_y,
+            self.memo.amount,
+            self.memo.sender,
+            self.memo.recipient,
+        ]
+    }
+}
+
+/// Everything a join-split proof is bound to.
+///
+/// Withdrawals are a join-split with one input, no outputs and a non-zero
+/// `public_amount`; transfers have two inputs, two outputs and a zero
+/// `public_amount`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicInputs {
+    /// `domain_tag(program_id, pool, cluster)`.
+    pub domain: [u8; 32],
+    pub root: [u8; 32],
+    /// Slot at which `root` became current, as recorded by the pool tree.
+    pub root_slot: u64,
+    pub nullifiers: Vec<[u8; 32]>,
+    pub output_commitments: Vec<[u8; 32]>,
+    /// Value leaving the pool to `ExtData::recipient` (fee included).
+    pub public_amount: u64,
+    pub fee: u64,
+    pub relayer: Pubkey,
+    pub ext_data_hash: [u8; 32],
+    /// Set for transfers in pools with an auditor (`Circuit::AuditedJoinSplit`).
+    pub audit: Option<AuditBinding>,
+    /// `asset_id` of the notes spent and created, set in multi-asset pools
+    /// (`Circuit::MultiAssetJoinSplit`). Public, since the fee is paid from
+    /// that asset's vault.
+    pub asset_id: Option<[u8; 32]>,
+}
+
+impl PublicInputs {
+    /// The public input vector, in circuit order:
+    ///
+    /// `[domain, root, root_slot, public_amount, fee, relayer, ext_data_hash, nullifiers.., output_commitments..]`
+    ///
+    /// followed, when `audit` is set, by `[auditor.x, auditor.y,
+    /// ephemeral_x, ephemeral_y, amount, sender, recipient]` and, when
+    /// `asset_id` is set, by `[asset_id]`.
+    ///
+    /// This is the only place the vector is assembled; provers must call it
+    /// rather than rebuilding the layout themselves.
+    pub fn to_field_elements(&self) -> Vec<[u8; 32]> {
+        let mut inputs =
+            Vec::with_capacity(7 + self.nullifiers.len() + self.output_commitments.len());
+        inputs.push(self.domain);
+        inputs.push(self.root);
+        inputs.push(u64_to_field(self.root_slot));
+        inputs.push(u64_to_field(self.public_amount));
+        inputs.push(u64_to_field(self.fee));
+        inputs.push(pubkey_to_field(&self.relayer));
+        inputs.push(self.ext_data_hash);
+        inputs.extend_from_slice(&self.nullifiers);
+        inputs.extend_from_slice(&self.output_commitments);
+        if let Some(audit) = &self.audit {
+            inputs.extend_from_slice(&audit.to_field_elements());
+        }
+        if let Some(asset_id) = self.asset_id {
+            inputs.push(asset_id);
+        }
+        inputs
+    }
+}
+
+/// Hash of the leaves absorbed by a `BatchInsert`, in insertion order.
+pub fn leaves_hash(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let parts: Vec<&[u8]> = leaves.iter().map(|leaf| leaf.as_ref()).collect();
+    hash_to_field(&parts)
+}
+
+/// Everything a batch insertion proof is bound to: appending the leaves
+/// hashed into `leaves_hash` at `start_index` forms a subtree with root
+/// `subtree_root` and turns `old_root` into `new_root`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchInsertInputs {
+    /// `domain_tag(program_id, pool, cluster)`.
+    pub domain: [u8; 32],
+    pub old_root: [u8; 32],
+    pub new_root: [u8; 32],
+    pub leaves_hash: [u8; 32],
+    pub start_index: u64,
+    pub subtree_root: [u8; 32],
+}
+
+impl BatchInsertInputs {
+    /// `[domain, old_root, new_root, leaves_hash, start_index, subtree_root]`
+    pub fn to_field_elements(&self) -> Vec<[u8; 32]> {
+        vec![
+            self.domain,
+            self.old_root,
+            self.new_root,
+            self.leaves_hash,
+            u64_to_field(self.start_index),
+            self.subtree_root,
+        ]
+    }
+}
+
+/// Everything a swap proof is bound to: the note spent under `nullifier`
+/// in the input pool is worth `amount_in` (fee included), and
+/// `output_commitment` opens a note of the output pool whose amount the
+/// program fills in, which must be at least `min_amount_out`.
+///
+/// None of this hides the trade: `amount_in`, `fee` and the reserve's
+/// `amount_out` are public, and so is the output leaf the nullifier is
+/// exchanged for. Only the owners of the two notes stay private.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapInputs {
+    /// `domain_tag` of the input pool.
+    pub domain: [u8; 32],
+    pub root: [u8; 32],
+    /// Slot at which `root` became current, as recorded by the input pool
+    /// tree.
+    pub root_slot: u64,
+    pub nullifier: [u8; 32],
+    pub amount_in: u64,
+    pub fee: u64,
+    pub relayer: Pubkey,
+    /// `domain_tag` of the output pool.
+    pub output_domain: [u8; 32],
+    pub output_commitment: [u8; 32],
+    pub min_amount_out: u64,
+    pub ext_data_hash: [u8; 32],
+    /// Set when either pool has an auditor (`Circuit::AuditedSwap`).
+    pub audit: Option<AuditBinding>,
+}
+
+impl SwapInputs {
+    /// `[domain, root, root_slot, nullifier, amount_in, fee, relayer,
+    /// output_domain, output_commitment, min_amount_out, ext_data_hash]`,
+    /// followed, when `audit` is set, by the same seven elements as in
+    /// `PublicInputs`.
+    pub fn to_field_elements(&self) -> Vec<[u8; 32]> {
+        let mut inputs = vec![
+            self.domain,
+            self.root,
+            u64_to_field(self.root_slot),
+            self.nullifier,
+            u64_to_field(self.amount_in),
+            u64_to_field(self.fee),
+            pubkey_to_field(&self.relayer),
+            self.output_domain,
+            self.output_commitment,
+            u64_to_field(self.min_amount_out),
+            self.ext_data_hash,
+        ];
+        if let Some(audit) = &self.audit {
+            inputs.extend_from_slice(&audit.to_field_elements());
+        }
+        inputs
+    }
+}
+
+/// Everything a reward claim proof is bound to: the note spent under
+/// `nullifier` was in the tree at `start_root`, `new_commitment` is a note
+/// of the pool of the same value, and `reward_commitment` is a note of the
+/// reward pool worth its reward for the `end_slot - start_slot` slots
+/// rewarded between the two checkpoints (`RootCheckpoint::rewarded_slots`),
+/// less `fee`. The note's value and the reward amount stay private.
+///
+/// The `Circuit::Reward` constraints, with private witnesses the note
+/// (`amount`, blinding, owner key), its Merkle path, the new note opening
+/// (blinding, owner key) and the reward note opening (`reward_amount`,
+/// blinding, owner key):
+///
+/// 1. The note's leaf, computed exactly as the join-split circuit computes
+///    the leaf of an input note under `domain`, is on a path to
+///    `start_root`.
+/// 2. `nullifier` is that note's join-split nullifier; the claim spends
+///    it, so the note was unspent for the whole span.
+/// 3. `new_commitment` is the output-note commitment of the join-split
+///    circuit under `domain` for `amount`.
+/// 4. `start_slot < end_slot`, and `amount`, `reward` and `reward_amount`
+///    are range-checked to 64 bits.
+/// 5. `reward = floor(amount * (end_slot - start_slot) * rate / RATE_SCALE)`,
+///    proven with a 64-bit range-checked remainder below `RATE_SCALE`
+///    (`RewardProgram::reward`).
+/// 6. `fee <= reward` and `reward_amount = reward - fee`.
+/// 7. `reward_commitment` is the output-note commitment of the join-split
+///    circuit under `reward_domain` for `reward_amount`.
+/// 8. `relayer` and `ext_data_hash` are bound as in the join-split circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RewardInputs {
+    /// `domain_tag` of the pool.
+    pub domain: [u8; 32],
+    pub start_root: [u8; 32],
+    /// `rewarded_slots` of the start and end checkpoints.
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub rate: u64,
+    pub nullifier: [u8; 32],
+    pub new_commitment: [u8; 32],
+    /// `domain_tag` of the reward pool.
+    pub reward_domain: [u8; 32],
+    pub reward_commitment: [u8; 32],
+    pub fee: u64,
+    pub relayer: Pubkey,
+    pub ext_data_hash: [u8; 32],
+}
+
+impl RewardInputs {
+    /// `[domain, start_root, start_slot, end_slot, rate, nullifier,
+    /// new_commitment, reward_domain, reward_commitment, fee, relayer,
+    /// ext_data_hash]`
+    pub fn to_field_elements(&self) -> Vec<[u8; 32]> {
+        vec![
+            self.domain,
+            self.start_root,
+            u64_to_field(self.start_slot),
+            u64_to_field(self.end_slot),
+            u64_to_field(self.rate),
+            self.nullifier,
+            self.new_commitment,
+            self.reward_domain,
+            self.reward_commitment,
+            u64_to_field(self.fee),
+            pubkey_to_field(&self.relayer),
+            self.ext_data_hash,
+        ]
+    }
+}
+
+/// Everything an association proof is bound to: the note spent under
+/// `nullifier` is a leaf of the association set with root
+/// `association_root`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssociationInputs {
+    /// `domain_tag(program_id, pool, cluster)`.
+    pub domain: [u8; 32],
+    pub association_root: [u8; 32],
+    pub nullifier: [u8; 32],
+}
+
+impl AssociationInputs {
+    /// `[domain, association_root, nullifier]`
+    pub fn to_field_elements(&self) -> Vec<[u8; 32]> {
+        vec![self.domain, self.association_root, self.nullifier]
+    }
+}
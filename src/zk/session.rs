@@ -0,0 +1,86 @@
+//! Resumable proof verification.
+//!
+//! A pairing check plus the tree update of the instruction using it may
+//! not fit in one transaction, so a `VerificationSession` account runs the
+//! check in steps: the public inputs are folded into the prepared inputs a
+//! few at a time, then one `alt_bn128_pairing` call checks the proof.
+//! Transfer and withdraw then consume the verified session instead of
+//! checking the proof themselves.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+use crate::{error::HandCrankedError, instruction::ProofSystem};
+
+/// Uncompressed G1 point holding the prepared public inputs.
+pub const PREPARED_INPUTS_LEN: usize = 64;
+/// Public inputs folded in by one `PrepareInputs` step (a scalar
+/// multiplication and an addition each).
+pub const INPUTS_PER_STEP: usize = 4;
+
+/// Next step a session will run.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionStep {
+    /// Fold the public inputs from the given index on into the prepared
+    /// inputs; index 0 starts from the key's constant term.
+    PrepareInputs(u8),
+    /// The four-pair pairing check of the proof.
+    Pairing,
+    /// The proof checked out; the session can be consumed.
+    Verified,
+}
+
+/// Run `step` against the session's verifying key `key` and return the
+/// step that follows it. A failed pairing check is `InvalidProof`.
+pub fn advance(
+    system: ProofSystem,
+    key: &[u8],
+    proof: &[u8],
+    public_inputs: &[[u8; 32]],
+    step: SessionStep,
+    prepared_inputs: &mut [u8; PREPARED_INPUTS_LEN],
+) -> Result<SessionStep, ProgramError> {
+    if system != ProofSystem::Groth16 {
+        return Err(HandCrankedError::UnsupportedProofSystem.into());
+    }
+
+    #[cfg(feature = "zk-groth16")]
+    {
+        use crate::zk::groth16_circuit::{self, Groth16VerifyingKey};
+
+        let vk =
+            Groth16VerifyingKey::try_from_slice(key).map_err(|_| HandCrankedError::InvalidProof)?;
+        match step {
+            SessionStep::PrepareInputs(first) => {
+                let first = first as usize;
+                if first == 0 {
+                    if vk.ic.len() != public_inputs.len() + 1 {
+                        return Err(HandCrankedError::InvalidProof.into());
+                    }
+                    *prepared_inputs = vk.ic[0];
+                }
+                let end = (first + INPUTS_PER_STEP).min(public_inputs.len());
+                let inputs = public_inputs
+                    .get(first..end)
+                    .ok_or(HandCrankedError::InvalidInstruction)?;
+                groth16_circuit::fold_inputs(&vk, first, inputs, prepared_inputs)?;
+                if end < public_inputs.len() {
+                    Ok(SessionStep::PrepareInputs(end as u8))
+                } else {
+                    Ok(SessionStep::Pairing)
+                }
+            }
+            SessionStep::Pairing => {
+                groth16_circuit::pairing_check(&vk, proof, prepared_inputs)?;
+                Ok(SessionStep::Verified)
+            }
+            SessionStep::Verified => Ok(SessionStep::Verified),
+        }
+    }
+
+    #[cfg(not(feature = "zk-groth16"))]
+    {
+        let _ = (key, proof, public_inputs, step, prepared_inputs);
+        Err(HandCrankedError::UnsupportedProofSystem.into())
+    }
+}
@@ -5,10 +5,9 @@ use crate::error::HandCrankedError;
 
 pub fn verify_stark(
     proof_bytes: &[u8],
-    public_commitment: &[u8; 32],
-    nullifier_bytes: &[u8; 32],
+    public_inputs: &[[u8; 32]],
 ) -> Result<(), ProgramError> {
-    let _ = (proof_bytes, public_commitment, nullifier_bytes);
+    let _ = (proof_bytes, public_inputs);
 
     // TODO: Implement real STARK verification with winterfell.
     Err(HandCrankedError::UnsupportedProofSystem.into())
@@ -0,0 +1,344 @@
+#![cfg(feature = "test-utils")]
+
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    merkle::asset_deposit_leaf,
+    processor2::Processor,
+    state::{Cluster, DepositQueue, PoolParams, PoolState, VaultStats, MULTI_ASSET, NATIVE_ASSET},
+    zk::{public_inputs::asset_id, Circuit, MockVerifier},
+};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+
+const NOTE: u64 = 1_000_000_000;
+
+fn accept_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::accepting())
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+/// A SOL-holding pool: either a plain SOL pool or a multi-asset pool with
+/// a SOL vault.
+struct Pool {
+    program_id: Pubkey,
+    pool: Pubkey,
+    multi_asset: bool,
+}
+
+impl Pool {
+    fn pda(&self, seed: &[u8], key: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[seed, self.pool.as_ref(), key], &self.program_id).0
+    }
+
+    fn queue(&self) -> Pubkey {
+        Pubkey::find_program_address(&[b"queue", self.pool.as_ref()], &self.program_id).0
+    }
+
+    /// Multi-asset pools keep one vault per asset.
+    fn vault(&self) -> Pubkey {
+        match self.multi_asset {
+            true => self.pda(b"vault", NATIVE_ASSET.as_ref()),
+            false => {
+                Pubkey::find_program_address(&[b"vault", self.pool.as_ref()], &self.program_id).0
+            }
+        }
+    }
+
+    fn stats(&self) -> Pubkey {
+        self.pda(b"stats", NATIVE_ASSET.as_ref())
+    }
+
+    fn circuit(&self) -> Circuit {
+        match self.multi_asset {
+            true => Circuit::MultiAssetJoinSplit,
+            false => Circuit::JoinSplit,
+        }
+    }
+
+    fn verifying_key(&self) -> Pubkey {
+        let seeds: &[&[u8]] = &[
+            b"verifying-key",
+            self.pool.as_ref(),
+            &[ProofSystem::Groth16 as u8],
+            &[self.circuit() as u8],
+        ];
+        Pubkey::find_program_address(seeds, &self.program_id).0
+    }
+
+    /// Trailing accounts naming the asset moved: multi-asset pools name
+    /// SOL by the system program.
+    fn asset_accounts(&self) -> Vec<AccountMeta> {
+        match self.multi_asset {
+            true => vec![AccountMeta::new_readonly(NATIVE_ASSET, false)],
+            false => vec![],
+        }
+    }
+
+    fn instruction(&self, accounts: Vec<AccountMeta>, data: HandCrankedInstruction) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: data.try_to_vec().unwrap(),
+        }
+    }
+}
+
+async fn send(context: &mut ProgramTestContext, ix: Instruction) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    tx.sign(&[&context.payer], blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+async fn account_data(context: &mut ProgramTestContext, address: Pubkey) -> Vec<u8> {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+        .data
+}
+
+/// Open pool 0 holding `asset` with its verifying key. With `prefund`,
+/// lamports are sent to the pool, queue and vault addresses first.
+async fn setup(asset: Pubkey, prefund: bool) -> (ProgramTestContext, Pool) {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, processor!(accept_all));
+    let mut context = program_test.start_with_context().await;
+    let pool = Pool {
+        program_id,
+        pool: Pubkey::find_program_address(&[b"pool", &0u64.to_le_bytes()], &program_id).0,
+        multi_asset: asset == MULTI_ASSET,
+    };
+    let payer = context.payer.pubkey();
+
+    if prefund {
+        for address in [pool.pool, pool.queue(), pool.vault()] {
+            send(
+                &mut context,
+                system_instruction::transfer(&payer, &address, 1),
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    let init = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.vault(), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats(), false),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id: 0,
+            asset,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        },
+    );
+    send(&mut context, init).await.unwrap();
+    let key = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit: pool.circuit(),
+            len: 1,
+        },
+    );
+    send(&mut context, key).await.unwrap();
+    let finalize = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key(), false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        HandCrankedInstruction::FinalizeVerifyingKey,
+    );
+    send(&mut context, finalize).await.unwrap();
+    (context, pool)
+}
+
+fn initialize_asset_vault(pool: &Pool, payer: Pubkey) -> Instruction {
+    pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.pda(b"vault", NATIVE_ASSET.as_ref()), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats(), false),
+            AccountMeta::new_readonly(NATIVE_ASSET, false),
+        ],
+        HandCrankedInstruction::InitializeAssetVault,
+    )
+}
+
+async fn deposit(context: &mut ProgramTestContext, pool: &Pool, commitment: [u8; 32]) {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(pool.pool, false),
+        AccountMeta::new(pool.queue(), false),
+        AccountMeta::new(context.payer.pubkey(), true),
+        AccountMeta::new(pool.pda(b"note", &commitment), false),
+        AccountMeta::new(pool.vault(), false),
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        AccountMeta::new(pool.stats(), false),
+    ];
+    accounts.extend(pool.asset_accounts());
+    let ix = pool.instruction(
+        accounts,
+        HandCrankedInstruction::Deposit {
+            amount: NOTE,
+            commitment,
+            encrypted_note: vec![],
+        },
+    );
+    send(context, ix).await.unwrap();
+}
+
+/// Crank the queue and withdraw a whole note to `recipient`.
+async fn withdraw(
+    context: &mut ProgramTestContext,
+    pool: &Pool,
+    nullifier: [u8; 32],
+    recipient: Pubkey,
+) {
+    let crank = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue(), false),
+            AccountMeta::new(context.payer.pubkey(), false),
+        ],
+        HandCrankedInstruction::Crank { max_leaves: 8 },
+    );
+    send(context, crank).await.unwrap();
+    let root = PoolState::unpack(&account_data(context, pool.pool).await)
+        .unwrap()
+        .tree
+        .root();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(pool.pool, false),
+        AccountMeta::new(pool.pda(b"nullifier", &nullifier), false),
+        AccountMeta::new(pool.vault(), false),
+        AccountMeta::new(recipient, false),
+        AccountMeta::new(context.payer.pubkey(), true),
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        AccountMeta::new(pool.stats(), false),
+        AccountMeta::new_readonly(pool.verifying_key(), false),
+    ];
+    accounts.extend(pool.asset_accounts());
+    let data = HandCrankedInstruction::Withdraw {
+        proof_system: ProofSystem::Groth16,
+        proof: ProofSource::Inline(vec![0; 128]),
+        root,
+        nullifier,
+        amount: NOTE,
+        fee: 0,
+        association: None,
+        callback: None,
+    };
+    send(context, pool.instruction(accounts, data))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_multi_asset_pool_deposits_and_withdraws_through_asset_vault() {
+    let (mut context, pool) = setup(MULTI_ASSET, false).await;
+    let payer = context.payer.pubkey();
+
+    send(&mut context, initialize_asset_vault(&pool, payer))
+        .await
+        .unwrap();
+    assert_eq!(
+        send(&mut context, initialize_asset_vault(&pool, payer))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::AlreadyInitialized)
+    );
+
+    // The leaf commits to the asset of the deposit.
+    deposit(&mut context, &pool, [1; 32]).await;
+    let queue = DepositQueue::unpack(&account_data(&mut context, pool.queue()).await).unwrap();
+    assert_eq!(
+        queue.pending,
+        vec![asset_deposit_leaf(&asset_id(&NATIVE_ASSET), NOTE, &[1; 32]).unwrap()]
+    );
+
+    let recipient = Pubkey::new_unique();
+    withdraw(&mut context, &pool, [2; 32], recipient).await;
+    assert_eq!(
+        context.banks_client.get_balance(recipient).await.unwrap(),
+        NOTE
+    );
+    let stats = VaultStats::unpack(&account_data(&mut context, pool.stats()).await).unwrap();
+    assert_eq!(stats.asset, NATIVE_ASSET);
+    assert_eq!(
+        (stats.stats.liability(), stats.stats.withdraw_count),
+        (0, 1)
+    );
+}
+
+#[tokio::test]
+async fn test_asset_vaults_are_for_multi_asset_pools_only() {
+    let (mut context, pool) = setup(NATIVE_ASSET, false).await;
+    let payer = context.payer.pubkey();
+    assert_eq!(
+        send(&mut context, initialize_asset_vault(&pool, payer))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+}
+
+#[tokio::test]
+async fn test_prefunded_pool_and_vault_addresses_still_initialize() {
+    let (mut context, pool) = setup(MULTI_ASSET, true).await;
+    let payer = context.payer.pubkey();
+    send(&mut context, initialize_asset_vault(&pool, payer))
+        .await
+        .unwrap();
+
+    deposit(&mut context, &pool, [1; 32]).await;
+    let recipient = Pubkey::new_unique();
+    withdraw(&mut context, &pool, [2; 32], recipient).await;
+    assert_eq!(
+        context.banks_client.get_balance(recipient).await.unwrap(),
+        NOTE
+    );
+}
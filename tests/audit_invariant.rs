@@ -0,0 +1,300 @@
+#![cfg(feature = "test-utils")]
+
+use borsh::BorshSerialize;
+
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    processor2::Processor,
+    state::{Cluster, PoolParams, PoolState, VaultStats, NATIVE_ASSET},
+    zk::{Circuit, MockVerifier},
+};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult};
+use solana_program_test::*;
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+};
+
+const NOTE: u64 = 1_000_000_000;
+
+fn accept_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::accepting())
+}
+
+struct Pool {
+    program_id: Pubkey,
+    pool: Pubkey,
+    queue: Pubkey,
+    vault: Pubkey,
+    stats: Pubkey,
+    verifying_key: Pubkey,
+}
+
+impl Pool {
+    fn new(program_id: Pubkey) -> Self {
+        let pool = Pubkey::find_program_address(&[b"pool", &0u64.to_le_bytes()], &program_id).0;
+        let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &program_id).0;
+        Self {
+            program_id,
+            pool,
+            queue: pda(&[b"queue", pool.as_ref()]),
+            vault: pda(&[b"vault", pool.as_ref()]),
+            stats: pda(&[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()]),
+            verifying_key: pda(&[
+                b"verifying-key",
+                pool.as_ref(),
+                &[ProofSystem::Groth16 as u8],
+                &[Circuit::JoinSplit as u8],
+            ]),
+        }
+    }
+
+    fn pda(&self, seed: &[u8], key: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[seed, self.pool.as_ref(), key], &self.program_id).0
+    }
+
+    fn instruction(&self, accounts: Vec<AccountMeta>, data: HandCrankedInstruction) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: data.try_to_vec().unwrap(),
+        }
+    }
+}
+
+async fn send(context: &mut ProgramTestContext, ix: Instruction) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    tx.sign(&[&context.payer], blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+async fn audit(context: &mut ProgramTestContext, pool: &Pool) -> Result<(), BanksClientError> {
+    let ix = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new_readonly(pool.vault, false),
+            AccountMeta::new_readonly(pool.stats, false),
+        ],
+        HandCrankedInstruction::AuditInvariant,
+    );
+    send(context, ix).await
+}
+
+async fn stats(context: &mut ProgramTestContext, pool: &Pool) -> VaultStats {
+    let account = context
+        .banks_client
+        .get_account(pool.stats)
+        .await
+        .unwrap()
+        .unwrap();
+    VaultStats::unpack(&account.data).unwrap()
+}
+
+async fn deposit(context: &mut ProgramTestContext, pool: &Pool, commitment: [u8; 32]) {
+    let payer = context.payer.pubkey();
+    let ix = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.pda(b"note", &commitment), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::Deposit {
+            amount: NOTE,
+            commitment,
+            encrypted_note: vec![],
+        },
+    );
+    send(context, ix).await.unwrap();
+}
+
+/// Withdraw a whole note to `recipient`, delayed or not.
+async fn withdraw(
+    context: &mut ProgramTestContext,
+    pool: &Pool,
+    nullifier: [u8; 32],
+    recipient: Pubkey,
+    delayed: bool,
+) {
+    let crank = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(context.payer.pubkey(), false),
+        ],
+        HandCrankedInstruction::Crank { max_leaves: 8 },
+    );
+    send(context, crank).await.unwrap();
+    let pool_account = context
+        .banks_client
+        .get_account(pool.pool)
+        .await
+        .unwrap()
+        .unwrap();
+    let root = PoolState::unpack(&pool_account.data).unwrap().tree.root();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(pool.pool, false),
+        AccountMeta::new(pool.pda(b"nullifier", &nullifier), false),
+        AccountMeta::new(pool.vault, false),
+        AccountMeta::new(recipient, false),
+        AccountMeta::new(context.payer.pubkey(), true),
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        AccountMeta::new(pool.stats, false),
+    ];
+    let proof = ProofSource::Inline(vec![0; 128]);
+    let data = match delayed {
+        true => {
+            accounts.push(AccountMeta::new(pool.pda(b"pending", &nullifier), false));
+            accounts.push(AccountMeta::new_readonly(pool.verifying_key, false));
+            HandCrankedInstruction::RequestWithdraw {
+                proof_system: ProofSystem::Groth16,
+                proof,
+                root,
+                nullifier,
+                amount: NOTE,
+                fee: 0,
+                association: None,
+            }
+        }
+        false => {
+            accounts.push(AccountMeta::new_readonly(pool.verifying_key, false));
+            HandCrankedInstruction::Withdraw {
+                proof_system: ProofSystem::Groth16,
+                proof,
+                root,
+                nullifier,
+                amount: NOTE,
+                fee: 0,
+                association: None,
+                callback: None,
+            }
+        }
+    };
+    send(context, pool.instruction(accounts, data))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_audit_holds_through_deposits_and_withdrawals() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, processor!(accept_all));
+    let mut context = program_test.start_with_context().await;
+    let pool = Pool::new(program_id);
+    let payer = context.payer.pubkey();
+
+    let init = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id: 0,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        },
+    );
+    send(&mut context, init).await.unwrap();
+    let key = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit: Circuit::JoinSplit,
+            len: 1,
+        },
+    );
+    send(&mut context, key).await.unwrap();
+    let finalize = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        HandCrankedInstruction::FinalizeVerifyingKey,
+    );
+    send(&mut context, finalize).await.unwrap();
+    audit(&mut context, &pool).await.unwrap();
+
+    deposit(&mut context, &pool, [1; 32]).await;
+    deposit(&mut context, &pool, [2; 32]).await;
+    audit(&mut context, &pool).await.unwrap();
+    assert_eq!(stats(&mut context, &pool).await.stats.liability(), 2 * NOTE);
+
+    let recipient = Pubkey::new_unique();
+    withdraw(&mut context, &pool, [3; 32], recipient, false).await;
+    audit(&mut context, &pool).await.unwrap();
+    assert_eq!(stats(&mut context, &pool).await.stats.liability(), NOTE);
+
+    // A delayed withdrawal stays a liability until it is executed.
+    withdraw(&mut context, &pool, [4; 32], recipient, true).await;
+    audit(&mut context, &pool).await.unwrap();
+    let pending = stats(&mut context, &pool).await.stats;
+    assert_eq!((pending.total_shielded, pending.total_pending), (0, NOTE));
+
+    let execute = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.pda(b"pending", &[4; 32]), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::ExecuteWithdraw,
+    );
+    send(&mut context, execute).await.unwrap();
+    audit(&mut context, &pool).await.unwrap();
+    assert_eq!(stats(&mut context, &pool).await.stats.liability(), 0);
+    assert_eq!(stats(&mut context, &pool).await.stats.withdraw_count, 2);
+
+    // Owing more than the vault holds trips the audit.
+    let mut account = context
+        .banks_client
+        .get_account(pool.stats)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut inflated = VaultStats::unpack(account.data()).unwrap();
+    inflated.stats.total_shielded = 1;
+    inflated.serialize(&mut &mut account.data[..]).unwrap();
+    context.set_account(&pool.stats, &AccountSharedData::from(account));
+    assert_eq!(
+        audit(&mut context, &pool).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(HandCrankedError::InvariantViolated as u32)
+        )
+    );
+}
@@ -0,0 +1,69 @@
+use hand_cranked_privacy::{
+    cpi::{
+        deposit_instruction, find_asset_vault_address, find_stats_address, find_vault_address,
+        TokenKeys,
+    },
+    state::NATIVE_ASSET,
+};
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey, system_program};
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+const POOL: Pubkey = Pubkey::new_from_array([8; 32]);
+
+fn deposit_accounts(multi_asset: bool, token: Option<&TokenKeys>) -> Vec<AccountMeta> {
+    deposit_instruction(
+        &PROGRAM_ID,
+        &POOL,
+        &Pubkey::new_unique(),
+        1_000,
+        [1; 32],
+        vec![],
+        multi_asset,
+        token,
+    )
+    .accounts
+}
+
+#[test]
+fn test_single_asset_deposits_use_the_pool_vault() {
+    let accounts = deposit_accounts(false, None);
+    assert_eq!(accounts.len(), 7);
+    assert_eq!(accounts[4].pubkey, find_vault_address(&PROGRAM_ID, &POOL).0);
+}
+
+#[test]
+fn test_multi_asset_deposits_use_the_asset_vault() {
+    // SOL is named by the system program after the fixed accounts.
+    let accounts = deposit_accounts(true, None);
+    assert_eq!(
+        accounts[4].pubkey,
+        find_asset_vault_address(&PROGRAM_ID, &POOL, &NATIVE_ASSET).0
+    );
+    assert_eq!(
+        accounts[6].pubkey,
+        find_stats_address(&PROGRAM_ID, &POOL, &NATIVE_ASSET).0
+    );
+    assert_eq!(
+        accounts[7..],
+        [AccountMeta::new_readonly(system_program::id(), false)]
+    );
+
+    let token = TokenKeys {
+        mint: Pubkey::new_unique(),
+        token_program: spl_token::id(),
+        token_account: Pubkey::new_unique(),
+    };
+    let accounts = deposit_accounts(true, Some(&token));
+    assert_eq!(
+        accounts[4].pubkey,
+        find_asset_vault_address(&PROGRAM_ID, &POOL, &token.mint).0
+    );
+    assert_eq!(
+        accounts[7..],
+        [
+            AccountMeta::new_readonly(token.mint, false),
+            AccountMeta::new_readonly(token.token_program, false),
+            AccountMeta::new(token.token_account, false),
+        ]
+    );
+}
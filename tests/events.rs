@@ -0,0 +1,87 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    client::{decode_event, decode_events, DecodeError},
+    events::{DepositEvent, HandCrankedEvent, NullifierSpentEvent, EVENT_VERSION},
+};
+use solana_program::pubkey::Pubkey;
+
+fn program_data(event: &HandCrankedEvent) -> String {
+    format!(
+        "Program data: {} {}",
+        STANDARD.encode([EVENT_VERSION]),
+        STANDARD.encode(event.try_to_vec().unwrap())
+    )
+}
+
+#[test]
+fn test_decode_event_roundtrip() {
+    let event = HandCrankedEvent::Deposit(DepositEvent {
+        pool: Pubkey::new_unique(),
+        leaf_index: 7,
+        commitment: [3u8; 32],
+        ciphertext: vec![1, 2, 3],
+    });
+
+    assert_eq!(decode_event(&program_data(&event)), Ok(Some(event)));
+    assert_eq!(decode_event("Program log: Instruction: Deposit"), Ok(None));
+}
+
+#[test]
+fn test_decode_event_reports_other_versions() {
+    let event = HandCrankedEvent::NullifierSpent(NullifierSpentEvent {
+        pool: Pubkey::new_unique(),
+        nullifier: [1u8; 32],
+    });
+    let old = format!(
+        "Program data: {} {}",
+        STANDARD.encode([EVENT_VERSION - 1]),
+        STANDARD.encode(event.try_to_vec().unwrap())
+    );
+    assert_eq!(
+        decode_event(&old),
+        Err(DecodeError::UnsupportedVersion(EVENT_VERSION - 1))
+    );
+    assert_eq!(
+        decode_event("Program data: not-base64"),
+        Err(DecodeError::Malformed)
+    );
+
+    let program_id = Pubkey::new_unique();
+    let logs = vec![
+        format!("Program {} invoke [1]", program_id),
+        program_data(&event),
+        old,
+        format!("Program {} success", program_id),
+    ];
+    assert_eq!(
+        decode_events(&program_id, &logs),
+        Err(DecodeError::UnsupportedVersion(EVENT_VERSION - 1))
+    );
+}
+
+#[test]
+fn test_decode_events_ignores_other_programs() {
+    let program_id = Pubkey::new_unique();
+    let other_id = Pubkey::new_unique();
+    let pool = Pubkey::new_unique();
+    let ours = HandCrankedEvent::NullifierSpent(NullifierSpentEvent {
+        pool,
+        nullifier: [9u8; 32],
+    });
+    let theirs = HandCrankedEvent::NullifierSpent(NullifierSpentEvent {
+        pool,
+        nullifier: [8u8; 32],
+    });
+
+    let logs = vec![
+        format!("Program {} invoke [1]", program_id),
+        format!("Program {} invoke [2]", other_id),
+        program_data(&theirs),
+        format!("Program {} success", other_id),
+        program_data(&ours),
+        format!("Program {} success", program_id),
+    ];
+
+    assert_eq!(decode_events(&program_id, &logs), Ok(vec![ours]));
+}
@@ -0,0 +1,98 @@
+#![cfg(all(feature = "zk-groth16", feature = "test-utils"))]
+
+use ark_bn254::{Bn254, Fr};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::Groth16;
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::ProofSystem,
+    zk::{
+        groth16_circuit::{Groth16VerifyingKey, PrivateTransferCircuit},
+        groth16_encoding::encode_groth16_proof,
+        session::{advance, SessionStep, PREPARED_INPUTS_LEN},
+        Circuit, DefaultVerifier, ProofVerifier,
+    },
+};
+use solana_program::program_error::ProgramError;
+
+fn scalar(value: u64) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Fr::from(value).into_bigint().to_bytes_le());
+    out
+}
+
+/// A real proof of the toy circuit (`commitment == nullifier`) for 5, and
+/// its key as a verifying key account holds it.
+fn proof() -> (Vec<u8>, Vec<u8>) {
+    let mut rng = ark_std::test_rng();
+    let circuit = || PrivateTransferCircuit {
+        commitment: Some(Fr::from(5u64)),
+        nullifier: Some(Fr::from(5u64)),
+    };
+    let pk =
+        Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit(), &mut rng).unwrap();
+    let proof =
+        Groth16::<Bn254>::create_random_proof_with_reduction(circuit(), &pk, &mut rng).unwrap();
+    (
+        Groth16VerifyingKey::from(&pk.vk).try_to_vec().unwrap(),
+        encode_groth16_proof(&proof).to_vec(),
+    )
+}
+
+#[test]
+fn test_verifier_checks_real_proofs() {
+    let invalid: ProgramError = HandCrankedError::InvalidProof.into();
+    let (key, proof) = proof();
+    let verify = |proof: &[u8], inputs: &[[u8; 32]]| {
+        DefaultVerifier.verify(
+            ProofSystem::Groth16,
+            Circuit::JoinSplit,
+            &key,
+            proof,
+            inputs,
+        )
+    };
+
+    assert_eq!(verify(&proof, &[scalar(5), scalar(5)]), Ok(()));
+    assert_eq!(
+        verify(&proof, &[scalar(5), scalar(6)]),
+        Err(invalid.clone())
+    );
+    // One input short of the key.
+    assert_eq!(verify(&proof, &[scalar(5)]), Err(invalid.clone()));
+
+    // `C` swapped for `A`.
+    let mut forged = proof.clone();
+    forged.copy_within(..32, 96);
+    assert_eq!(verify(&forged, &[scalar(5), scalar(5)]), Err(invalid));
+}
+
+#[test]
+fn test_session_steps_reach_the_same_verdict() {
+    let (key, proof) = proof();
+
+    let run = |inputs: &[[u8; 32]]| {
+        let mut step = SessionStep::PrepareInputs(0);
+        let mut prepared = [0u8; PREPARED_INPUTS_LEN];
+        let mut steps = 0;
+        while step != SessionStep::Verified {
+            step = advance(
+                ProofSystem::Groth16,
+                &key,
+                &proof,
+                inputs,
+                step,
+                &mut prepared,
+            )?;
+            steps += 1;
+        }
+        Ok::<_, ProgramError>(steps)
+    };
+
+    assert_eq!(run(&[scalar(5), scalar(5)]), Ok(2));
+    assert_eq!(
+        run(&[scalar(5), scalar(6)]),
+        Err(HandCrankedError::InvalidProof.into())
+    );
+}
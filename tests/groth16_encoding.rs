@@ -0,0 +1,99 @@
+#![cfg(feature = "zk-groth16")]
+
+use ark_bn254::{Fq2, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_groth16::Proof;
+use ark_serialize::CanonicalSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    zk::{
+        groth16_circuit::{g1_bytes, g2_bytes, verify_groth16, Groth16VerifyingKey},
+        groth16_encoding::{decode_groth16_proof, encode_groth16_proof, GROTH16_PROOF_LEN},
+    },
+};
+use solana_program::program_error::ProgramError;
+
+fn proof() -> Proof<ark_bn254::Bn254> {
+    Proof {
+        a: G1Affine::generator(),
+        b: G2Affine::generator(),
+        c: (G1Affine::generator() + G1Affine::generator()).into(),
+    }
+}
+
+/// A key without public inputs, to run proofs through the on-chain
+/// decoder of `verify_groth16`.
+fn key() -> Groth16VerifyingKey {
+    Groth16VerifyingKey {
+        alpha_g1: g1_bytes(&G1Affine::generator()),
+        beta_g2: g2_bytes(&G2Affine::generator()),
+        gamma_g2: g2_bytes(&G2Affine::generator()),
+        delta_g2: g2_bytes(&G2Affine::generator()),
+        ic: vec![g1_bytes(&G1Affine::generator())],
+    }
+}
+
+/// A point on the G2 curve outside the prime-order subgroup.
+fn g2_outside_subgroup() -> G2Affine {
+    (1u64..)
+        .filter_map(|x| G2Affine::get_point_from_x_unchecked(Fq2::from(x), false))
+        .find(|point| !point.is_in_correct_subgroup_assuming_on_curve())
+        .unwrap()
+}
+
+#[test]
+fn test_proof_round_trips() {
+    let bytes = encode_groth16_proof(&proof());
+    assert_eq!(bytes.len(), GROTH16_PROOF_LEN);
+    assert_eq!(decode_groth16_proof(&bytes).unwrap(), proof());
+}
+
+#[test]
+fn test_rejects_malformed_proofs() {
+    let invalid: ProgramError = HandCrankedError::InvalidProof.into();
+    let bytes = encode_groth16_proof(&proof());
+
+    // Wrong length.
+    assert_eq!(decode_groth16_proof(&bytes[..96]), Err(invalid.clone()));
+
+    // `x` = 4 has no point on y^2 = x^3 + 3.
+    let mut off_curve = bytes;
+    off_curve[..32].copy_from_slice(&[0; 32]);
+    off_curve[0] = 4;
+    assert_eq!(decode_groth16_proof(&off_curve), Err(invalid.clone()));
+
+    // `x` at or above the field modulus.
+    let mut non_canonical = bytes;
+    non_canonical[..31].copy_from_slice(&[0xff; 31]);
+    non_canonical[31] = 0x3f;
+    assert_eq!(decode_groth16_proof(&non_canonical), Err(invalid.clone()));
+
+    // Infinity flag with stray coordinate bits.
+    let mut stray_bits = bytes;
+    stray_bits[96..128].copy_from_slice(&[0; 32]);
+    stray_bits[96] = 1;
+    stray_bits[127] = 0x40;
+    assert_eq!(decode_groth16_proof(&stray_bits), Err(invalid.clone()));
+
+    // `B` on the curve but outside the subgroup.
+    let mut outside_subgroup = bytes;
+    g2_outside_subgroup()
+        .serialize_compressed(&mut outside_subgroup[32..96])
+        .unwrap();
+    assert_eq!(
+        decode_groth16_proof(&outside_subgroup),
+        Err(invalid.clone())
+    );
+
+    // The on-chain verifier decodes with the `alt_bn128` syscalls instead,
+    // and must turn down the same encodings.
+    for malformed in [
+        &bytes[..96],
+        &off_curve,
+        &non_canonical,
+        &stray_bits,
+        &outside_subgroup,
+    ] {
+        assert_eq!(verify_groth16(&key(), malformed, &[]), Err(invalid.clone()));
+    }
+}
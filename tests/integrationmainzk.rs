@@ -1,6 +1,7 @@
 use hand_cranked_privacy::{
     instruction::HandCrankedInstruction,
-    processor::Processor,
+    processor2::Processor,
+    state::{Cluster, PoolParams, PoolState, NATIVE_ASSET},
 };
 use solana_program_test::*;
 use solana_sdk::{
@@ -22,18 +23,44 @@ async fn test_initialize_and_deposit() {
     let mut context = program_test.start_with_context().await;
 
     let payer = &context.payer;
-    let global_state = Pubkey::find_program_address(&[b"global-state"], &program_id).0;
+    let pool_id = 7u64;
+    let pool = Pubkey::find_program_address(&[b"pool", &pool_id.to_le_bytes()], &program_id).0;
+    let queue = Pubkey::find_program_address(&[b"queue", pool.as_ref()], &program_id).0;
+    let vault = Pubkey::find_program_address(&[b"vault", pool.as_ref()], &program_id).0;
+    let stats = Pubkey::find_program_address(
+        &[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()],
+        &program_id,
+    )
+    .0;
     let system_program = solana_sdk::system_program::id();
 
     // Initialize
     let init_ix = Instruction {
         program_id,
         accounts: vec![
-            AccountMeta::new(global_state, false),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(queue, false),
             AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(vault, false),
             AccountMeta::new_readonly(system_program, false),
+            AccountMeta::new(stats, false),
         ],
-        data: HandCrankedInstruction::Initialize.try_to_vec().unwrap(),
+        data: HandCrankedInstruction::InitializePool {
+            pool_id,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 150,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        }
+        .try_to_vec()
+        .unwrap(),
     };
 
     let mut tx = Transaction::new_with_payer(&[init_ix], Some(&payer.pubkey()));
@@ -42,22 +69,66 @@ async fn test_initialize_and_deposit() {
 
     // Deposit
     let commitment = [1u8; 32];
-    let note_pda = Pubkey::find_program_address(&[b"note", &commitment], &program_id).0;
+    let note_pda =
+        Pubkey::find_program_address(&[b"note", pool.as_ref(), &commitment], &program_id).0;
 
     let deposit_ix = Instruction {
         program_id,
         accounts: vec![
-            AccountMeta::new(global_state, false),
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(queue, false),
             AccountMeta::new(payer.pubkey(), true),
             AccountMeta::new(note_pda, false),
+            AccountMeta::new(vault, false),
             AccountMeta::new_readonly(system_program, false),
+            AccountMeta::new(stats, false),
         ],
-        data: HandCrankedInstruction::Deposit { commitment }
-            .try_to_vec()
-            .unwrap(),
+        data: HandCrankedInstruction::Deposit {
+            amount: 1_000_000_000,
+            commitment,
+            encrypted_note: vec![],
+        }
+        .try_to_vec()
+        .unwrap(),
     };
 
     let mut tx2 = Transaction::new_with_payer(&[deposit_ix], Some(&payer.pubkey()));
     tx2.sign(&[payer], context.last_blockhash);
     context.banks_client.process_transaction(tx2).await.unwrap();
+
+    // Crank the queued leaf into the tree
+    let crank_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(queue, false),
+            AccountMeta::new(payer.pubkey(), false),
+        ],
+        data: HandCrankedInstruction::Crank { max_leaves: 8 }
+            .try_to_vec()
+            .unwrap(),
+    };
+
+    let mut tx3 = Transaction::new_with_payer(&[crank_ix], Some(&payer.pubkey()));
+    tx3.sign(&[payer], context.last_blockhash);
+    context.banks_client.process_transaction(tx3).await.unwrap();
+
+    // The vault covers everything shielded so far
+    let audit_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new_readonly(stats, false),
+        ],
+        data: HandCrankedInstruction::AuditInvariant.try_to_vec().unwrap(),
+    };
+
+    let mut tx4 = Transaction::new_with_payer(&[audit_ix], Some(&payer.pubkey()));
+    tx4.sign(&[payer], context.last_blockhash);
+    context.banks_client.process_transaction(tx4).await.unwrap();
+
+    let pool_account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let pool_state = PoolState::unpack(&pool_account.data).unwrap();
+    assert_eq!(pool_state.tree.next_index, 1);
 }
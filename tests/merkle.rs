@@ -0,0 +1,167 @@
+use hand_cranked_privacy::merkle::{
+    hash_pair, subtree_root, IncrementalMerkleTree, MerkleTree, AGE_CHECKPOINTS,
+    AGE_CHECKPOINT_INTERVAL, ROOT_HISTORY_SIZE, TREE_DEPTH, ZERO_HASHES,
+};
+
+fn root_from_path(leaf: [u8; 32], index: u64, path: &[[u8; 32]; TREE_DEPTH]) -> [u8; 32] {
+    let mut node = leaf;
+    for (level, sibling) in path.iter().enumerate() {
+        node = if (index >> level) & 1 == 0 {
+            hash_pair(&node, sibling).unwrap()
+        } else {
+            hash_pair(sibling, &node).unwrap()
+        };
+    }
+    node
+}
+
+#[test]
+fn test_empty_tree_root() {
+    let tree = MerkleTree::new().unwrap();
+    assert_eq!(tree.root(), ZERO_HASHES[TREE_DEPTH]);
+    assert!(tree.path(0).is_none());
+}
+
+#[test]
+fn test_zero_hashes_are_hashes_of_empty_subtrees() {
+    assert_eq!(ZERO_HASHES[0], [0; 32]);
+    for level in 1..=TREE_DEPTH {
+        assert_eq!(
+            ZERO_HASHES[level],
+            hash_pair(&ZERO_HASHES[level - 1], &ZERO_HASHES[level - 1]).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_paths_verify_against_root() {
+    let mut tree = MerkleTree::new().unwrap();
+    let leaves: Vec<[u8; 32]> = (1..=5u8)
+        .map(|i| {
+            let mut leaf = [i; 32];
+            leaf[31] = 0;
+            leaf
+        })
+        .collect();
+
+    for (i, leaf) in leaves.iter().enumerate() {
+        assert_eq!(tree.insert(*leaf).unwrap(), i as u64);
+    }
+
+    for (i, leaf) in leaves.iter().enumerate() {
+        let path = tree.path(i as u64).unwrap();
+        assert_eq!(root_from_path(*leaf, i as u64, &path), tree.root());
+    }
+}
+
+#[test]
+fn test_incremental_tree_matches_full_tree() {
+    let mut full = MerkleTree::new().unwrap();
+    let mut incremental = IncrementalMerkleTree::new().unwrap();
+    assert_eq!(incremental.root(), full.root());
+
+    let mut first_root = None;
+    for i in 0..(ROOT_HISTORY_SIZE as u8 + 2) {
+        let leaf = [i; 32];
+        assert_eq!(
+            incremental.insert(&leaf, i as u64).unwrap(),
+            full.insert(leaf).unwrap()
+        );
+        assert_eq!(incremental.root(), full.root());
+        assert!(incremental.is_known_root(&full.root()));
+        first_root.get_or_insert(full.root());
+    }
+
+    // The oldest roots fall out of the history.
+    assert!(!incremental.is_known_root(&first_root.unwrap()));
+}
+
+#[test]
+fn test_insert_subtree_matches_leaf_inserts() {
+    let mut full = MerkleTree::new().unwrap();
+    let mut batched = IncrementalMerkleTree::new().unwrap();
+
+    // Four single leaves leave the next index aligned for a batch of four.
+    for i in 0..4u8 {
+        full.insert([i; 32]).unwrap();
+        batched.insert(&[i; 32], 0).unwrap();
+    }
+
+    let leaves: Vec<[u8; 32]> = (4..8u8).map(|i| [i; 32]).collect();
+    for leaf in &leaves {
+        full.insert(*leaf).unwrap();
+    }
+    let root = subtree_root(&leaves).unwrap();
+    assert_eq!(batched.insert_subtree(&root, 2, 0).unwrap(), 4);
+    assert_eq!(batched.next_index, 8);
+    assert_eq!(batched.root(), full.root());
+
+    // Single inserts keep working after a batch.
+    full.insert([8; 32]).unwrap();
+    batched.insert(&[8; 32], 0).unwrap();
+    assert_eq!(batched.root(), full.root());
+
+    // Index 9 is not aligned to a batch of two.
+    assert!(batched.insert_subtree(&root, 1, 0).is_err());
+}
+
+#[test]
+fn test_insert_leaves_records_one_root() {
+    let mut full = MerkleTree::new().unwrap();
+    let mut tree = IncrementalMerkleTree::new().unwrap();
+    tree.insert(&[1; 32], 0).unwrap();
+    full.insert([1; 32]).unwrap();
+    let old_root = tree.root();
+
+    let leaves: Vec<[u8; 32]> = (2..ROOT_HISTORY_SIZE as u8 + 4).map(|i| [i; 32]).collect();
+    for leaf in &leaves {
+        full.insert(*leaf).unwrap();
+    }
+    assert_eq!(tree.insert_leaves(&leaves, 0).unwrap(), 1);
+    assert_eq!(tree.next_index, 1 + leaves.len() as u64);
+    assert_eq!(tree.root(), full.root());
+    assert!(tree.is_known_root(&old_root));
+    assert_eq!(tree.current_root_index, 2);
+
+    assert_eq!(tree.insert_leaves(&[], 0).unwrap(), tree.next_index);
+    assert_eq!(tree.current_root_index, 2);
+}
+
+#[test]
+fn test_roots_are_stamped_with_their_slot() {
+    let mut tree = IncrementalMerkleTree::new().unwrap();
+    tree.insert(&[1; 32], 100).unwrap();
+    let first = tree.root();
+    tree.insert(&[2; 32], 250).unwrap();
+
+    assert_eq!(tree.root_slot(&first), Some(100));
+    assert_eq!(tree.root_slot(&tree.root()), Some(250));
+    assert_eq!(tree.root_slot(&[7; 32]), None);
+}
+
+#[test]
+fn test_age_checkpoints_outlive_root_history() {
+    let mut tree = IncrementalMerkleTree::new().unwrap();
+    tree.insert(&[1; 32], 100).unwrap();
+    tree.insert(&[2; 32], 200).unwrap();
+    let old = tree.root();
+
+    // Flooding one epoch cycles the root history but only replaces the
+    // checkpoint of that epoch.
+    let next_epoch = AGE_CHECKPOINT_INTERVAL;
+    for i in 0..ROOT_HISTORY_SIZE as u8 {
+        tree.insert(&[i + 3; 32], next_epoch + i as u64).unwrap();
+    }
+    assert_eq!(tree.root_slot(&old), Some(200));
+    assert_eq!(tree.root_slot(&tree.root()), Some(next_epoch + 29));
+
+    // Each later epoch with an insertion takes one checkpoint.
+    for epoch in 2..AGE_CHECKPOINTS as u64 {
+        tree.insert(&[9; 32], epoch * AGE_CHECKPOINT_INTERVAL)
+            .unwrap();
+    }
+    assert_eq!(tree.root_slot(&old), Some(200));
+    tree.insert(&[9; 32], AGE_CHECKPOINTS as u64 * AGE_CHECKPOINT_INTERVAL)
+        .unwrap();
+    assert_eq!(tree.root_slot(&old), None);
+}
@@ -0,0 +1,38 @@
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    state::{Cluster, PoolParams, PoolState, NATIVE_ASSET},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn params() -> PoolParams {
+    PoolParams {
+        cluster: Cluster::Localnet,
+        crank_reward: 5_000,
+        min_withdraw_delay: 0,
+        max_withdraw_delay: 150,
+        curator: Pubkey::default(),
+        auditor: None,
+        min_note_age: None,
+    }
+}
+
+#[test]
+fn test_older_pool_versions_are_rejected() {
+    let pool = PoolState::new(1, Pubkey::new_unique(), NATIVE_ASSET, None, params()).unwrap();
+    let mut data = pool.try_to_vec().unwrap();
+    data.resize(PoolState::LEN, 0);
+    assert_eq!(PoolState::unpack(&data).unwrap(), pool);
+
+    data[1] = PoolState::VERSION - 1;
+    assert_eq!(
+        PoolState::unpack(&data).unwrap_err(),
+        ProgramError::Custom(HandCrankedError::UnsupportedVersion as u32)
+    );
+
+    // Never-initialized accounts are not a version problem.
+    assert_eq!(
+        PoolState::unpack(&[0u8; PoolState::LEN]).unwrap_err(),
+        ProgramError::Custom(HandCrankedError::InvalidAccountData as u32)
+    );
+}
@@ -0,0 +1,248 @@
+#![cfg(feature = "test-utils")]
+
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    processor2::Processor,
+    state::{Cluster, PoolParams, PoolState, NATIVE_ASSET},
+    zk::{public_inputs::SCALAR_MODULUS, Circuit, MockVerifier},
+};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+
+fn accept_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::accepting())
+}
+
+fn reject_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::rejecting())
+}
+
+/// Open a pool and send one private transfer spending `nullifier_1`
+/// through a processor whose verifier accepts or rejects every proof,
+/// returning the transfer's result and whether `nullifier_1` was recorded.
+/// With `prefund`, lamports are sent to the nullifier address first;
+/// without `finalized`, the verifying key is left unfinalized.
+async fn transfer_with(
+    accept: bool,
+    nullifier_1: [u8; 32],
+    prefund: bool,
+    finalized: bool,
+) -> (Result<(), BanksClientError>, bool) {
+    let process = if accept {
+        processor!(accept_all)
+    } else {
+        processor!(reject_all)
+    };
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, process);
+    let mut context = program_test.start_with_context().await;
+
+    let payer = &context.payer;
+    let pool_id = 1u64;
+    let pool = Pubkey::find_program_address(&[b"pool", &pool_id.to_le_bytes()], &program_id).0;
+    let queue = Pubkey::find_program_address(&[b"queue", pool.as_ref()], &program_id).0;
+    let vault = Pubkey::find_program_address(&[b"vault", pool.as_ref()], &program_id).0;
+    let stats = Pubkey::find_program_address(
+        &[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()],
+        &program_id,
+    )
+    .0;
+    // The mock ignores the key, but the account must still be the pool's.
+    let verifying_key = Pubkey::find_program_address(
+        &[
+            b"verifying-key",
+            pool.as_ref(),
+            &[ProofSystem::Groth16 as u8],
+            &[Circuit::JoinSplit as u8],
+        ],
+        &program_id,
+    )
+    .0;
+    let system_program = solana_sdk::system_program::id();
+
+    let init_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(queue, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(system_program, false),
+            AccountMeta::new(stats, false),
+        ],
+        data: HandCrankedInstruction::InitializePool {
+            pool_id,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 150,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let key_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(verifying_key, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program, false),
+        ],
+        data: HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit: Circuit::JoinSplit,
+            len: 1,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let finalize_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(verifying_key, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+        ],
+        data: HandCrankedInstruction::FinalizeVerifyingKey
+            .try_to_vec()
+            .unwrap(),
+    };
+    let mut instructions = vec![init_ix, key_ix];
+    if finalized {
+        instructions.push(finalize_ix);
+    }
+    let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    tx.sign(&[payer], context.last_blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pool_account = context
+        .banks_client
+        .get_account(pool)
+        .await
+        .unwrap()
+        .unwrap();
+    let root = PoolState::unpack(&pool_account.data).unwrap().tree.root();
+
+    let nullifier_2 = [2u8; 32];
+    let nullifier_pda = |nullifier: &[u8; 32]| {
+        Pubkey::find_program_address(&[b"nullifier", pool.as_ref(), nullifier], &program_id).0
+    };
+    if prefund {
+        let fund_ix =
+            system_instruction::transfer(&payer.pubkey(), &nullifier_pda(&nullifier_1), 1);
+        let mut tx = Transaction::new_with_payer(&[fund_ix], Some(&payer.pubkey()));
+        tx.sign(&[payer], context.last_blockhash);
+        context.banks_client.process_transaction(tx).await.unwrap();
+    }
+    let transfer_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(queue, false),
+            AccountMeta::new(nullifier_pda(&nullifier_1), false),
+            AccountMeta::new(nullifier_pda(&nullifier_2), false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program, false),
+            AccountMeta::new(stats, false),
+            AccountMeta::new_readonly(verifying_key, false),
+        ],
+        data: HandCrankedInstruction::PrivateTransfer {
+            proof_system: ProofSystem::Groth16,
+            proof: ProofSource::Inline(vec![0; 128]),
+            root,
+            nullifier_1,
+            nullifier_2,
+            new_commitment_1: [3; 32],
+            new_commitment_2: [4; 32],
+            fee: 0,
+            encrypted_note_1: vec![],
+            encrypted_note_2: vec![],
+            auditor_memo: None,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let mut tx = Transaction::new_with_payer(&[transfer_ix], Some(&payer.pubkey()));
+    tx.sign(&[payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(tx).await;
+
+    let spent = context
+        .banks_client
+        .get_account(nullifier_pda(&nullifier_1))
+        .await
+        .unwrap()
+        .map_or(false, |account| account.owner == program_id);
+    (result, spent)
+}
+
+#[tokio::test]
+async fn test_transfer_with_accepted_proof_spends_nullifiers() {
+    let (result, spent) = transfer_with(true, [1; 32], false, true).await;
+    result.unwrap();
+    assert!(spent);
+}
+
+#[tokio::test]
+async fn test_prefunded_nullifier_can_still_be_spent() {
+    let (result, spent) = transfer_with(true, [1; 32], true, true).await;
+    result.unwrap();
+    assert!(spent);
+}
+
+#[tokio::test]
+async fn test_non_canonical_nullifier_is_rejected() {
+    // r + 1: the same field element as the nullifier 1 once reduced.
+    let mut nullifier = SCALAR_MODULUS;
+    nullifier[0] += 1;
+    let (result, spent) = transfer_with(true, nullifier, false, true).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(HandCrankedError::NonCanonicalInput as u32)
+        )
+    );
+    assert!(!spent);
+}
+
+#[tokio::test]
+async fn test_transfer_with_rejected_proof_fails() {
+    let (result, spent) = transfer_with(false, [1; 32], false, true).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(HandCrankedError::InvalidProof as u32)
+        )
+    );
+    assert!(!spent);
+}
+
+#[tokio::test]
+async fn test_unfinalized_verifying_key_is_rejected() {
+    let (result, spent) = transfer_with(true, [1; 32], false, false).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(HandCrankedError::VerifyingKeyNotFinalized as u32)
+        )
+    );
+    assert!(!spent);
+}
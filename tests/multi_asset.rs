@@ -0,0 +1,79 @@
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    merkle::{asset_deposit_leaf, deposit_leaf},
+    state::{Cluster, PoolParams, PoolState, MULTI_ASSET, NATIVE_ASSET},
+    zk::{
+        public_inputs::{asset_id, AuditorKey, PublicInputs},
+        Circuit,
+    },
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn params() -> PoolParams {
+    PoolParams {
+        cluster: Cluster::Localnet,
+        crank_reward: 5_000,
+        min_withdraw_delay: 0,
+        max_withdraw_delay: 150,
+        curator: Pubkey::default(),
+        auditor: None,
+        min_note_age: None,
+    }
+}
+
+#[test]
+fn test_multi_asset_pool_uses_multi_asset_circuit() {
+    let pool = PoolState::new(0, Pubkey::new_unique(), MULTI_ASSET, None, params()).unwrap();
+    assert!(pool.is_multi_asset());
+    assert_eq!(pool.circuit(), Circuit::MultiAssetJoinSplit);
+}
+
+#[test]
+fn test_multi_asset_pool_rejects_denomination_and_auditor() {
+    let invalid = ProgramError::from(HandCrankedError::InvalidInstruction);
+    assert_eq!(
+        PoolState::new(0, Pubkey::new_unique(), MULTI_ASSET, Some(10), params()).unwrap_err(),
+        invalid
+    );
+
+    let mut audited = params();
+    audited.auditor = Some(AuditorKey {
+        x: [1u8; 32],
+        y: [2u8; 32],
+    });
+    assert_eq!(
+        PoolState::new(0, Pubkey::new_unique(), MULTI_ASSET, None, audited).unwrap_err(),
+        invalid
+    );
+}
+
+#[test]
+fn test_deposit_leaf_binds_asset() {
+    let commitment = [7u8; 32];
+    let sol = asset_deposit_leaf(&asset_id(&NATIVE_ASSET), 100, &commitment).unwrap();
+    let token = asset_deposit_leaf(&asset_id(&Pubkey::new_unique()), 100, &commitment).unwrap();
+    assert_ne!(sol, token);
+    assert_ne!(sol, deposit_leaf(100, &commitment).unwrap());
+}
+
+#[test]
+fn test_asset_id_is_last_public_input() {
+    let mint = Pubkey::new_unique();
+    let inputs = PublicInputs {
+        domain: [9u8; 32],
+        root: [1u8; 32],
+        root_slot: 0,
+        nullifiers: vec![[2u8; 32]],
+        output_commitments: vec![],
+        public_amount: 10,
+        fee: 0,
+        relayer: Pubkey::default(),
+        ext_data_hash: [0u8; 32],
+        audit: None,
+        asset_id: Some(asset_id(&mint)),
+    };
+
+    let elements = inputs.to_field_elements();
+    assert_eq!(elements.len(), 7 + 1 + 1);
+    assert_eq!(elements[8], asset_id(&mint));
+}
@@ -0,0 +1,285 @@
+#![cfg(feature = "test-utils")]
+
+use borsh::BorshSerialize;
+
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    merkle::{AGE_CHECKPOINT_INTERVAL, MAX_NOTE_AGE, ROOT_HISTORY_SIZE},
+    processor2::Processor,
+    state::{Cluster, PoolParams, PoolState, NATIVE_ASSET},
+    zk::{Circuit, MockVerifier},
+};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+};
+
+const NOTE: u64 = 1_000_000;
+const MIN_NOTE_AGE: u64 = 2_000;
+
+fn accept_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::accepting())
+}
+
+struct Pool {
+    program_id: Pubkey,
+    pool: Pubkey,
+    queue: Pubkey,
+    vault: Pubkey,
+    stats: Pubkey,
+    verifying_key: Pubkey,
+}
+
+impl Pool {
+    fn new(program_id: Pubkey) -> Self {
+        let pool = Pubkey::find_program_address(&[b"pool", &0u64.to_le_bytes()], &program_id).0;
+        let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &program_id).0;
+        Self {
+            program_id,
+            pool,
+            queue: pda(&[b"queue", pool.as_ref()]),
+            vault: pda(&[b"vault", pool.as_ref()]),
+            stats: pda(&[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()]),
+            verifying_key: pda(&[
+                b"verifying-key",
+                pool.as_ref(),
+                &[ProofSystem::Groth16 as u8],
+                &[Circuit::JoinSplit as u8],
+            ]),
+        }
+    }
+
+    fn pda(&self, seed: &[u8], key: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[seed, self.pool.as_ref(), key], &self.program_id).0
+    }
+
+    fn instruction(&self, accounts: Vec<AccountMeta>, data: HandCrankedInstruction) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: data.try_to_vec().unwrap(),
+        }
+    }
+}
+
+async fn send(context: &mut ProgramTestContext, ix: Instruction) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    tx.sign(&[&context.payer], blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+fn initialize(pool: &Pool, payer: Pubkey, min_note_age: u64) -> Instruction {
+    pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id: 0,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: Some(min_note_age),
+            },
+        },
+    )
+}
+
+async fn root(context: &mut ProgramTestContext, pool: &Pool) -> [u8; 32] {
+    let account = context
+        .banks_client
+        .get_account(pool.pool)
+        .await
+        .unwrap()
+        .unwrap();
+    PoolState::unpack(&account.data).unwrap().tree.root()
+}
+
+async fn deposit(context: &mut ProgramTestContext, pool: &Pool, commitment: [u8; 32]) {
+    let payer = context.payer.pubkey();
+    let ix = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.pda(b"note", &commitment), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::Deposit {
+            amount: NOTE,
+            commitment,
+            encrypted_note: vec![],
+        },
+    );
+    send(context, ix).await.unwrap();
+}
+
+async fn crank(context: &mut ProgramTestContext, pool: &Pool, max_leaves: u8) {
+    let ix = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(context.payer.pubkey(), false),
+        ],
+        HandCrankedInstruction::Crank { max_leaves },
+    );
+    send(context, ix).await.unwrap();
+}
+
+async fn withdraw(
+    context: &mut ProgramTestContext,
+    pool: &Pool,
+    root: [u8; 32],
+    nullifier: [u8; 32],
+) -> Result<(), BanksClientError> {
+    let ix = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.pda(b"nullifier", &nullifier), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+            AccountMeta::new_readonly(pool.verifying_key, false),
+        ],
+        HandCrankedInstruction::Withdraw {
+            proof_system: ProofSystem::Groth16,
+            proof: ProofSource::Inline(vec![0; 128]),
+            root,
+            nullifier,
+            amount: NOTE,
+            fee: 0,
+            association: None,
+            callback: None,
+        },
+    );
+    send(context, ix).await
+}
+
+#[tokio::test]
+async fn test_min_note_age_is_bounded_by_retained_history() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, processor!(accept_all));
+    let mut context = program_test.start_with_context().await;
+    let pool = Pool::new(program_id);
+    let payer = context.payer.pubkey();
+
+    assert_eq!(
+        send(&mut context, initialize(&pool, payer, MAX_NOTE_AGE + 1))
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+    send(&mut context, initialize(&pool, payer, MAX_NOTE_AGE))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_young_notes_cannot_be_withdrawn() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, processor!(accept_all));
+    let mut context = program_test.start_with_context().await;
+    let pool = Pool::new(program_id);
+    let payer = context.payer.pubkey();
+
+    send(&mut context, initialize(&pool, payer, MIN_NOTE_AGE))
+        .await
+        .unwrap();
+    let key = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit: Circuit::JoinSplit,
+            len: 1,
+        },
+    );
+    send(&mut context, key).await.unwrap();
+    let finalize = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        HandCrankedInstruction::FinalizeVerifyingKey,
+    );
+    send(&mut context, finalize).await.unwrap();
+
+    deposit(&mut context, &pool, [1; 32]).await;
+    crank(&mut context, &pool, 1).await;
+    let old_root = root(&mut context, &pool).await;
+    let deposited_at = context.banks_client.get_root_slot().await.unwrap();
+    assert_eq!(
+        withdraw(&mut context, &pool, old_root, [2; 32])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::NoteTooYoung)
+    );
+
+    // Cycle the root history in a later epoch: the root stays known
+    // through its age checkpoint, but is still too young.
+    context
+        .warp_to_slot((deposited_at / AGE_CHECKPOINT_INTERVAL + 1) * AGE_CHECKPOINT_INTERVAL)
+        .unwrap();
+    for i in 0..ROOT_HISTORY_SIZE as u8 {
+        deposit(&mut context, &pool, [i + 3; 32]).await;
+        crank(&mut context, &pool, 1).await;
+    }
+    assert_eq!(
+        withdraw(&mut context, &pool, old_root, [2; 32])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::NoteTooYoung)
+    );
+
+    context
+        .warp_to_slot(deposited_at + MIN_NOTE_AGE + 1)
+        .unwrap();
+    withdraw(&mut context, &pool, old_root, [2; 32])
+        .await
+        .unwrap();
+    let new_root = root(&mut context, &pool).await;
+    assert_eq!(
+        withdraw(&mut context, &pool, new_root, [3; 32])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::NoteTooYoung)
+    );
+}
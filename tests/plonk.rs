@@ -0,0 +1,204 @@
+#![cfg(feature = "zk-plonk")]
+
+use std::str::FromStr;
+
+use ark_bn254::{Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_ff::{BigInteger, PrimeField};
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    zk::plonk::{
+        decode_plonk_proof, encode_plonk_key, encode_plonk_proof, verify_plonk, PlonkCircuitKey,
+        PlonkProof, PLONK_PROOF_LEN,
+    },
+};
+use solana_program::program_error::ProgramError;
+
+fn fq(value: &str) -> Fq {
+    Fq::from_str(value).unwrap()
+}
+
+fn fr(value: &str) -> Fr {
+    Fr::from_str(value).unwrap()
+}
+
+fn g1(x: &str, y: &str) -> G1Affine {
+    G1Affine::new(fq(x), fq(y))
+}
+
+fn scalar(value: u64) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Fr::from(value).into_bigint().to_bytes_le());
+    out
+}
+
+fn proof() -> PlonkProof {
+    let g = G1Affine::generator();
+    PlonkProof {
+        a: g,
+        b: (g + g).into(),
+        c: g,
+        z: G1Affine::zero(),
+        t1: g,
+        t2: g,
+        t3: g,
+        wxi: g,
+        wxiw: g,
+        eval_a: Fr::from(1u64),
+        eval_b: Fr::from(2u64),
+        eval_c: Fr::from(3u64),
+        eval_s1: Fr::from(4u64),
+        eval_s2: Fr::from(5u64),
+        eval_zw: -Fr::from(1u64),
+    }
+}
+
+#[test]
+fn test_plonk_proof_round_trips() {
+    let bytes = encode_plonk_proof(&proof());
+    assert_eq!(bytes.len(), PLONK_PROOF_LEN);
+    assert_eq!(decode_plonk_proof(&bytes).unwrap(), proof());
+}
+
+#[test]
+fn test_plonk_proof_rejects_non_canonical_scalars() {
+    let invalid: ProgramError = HandCrankedError::InvalidProof.into();
+    let mut bytes = encode_plonk_proof(&proof());
+    assert_eq!(decode_plonk_proof(&bytes[1..]), Err(invalid.clone()));
+
+    // `eval_zw` replaced by a value above the scalar modulus.
+    bytes[PLONK_PROOF_LEN - 32..].copy_from_slice(&[0xff; 32]);
+    assert_eq!(decode_plonk_proof(&bytes), Err(invalid));
+}
+
+/// A proof of `x * x == y` and `x + y == z` for `x = 3` over a four-row
+/// domain, with public inputs `y = 9` and `z = 12`, as snarkjs writes
+/// `verification_key.json` and `proof.json`. The vector was produced by a
+/// port of snarkjs' `prover_plonk.js` (without blinding) against a fixed
+/// toy `tau` rather than a ceremony, so `x_2` is only good for this test.
+fn known_answer() -> (PlonkCircuitKey, PlonkProof) {
+    let key = PlonkCircuitKey {
+        power: 2,
+        n_public: 2,
+        k1: fr("2"),
+        k2: fr("3"),
+        omega: fr("21888242871839275217838484774961031246007050428528088939761107053157389710902"),
+        qm: g1(
+            "15220724900273992439135706892558484387871484815553306809692737750623076643356",
+            "12082091538532443763549295994051974785911720357843881770918799672989042179947",
+        ),
+        ql: g1(
+            "19200281150190649791812710965870326976325212411798584678970371043221458186475",
+            "4192076887757132454692048266861817431077762057008911222557504230203540846433",
+        ),
+        qr: g1(
+            "7585502817317622427543812775110507852277574837401851099628556960144062710382",
+            "19638179911722886780054758802637406688889009733600677025319085460586648095365",
+        ),
+        qo: g1(
+            "14014664851098287161408954457781024264749059899938223436704741921376285726348",
+            "14906426861150114914142279023466364635893302343263851768532905812464782990172",
+        ),
+        qc: G1Affine::zero(),
+        s1: g1(
+            "6740193398206818731660969747096820890870954483070433438461688742672286952526",
+            "1713623604114215204388330471625553276132255268626900078682515552250928682396",
+        ),
+        s2: g1(
+            "1364735913465320918251883837045068826406485777451672774459069694062453709288",
+            "8008044011095968617272713237272160372127461141151743516652748117409696957093",
+        ),
+        s3: g1(
+            "16897016686182121720484188174693746995847415390599752746807954460126583074370",
+            "808586609557879346770300181071253215094824286037187855665742240569681285661",
+        ),
+        x_2: G2Affine::new(
+            Fq2::new(
+                fq("21607483242045350228442449626083685481804805253127506432969915971323309399458"),
+                fq("12683785895225546310234546507907134871397301378856448973656288578499942969144"),
+            ),
+            Fq2::new(
+                fq("20316798714624197357614072070539154558759749530763270710450178230022746754031"),
+                fq("7480503903733976356522185581063732585224910493517019384051262666503530416922"),
+            ),
+        ),
+    };
+    let proof = PlonkProof {
+        a: g1(
+            "6546010907454823179422026705648510433574246809695122775558639543161569591130",
+            "1184637153568646108774515282449354198028717780314124655174960401179590286601",
+        ),
+        b: g1(
+            "5987823547666200119934793318162005638024232968797951905304003637821078992205",
+            "17374390000474436590112136494372248021460787248102416355333736612747602351015",
+        ),
+        c: g1(
+            "15176579896927361980713874757986528706422310397490887610120296026686653760043",
+            "3082024360389198932556079439872522993253484817128012584276311404566462428484",
+        ),
+        z: g1(
+            "2356487934954270394829500698853768615247299120526579870111330645205419868155",
+            "7727896320798810729524214001116395083606565753139620827787095600325391811447",
+        ),
+        t1: g1(
+            "5708396134958785457217139027038484644378004445008568962926799158905763752989",
+            "2366555061543488078450934666722420274836052965823745512424047956536803744318",
+        ),
+        t2: g1(
+            "16895227802408069615637235550389063569202241057741142216629999457130725353333",
+            "18179442814790132757105510515014898137774424315944931546513519396199696756215",
+        ),
+        t3: g1(
+            "4617696120680996622716155017874372966342832551438224103099604667488746539157",
+            "16719814098247071104776272441040374898745215335421959308478334970050863629796",
+        ),
+        wxi: g1(
+            "9571372814632254750082586291840997420556871832041692614445283504275000728047",
+            "17320306943385519538234309677576233150981132519877982323774718119704877168673",
+        ),
+        wxiw: g1(
+            "6610389311860624653624033860366402566138746382413033011414588932546160097063",
+            "7840985593587483308069407718411261381713771342937982923498694386882946209126",
+        ),
+        eval_a: fr("16564935482819857576794226021251562183610397965676149759579384792244164437984"),
+        eval_b: fr("6435284532451548554908717537037803686242111265462157046300020018037950257033"),
+        eval_c: fr("7416455600009834434594365663365270068841937561242673047343200515804262823204"),
+        eval_s1: fr(
+            "10442459384296747871579639817505989717132555668473189689368689813239890323902",
+        ),
+        eval_s2: fr(
+            "14782091051149459150976094676074655025249004158470173010335510026810926526322",
+        ),
+        eval_zw: fr(
+            "19635653020964385762840173252743516773849721987586606662812314461335890172077",
+        ),
+    };
+    (key, proof)
+}
+
+#[test]
+fn test_verify_plonk_known_answer() {
+    let invalid: ProgramError = HandCrankedError::InvalidProof.into();
+    let (key, proof) = known_answer();
+    let key = encode_plonk_key(&key);
+    let proof = encode_plonk_proof(&proof);
+
+    assert_eq!(verify_plonk(&key, &proof, &[scalar(9), scalar(12)]), Ok(()));
+    assert_eq!(
+        verify_plonk(&key, &proof, &[scalar(9), scalar(13)]),
+        Err(invalid.clone())
+    );
+    assert_eq!(
+        verify_plonk(&key, &proof, &[scalar(9)]),
+        Err(invalid.clone())
+    );
+
+    // `eval_zw` off by one.
+    let (_, mut tampered) = known_answer();
+    tampered.eval_zw += Fr::from(1u64);
+    let tampered = encode_plonk_proof(&tampered);
+    assert_eq!(
+        verify_plonk(&key, &tampered, &[scalar(9), scalar(12)]),
+        Err(invalid)
+    );
+}
@@ -0,0 +1,227 @@
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError, instruction::HandCrankedInstruction, processor2::Processor,
+    state::ProofBuffer,
+};
+use solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+/// Larger than a program can create in one go.
+const PROOF_LEN: u32 = 20_000;
+
+async fn send(
+    context: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    tx.sign(&all_signers, blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+fn instruction(
+    program_id: Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: HandCrankedInstruction,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+fn write(program_id: Pubkey, buffer: Pubkey, authority: Pubkey, offset: u32) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(buffer, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+        HandCrankedInstruction::WriteProofChunk {
+            offset,
+            data: vec![7; 800],
+        },
+    )
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+#[tokio::test]
+async fn test_proof_buffer_grows_as_chunks_are_appended() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+        "hand_cranked_privacy",
+        program_id,
+        processor!(Processor::process),
+    );
+    let mut context = program_test.start_with_context().await;
+    let authority = context.payer.pubkey();
+    let buffer = Pubkey::find_program_address(
+        &[b"proof-buffer", authority.as_ref(), &1u64.to_le_bytes()],
+        &program_id,
+    )
+    .0;
+
+    let open = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(buffer, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::OpenProofBuffer {
+            buffer_id: 1,
+            len: PROOF_LEN,
+        },
+    );
+    send(&mut context, open, &[]).await.unwrap();
+
+    let full_len = ProofBuffer::LEN + PROOF_LEN as usize;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let account = context
+        .banks_client
+        .get_account(buffer)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), MAX_PERMITTED_DATA_INCREASE);
+    assert!(account.lamports >= rent.minimum_balance(full_len));
+
+    // Only the authority writes, and only inside the proof.
+    let stranger = Keypair::new();
+    let result = send(
+        &mut context,
+        write(program_id, buffer, stranger.pubkey(), 0),
+        &[&stranger],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::Unauthorized)
+    );
+    let result = send(
+        &mut context,
+        write(program_id, buffer, authority, PROOF_LEN - 100),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+
+    // Past the allocated bytes, chunks must be appended in order.
+    let allocated = (MAX_PERMITTED_DATA_INCREASE - ProofBuffer::LEN) as u32;
+    let result = send(
+        &mut context,
+        write(program_id, buffer, authority, allocated + 1),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+
+    let mut offset = allocated;
+    while offset < PROOF_LEN {
+        let start = offset.min(PROOF_LEN - 800);
+        send(
+            &mut context,
+            write(program_id, buffer, authority, start),
+            &[],
+        )
+        .await
+        .unwrap();
+        offset = start + 800;
+    }
+    let account = context
+        .banks_client
+        .get_account(buffer)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), full_len);
+    let (header, proof) = ProofBuffer::unpack(&account.data).unwrap();
+    assert_eq!(header.len, PROOF_LEN);
+    assert!(proof[allocated as usize..].iter().all(|byte| *byte == 7));
+
+    let close = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(buffer, false),
+            AccountMeta::new(authority, true),
+        ],
+        HandCrankedInstruction::CloseProofBuffer,
+    );
+    send(&mut context, close, &[]).await.unwrap();
+    assert!(context
+        .banks_client
+        .get_account(buffer)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_proof_buffer_must_be_at_its_address() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "hand_cranked_privacy",
+        program_id,
+        processor!(Processor::process),
+    );
+
+    // A program-owned account with a valid header for `authority`, but not
+    // at the buffer address the header names.
+    let authority = Keypair::new();
+    let mut data = ProofBuffer {
+        is_initialized: true,
+        authority: authority.pubkey(),
+        buffer_id: 1,
+        len: 800,
+    }
+    .try_to_vec()
+    .unwrap();
+    data.resize(ProofBuffer::LEN + 800, 0);
+    let forged = Pubkey::new_unique();
+    program_test.add_account(
+        forged,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let result = send(
+        &mut context,
+        write(program_id, forged, authority.pubkey(), 0),
+        &[&authority],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidAccountData)
+    );
+}
@@ -0,0 +1,184 @@
+use hand_cranked_privacy::{
+    instruction::WithdrawCallback,
+    state::Cluster,
+    zk::public_inputs::{
+        check_canonical, domain_tag, pubkey_to_field, u64_to_field, AuditBinding, AuditorKey,
+        AuditorMemo, ExtData, PublicInputs, SwapInputs, SCALAR_MODULUS,
+    },
+};
+use solana_program::pubkey::Pubkey;
+
+#[test]
+fn test_public_input_layout() {
+    let relayer = Pubkey::new_unique();
+    let ext_data = ExtData {
+        recipient: Pubkey::new_unique(),
+        relayer,
+        fee: 5,
+        encrypted_outputs: vec![],
+        callback: None,
+    };
+    let inputs = PublicInputs {
+        domain: [9u8; 32],
+        root: [1u8; 32],
+        root_slot: 42,
+        nullifiers: vec![[2u8; 32], [3u8; 32]],
+        output_commitments: vec![[4u8; 32], [5u8; 32]],
+        public_amount: 0,
+        fee: 5,
+        relayer,
+        ext_data_hash: ext_data.hash().unwrap(),
+        audit: None,
+        asset_id: None,
+    };
+
+    assert_eq!(
+        inputs.to_field_elements(),
+        vec![
+            [9u8; 32],
+            [1u8; 32],
+            u64_to_field(42),
+            u64_to_field(0),
+            u64_to_field(5),
+            pubkey_to_field(&relayer),
+            ext_data.hash().unwrap(),
+            [2u8; 32],
+            [3u8; 32],
+            [4u8; 32],
+            [5u8; 32],
+        ]
+    );
+}
+
+#[test]
+fn test_ext_data_hash_binds_recipient() {
+    let ext_data = ExtData {
+        recipient: Pubkey::new_unique(),
+        relayer: Pubkey::new_unique(),
+        fee: 0,
+        encrypted_outputs: vec![vec![1, 2, 3]],
+        callback: None,
+    };
+    let swapped = ExtData {
+        recipient: Pubkey::new_unique(),
+        ..ext_data.clone()
+    };
+    let with_callback = ExtData {
+        callback: Some(WithdrawCallback {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![],
+        }),
+        ..ext_data.clone()
+    };
+
+    assert_ne!(ext_data.hash().unwrap(), swapped.hash().unwrap());
+    assert_ne!(ext_data.hash().unwrap(), with_callback.hash().unwrap());
+    // Every hash is a canonical field element.
+    assert_eq!(ext_data.hash().unwrap()[31], 0);
+}
+
+#[test]
+fn test_domain_tag_separates_deployments() {
+    let program_id = Pubkey::new_unique();
+    let pool = Pubkey::new_unique();
+    let tag = domain_tag(&program_id, &pool, Cluster::Mainnet);
+
+    assert_ne!(tag, domain_tag(&program_id, &pool, Cluster::Devnet));
+    assert_ne!(
+        tag,
+        domain_tag(&program_id, &Pubkey::new_unique(), Cluster::Mainnet)
+    );
+    assert_ne!(
+        tag,
+        domain_tag(&Pubkey::new_unique(), &pool, Cluster::Mainnet)
+    );
+}
+
+#[test]
+fn test_audit_inputs_follow_output_commitments() {
+    let inputs = PublicInputs {
+        domain: [9u8; 32],
+        root: [1u8; 32],
+        root_slot: 0,
+        nullifiers: vec![[2u8; 32]],
+        output_commitments: vec![[3u8; 32]],
+        public_amount: 0,
+        fee: 0,
+        relayer: Pubkey::default(),
+        ext_data_hash: [0u8; 32],
+        audit: Some(AuditBinding {
+            key: AuditorKey {
+                x: [10u8; 32],
+                y: [11u8; 32],
+            },
+            memo: AuditorMemo {
+                ephemeral_x: [12u8; 32],
+                ephemeral_y: [13u8; 32],
+                amount: [14u8; 32],
+                sender: [15u8; 32],
+                recipient: [16u8; 32],
+            },
+        }),
+        asset_id: None,
+    };
+
+    let elements = inputs.to_field_elements();
+    assert_eq!(elements.len(), 9 + 7);
+    assert_eq!(elements[8], [3u8; 32]);
+    assert_eq!(
+        &elements[9..],
+        &(10..=16u8).map(|b| [b; 32]).collect::<Vec<_>>()[..]
+    );
+}
+
+#[test]
+fn test_swap_audit_inputs_follow_ext_data_hash() {
+    let audit = AuditBinding {
+        key: AuditorKey {
+            x: [10u8; 32],
+            y: [11u8; 32],
+        },
+        memo: AuditorMemo {
+            ephemeral_x: [12u8; 32],
+            ephemeral_y: [13u8; 32],
+            amount: [14u8; 32],
+            sender: [15u8; 32],
+            recipient: [16u8; 32],
+        },
+    };
+    let mut inputs = SwapInputs {
+        domain: [9u8; 32],
+        root: [1u8; 32],
+        root_slot: 77,
+        nullifier: [2u8; 32],
+        amount_in: 100,
+        fee: 1,
+        relayer: Pubkey::default(),
+        output_domain: [8u8; 32],
+        output_commitment: [3u8; 32],
+        min_amount_out: 90,
+        ext_data_hash: [4u8; 32],
+        audit: None,
+    };
+    assert_eq!(inputs.to_field_elements().len(), 11);
+    assert_eq!(inputs.to_field_elements()[2], u64_to_field(77));
+
+    inputs.audit = Some(audit.clone());
+    let elements = inputs.to_field_elements();
+    assert_eq!(elements[10], [4u8; 32]);
+    assert_eq!(&elements[11..], &audit.to_field_elements()[..]);
+    assert_eq!(audit.to_field_elements()[4], [14u8; 32]);
+}
+
+#[test]
+fn test_inputs_must_be_below_the_scalar_modulus() {
+    let mut below = SCALAR_MODULUS;
+    below[0] -= 1;
+    let mut above = SCALAR_MODULUS;
+    above[31] = 0xff;
+
+    assert!(check_canonical(&[[0; 32], below, u64_to_field(u64::MAX)]).is_ok());
+    assert!(check_canonical(&[[0; 32], SCALAR_MODULUS]).is_err());
+    assert!(check_canonical(&[above]).is_err());
+}
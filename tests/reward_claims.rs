@@ -0,0 +1,461 @@
+#![cfg(feature = "test-utils")]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    processor2::Processor,
+    state::{
+        Cluster, DepositQueue, NullifierState, PoolParams, PoolState, RewardProgram, VaultStats,
+        NATIVE_ASSET,
+    },
+    zk::{Circuit, MockVerifier},
+};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+};
+
+const NOTE: u64 = 1_000_000_000;
+/// 0.000001 reward units per unit of amount per slot.
+const RATE: u64 = 1_000;
+const FUNDING: u64 = 30_000_000;
+
+fn accept_all(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    Processor::process_with(program_id, accounts, data, &MockVerifier::accepting())
+}
+
+struct Pool {
+    program_id: Pubkey,
+    pool: Pubkey,
+    queue: Pubkey,
+    vault: Pubkey,
+    stats: Pubkey,
+}
+
+impl Pool {
+    fn new(program_id: Pubkey, pool_id: u64) -> Self {
+        let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &program_id).0;
+        let pool = pda(&[b"pool", &pool_id.to_le_bytes()]);
+        Self {
+            program_id,
+            pool,
+            queue: pda(&[b"queue", pool.as_ref()]),
+            vault: pda(&[b"vault", pool.as_ref()]),
+            stats: pda(&[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()]),
+        }
+    }
+
+    fn pda(&self, seed: &[u8], key: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[seed, self.pool.as_ref(), key], &self.program_id).0
+    }
+
+    fn verifying_key(&self, circuit: Circuit) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"verifying-key",
+                self.pool.as_ref(),
+                &[ProofSystem::Groth16 as u8],
+                &[circuit as u8],
+            ],
+            &self.program_id,
+        )
+        .0
+    }
+
+    fn instruction(&self, accounts: Vec<AccountMeta>, data: HandCrankedInstruction) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: data.try_to_vec().unwrap(),
+        }
+    }
+}
+
+struct Rewards {
+    pool: Pool,
+    reward_pool: Pool,
+    rewards: Pubkey,
+}
+
+async fn send(context: &mut ProgramTestContext, ix: Instruction) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    tx.sign(&[&context.payer], blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+async fn account_data(context: &mut ProgramTestContext, address: Pubkey) -> Vec<u8> {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+        .data
+}
+
+async fn initialize_pool(context: &mut ProgramTestContext, pool: &Pool, pool_id: u64) {
+    let payer = context.payer.pubkey();
+    let init = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        },
+    );
+    send(context, init).await.unwrap();
+}
+
+async fn initialize_verifying_key(context: &mut ProgramTestContext, pool: &Pool, circuit: Circuit) {
+    let payer = context.payer.pubkey();
+    let key = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key(circuit), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit,
+            len: 1,
+        },
+    );
+    send(context, key).await.unwrap();
+    let finalize = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.verifying_key(circuit), false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        HandCrankedInstruction::FinalizeVerifyingKey,
+    );
+    send(context, finalize).await.unwrap();
+}
+
+fn initialize_rewards(rewards: &Rewards, payer: Pubkey, checkpoint_interval: u64) -> Instruction {
+    rewards.pool.instruction(
+        vec![
+            AccountMeta::new_readonly(rewards.pool.pool, false),
+            AccountMeta::new_readonly(rewards.reward_pool.pool, false),
+            AccountMeta::new(rewards.rewards, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeRewardProgram {
+            rate: RATE,
+            checkpoint_interval,
+        },
+    )
+}
+
+/// Open a pool and a reward pool, and a funded reward program between
+/// them.
+async fn setup() -> (ProgramTestContext, Rewards) {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new("hand_cranked_privacy", program_id, processor!(accept_all));
+    let mut context = program_test.start_with_context().await;
+    let pool = Pool::new(program_id, 0);
+    let reward_pool = Pool::new(program_id, 1);
+    let rewards = Rewards {
+        rewards: Pubkey::find_program_address(&[b"rewards", pool.pool.as_ref()], &program_id).0,
+        pool,
+        reward_pool,
+    };
+    initialize_pool(&mut context, &rewards.pool, 0).await;
+    initialize_pool(&mut context, &rewards.reward_pool, 1).await;
+    initialize_verifying_key(&mut context, &rewards.pool, Circuit::JoinSplit).await;
+    initialize_verifying_key(&mut context, &rewards.pool, Circuit::Reward).await;
+
+    let payer = context.payer.pubkey();
+    let interval = RewardProgram::MIN_CHECKPOINT_INTERVAL;
+    assert_eq!(
+        send(
+            &mut context,
+            initialize_rewards(&rewards, payer, interval - 1)
+        )
+        .await
+        .unwrap_err()
+        .unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+    send(&mut context, initialize_rewards(&rewards, payer, interval))
+        .await
+        .unwrap();
+
+    let fund = rewards.pool.instruction(
+        vec![
+            AccountMeta::new(rewards.rewards, false),
+            AccountMeta::new_readonly(rewards.reward_pool.pool, false),
+            AccountMeta::new(rewards.reward_pool.vault, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(rewards.reward_pool.stats, false),
+        ],
+        HandCrankedInstruction::FundRewards { amount: FUNDING },
+    );
+    send(&mut context, fund).await.unwrap();
+    (context, rewards)
+}
+
+async fn reward_program(context: &mut ProgramTestContext, rewards: &Rewards) -> RewardProgram {
+    RewardProgram::try_from_slice(&account_data(context, rewards.rewards).await).unwrap()
+}
+
+async fn stats(context: &mut ProgramTestContext, pool: &Pool) -> VaultStats {
+    VaultStats::unpack(&account_data(context, pool.stats).await).unwrap()
+}
+
+async fn deposit_and_crank(context: &mut ProgramTestContext, pool: &Pool, commitment: [u8; 32]) {
+    let payer = context.payer.pubkey();
+    let deposit = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(pool.pda(b"note", &commitment), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+        ],
+        HandCrankedInstruction::Deposit {
+            amount: NOTE,
+            commitment,
+            encrypted_note: vec![],
+        },
+    );
+    send(context, deposit).await.unwrap();
+    let crank = pool.instruction(
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(pool.queue, false),
+            AccountMeta::new(payer, false),
+        ],
+        HandCrankedInstruction::Crank { max_leaves: 8 },
+    );
+    send(context, crank).await.unwrap();
+}
+
+async fn record_checkpoint(
+    context: &mut ProgramTestContext,
+    rewards: &Rewards,
+) -> Result<(), BanksClientError> {
+    let ix = rewards.pool.instruction(
+        vec![
+            AccountMeta::new_readonly(rewards.pool.pool, false),
+            AccountMeta::new(rewards.rewards, false),
+            AccountMeta::new_readonly(rewards.pool.stats, false),
+        ],
+        HandCrankedInstruction::RecordCheckpoint,
+    );
+    send(context, ix).await
+}
+
+async fn claim(
+    context: &mut ProgramTestContext,
+    rewards: &Rewards,
+    checkpoints: [u64; 2],
+    nullifier: [u8; 32],
+    fee: u64,
+) -> Result<(), BanksClientError> {
+    let ix = rewards.pool.instruction(
+        vec![
+            AccountMeta::new_readonly(rewards.pool.pool, false),
+            AccountMeta::new(rewards.pool.queue, false),
+            AccountMeta::new(rewards.pool.pda(b"nullifier", &nullifier), false),
+            AccountMeta::new_readonly(rewards.rewards, false),
+            AccountMeta::new_readonly(rewards.reward_pool.pool, false),
+            AccountMeta::new(rewards.reward_pool.queue, false),
+            AccountMeta::new(rewards.reward_pool.vault, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(rewards.reward_pool.stats, false),
+            AccountMeta::new_readonly(rewards.pool.verifying_key(Circuit::Reward), false),
+        ],
+        HandCrankedInstruction::ClaimReward {
+            proof_system: ProofSystem::Groth16,
+            proof: ProofSource::Inline(vec![0; 128]),
+            start_checkpoint: checkpoints[0],
+            end_checkpoint: checkpoints[1],
+            nullifier,
+            new_commitment: [4; 32],
+            encrypted_note: vec![],
+            fee,
+            reward_commitment: [5; 32],
+            encrypted_reward_note: vec![],
+        },
+    );
+    send(context, ix).await
+}
+
+/// Move past the checkpoint interval and record the next checkpoint.
+async fn next_checkpoint(context: &mut ProgramTestContext, rewards: &Rewards) {
+    let last = reward_program(context, rewards).await;
+    let last = last.checkpoint(last.checkpoint_count - 1).unwrap().slot;
+    context
+        .warp_to_slot(last + RewardProgram::MIN_CHECKPOINT_INTERVAL)
+        .unwrap();
+    record_checkpoint(context, rewards).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_funding_counts_as_reward_pool_value() {
+    let (mut context, rewards) = setup().await;
+
+    assert_eq!(reward_program(&mut context, &rewards).await.budget, FUNDING);
+    let funded = stats(&mut context, &rewards.reward_pool).await.stats;
+    assert_eq!((funded.total_shielded, funded.deposit_count), (FUNDING, 1));
+}
+
+#[tokio::test]
+async fn test_checkpoints_reserve_budget_for_unspent_value() {
+    let (mut context, rewards) = setup().await;
+    deposit_and_crank(&mut context, &rewards.pool, [1; 32]).await;
+
+    record_checkpoint(&mut context, &rewards).await.unwrap();
+    assert_eq!(
+        record_checkpoint(&mut context, &rewards)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::CheckpointTooSoon)
+    );
+    next_checkpoint(&mut context, &rewards).await;
+
+    let program = reward_program(&mut context, &rewards).await;
+    let [start, end] = [0, 1].map(|number| program.checkpoint(number).unwrap());
+    assert_eq!(start.value, NOTE);
+    let pool = PoolState::unpack(&account_data(&mut context, rewards.pool.pool).await).unwrap();
+    assert_eq!(start.root, pool.tree.root());
+    let reserve = program
+        .reserve(NOTE, end.rewarded_slots - start.rewarded_slots)
+        .unwrap();
+    assert!(reserve > 0);
+    assert_eq!(program.budget, FUNDING - reserve);
+}
+
+#[tokio::test]
+async fn test_claims_issue_shielded_notes_without_spending() {
+    let (mut context, rewards) = setup().await;
+    let nullifier = [9; 32];
+    deposit_and_crank(&mut context, &rewards.pool, [1; 32]).await;
+    record_checkpoint(&mut context, &rewards).await.unwrap();
+    next_checkpoint(&mut context, &rewards).await;
+
+    assert_eq!(
+        claim(&mut context, &rewards, [0, 2], nullifier, 0)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::UnknownCheckpoint)
+    );
+    claim(&mut context, &rewards, [0, 1], nullifier, 1_000)
+        .await
+        .unwrap();
+
+    // The note is spent for a note of the same value in the pool, and its
+    // reward note is queued in the reward pool.
+    let spent = NullifierState::try_from_slice(
+        &account_data(&mut context, rewards.pool.pda(b"nullifier", &nullifier)).await,
+    )
+    .unwrap();
+    assert!(spent.is_initialized);
+    let queue =
+        DepositQueue::unpack(&account_data(&mut context, rewards.pool.queue).await).unwrap();
+    assert_eq!(queue.pending, vec![[4; 32]]);
+    let queue =
+        DepositQueue::unpack(&account_data(&mut context, rewards.reward_pool.queue).await).unwrap();
+    assert_eq!(queue.pending, vec![[5; 32]]);
+    // Only the fee leaves the reward pool's shielded value.
+    let reward_stats = stats(&mut context, &rewards.reward_pool).await.stats;
+    assert_eq!(reward_stats.total_shielded, FUNDING - 1_000);
+
+    // The spent note cannot claim again.
+    next_checkpoint(&mut context, &rewards).await;
+    assert_eq!(
+        claim(&mut context, &rewards, [1, 2], nullifier, 0)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::NullifierAlreadySpent)
+    );
+}
+
+#[tokio::test]
+async fn test_spent_notes_cannot_claim() {
+    let (mut context, rewards) = setup().await;
+    let nullifier = [9; 32];
+    deposit_and_crank(&mut context, &rewards.pool, [1; 32]).await;
+    record_checkpoint(&mut context, &rewards).await.unwrap();
+    next_checkpoint(&mut context, &rewards).await;
+
+    let pool = &rewards.pool;
+    let root = PoolState::unpack(&account_data(&mut context, pool.pool).await)
+        .unwrap()
+        .tree
+        .root();
+    let recipient = Pubkey::new_unique();
+    let withdraw = pool.instruction(
+        vec![
+            AccountMeta::new_readonly(pool.pool, false),
+            AccountMeta::new(pool.pda(b"nullifier", &nullifier), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(pool.stats, false),
+            AccountMeta::new_readonly(pool.verifying_key(Circuit::JoinSplit), false),
+        ],
+        HandCrankedInstruction::Withdraw {
+            proof_system: ProofSystem::Groth16,
+            proof: ProofSource::Inline(vec![0; 128]),
+            root,
+            nullifier,
+            amount: NOTE,
+            fee: 0,
+            association: None,
+            callback: None,
+        },
+    );
+    send(&mut context, withdraw).await.unwrap();
+
+    assert_eq!(
+        claim(&mut context, &rewards, [0, 1], nullifier, 0)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::NullifierAlreadySpent)
+    );
+}
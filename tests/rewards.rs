@@ -0,0 +1,109 @@
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    state::{RewardProgram, RootCheckpoint, REWARD_CHECKPOINTS},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn program(rate: u64, checkpoint_interval: u64) -> RewardProgram {
+    RewardProgram::new(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        rate,
+        checkpoint_interval,
+    )
+}
+
+#[test]
+fn test_checkpoints_respect_interval() {
+    let mut rewards = program(1, 100);
+    assert_eq!(rewards.push_checkpoint([1u8; 32], 1_000, 0).unwrap(), 0);
+    assert_eq!(
+        rewards.push_checkpoint([2u8; 32], 1_099, 0).unwrap_err(),
+        ProgramError::from(HandCrankedError::CheckpointTooSoon)
+    );
+    assert_eq!(rewards.push_checkpoint([2u8; 32], 1_100, 7).unwrap(), 1);
+    assert_eq!(
+        rewards.checkpoint(1),
+        Some(RootCheckpoint {
+            root: [2u8; 32],
+            slot: 1_100,
+            value: 7,
+            rewarded_slots: 100,
+        })
+    );
+    assert_eq!(rewards.checkpoint(2), None);
+}
+
+#[test]
+fn test_old_checkpoints_are_evicted() {
+    let interval = RewardProgram::MIN_CHECKPOINT_INTERVAL;
+    let mut rewards = program(1, interval);
+    for number in 0..=REWARD_CHECKPOINTS as u64 {
+        rewards
+            .push_checkpoint([number as u8 + 1; 32], number * interval, 0)
+            .unwrap();
+    }
+    assert_eq!(rewards.checkpoint(0), None);
+    assert_eq!(rewards.checkpoint(1).unwrap().slot, interval);
+    assert_eq!(
+        rewards.checkpoint(REWARD_CHECKPOINTS as u64).unwrap().slot,
+        REWARD_CHECKPOINTS as u64 * interval
+    );
+}
+
+#[test]
+fn test_checkpoints_reserve_the_reward_of_their_span() {
+    // 0.5 reward units per unit of amount per slot.
+    let mut rewards = program(500_000_000, 100);
+    rewards.budget = 1_000;
+    rewards.push_checkpoint([1u8; 32], 0, 3).unwrap();
+    // 3 * 101 / 2 = 151.5, rounded up.
+    rewards.push_checkpoint([2u8; 32], 101, 10).unwrap();
+    assert_eq!(rewards.budget, 848);
+
+    assert_eq!(rewards.checkpoint(1).unwrap().rewarded_slots, 101);
+
+    // Any split of the span claims no more than was reserved.
+    assert!(rewards.reward(3, 101) <= 152);
+    assert!(rewards.reward(1, 101) + rewards.reward(2, 101) <= 152);
+}
+
+#[test]
+fn test_reward_is_amount_times_slots_times_rate() {
+    // 0.5 reward units per unit of amount per slot.
+    let rewards = program(500_000_000, 0);
+    assert_eq!(rewards.reward(1_000, 10), 5_000);
+    assert_eq!(rewards.reward(1, 1), 0);
+    assert_eq!(rewards.reward(u64::MAX, u64::MAX), u64::MAX);
+    assert_eq!(rewards.reserve(1, 1), Some(1));
+    assert_eq!(rewards.reserve(u64::MAX, u64::MAX), None);
+}
+
+#[test]
+fn test_long_gaps_only_reward_what_the_budget_covers() {
+    // 0.5 reward units per unit of amount per slot.
+    let mut rewards = program(500_000_000, 100);
+    rewards.budget = 1_000;
+    rewards.push_checkpoint([1u8; 32], 0, 10).unwrap();
+
+    // 10 * 1_000_000 / 2 is far more than the budget: the span is only
+    // rewarded for the 200 slots the budget covers.
+    rewards.push_checkpoint([2u8; 32], 1_000_000, 10).unwrap();
+    assert_eq!(rewards.budget, 0);
+    let checkpoint = rewards.checkpoint(1).unwrap();
+    assert_eq!(
+        (checkpoint.slot, checkpoint.rewarded_slots),
+        (1_000_000, 200)
+    );
+    assert!(rewards.reward(10, 200) <= 1_000);
+
+    // Checkpoints keep being recorded with nothing left to reserve.
+    rewards.push_checkpoint([3u8; 32], 1_000_100, 10).unwrap();
+    assert_eq!(rewards.checkpoint(2).unwrap().rewarded_slots, 200);
+
+    // An empty pool reserves nothing and keeps the clock running.
+    rewards.push_checkpoint([4u8; 32], 1_000_200, 0).unwrap();
+    rewards.push_checkpoint([5u8; 32], 1_000_300, 0).unwrap();
+    assert_eq!(rewards.checkpoint(4).unwrap().rewarded_slots, 300);
+}
@@ -0,0 +1,51 @@
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    instruction::ProofSystem,
+    state::VerificationSession,
+    zk::{
+        session::{SessionStep, PREPARED_INPUTS_LEN},
+        Circuit,
+    },
+};
+use solana_program::pubkey::Pubkey;
+
+fn session(step: SessionStep) -> VerificationSession {
+    VerificationSession {
+        is_initialized: true,
+        prover: Pubkey::new_unique(),
+        pool: Pubkey::new_unique(),
+        session_id: 7,
+        opened_slot: 100,
+        proof_system: ProofSystem::Groth16,
+        circuit: Circuit::JoinSplit,
+        step,
+        prepared_inputs: [0; PREPARED_INPUTS_LEN],
+        public_inputs: vec![[1; 32]; 9],
+        proof: vec![2; 256],
+    }
+}
+
+#[test]
+fn test_session_len_fits_every_step() {
+    for step in [
+        SessionStep::PrepareInputs(0),
+        SessionStep::PrepareInputs(8),
+        SessionStep::Pairing,
+        SessionStep::Verified,
+    ] {
+        let session = session(step);
+        let data = session.try_to_vec().unwrap();
+        assert!(data.len() <= VerificationSession::len(256, 9));
+
+        let mut account = vec![0u8; VerificationSession::len(256, 9)];
+        account[..data.len()].copy_from_slice(&data);
+        assert_eq!(VerificationSession::unpack(&account).unwrap(), session);
+    }
+}
+
+#[test]
+fn test_session_expiry() {
+    let session = session(SessionStep::PrepareInputs(0));
+    assert!(!session.is_expired(100 + VerificationSession::TIMEOUT_SLOTS));
+    assert!(session.is_expired(101 + VerificationSession::TIMEOUT_SLOTS));
+}
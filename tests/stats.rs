@@ -0,0 +1,39 @@
+use hand_cranked_privacy::state::PoolStats;
+
+#[test]
+fn test_liability_follows_pool_activity() {
+    let mut stats = PoolStats::default();
+
+    stats.record_deposit(1_000).unwrap();
+    stats.record_deposit(500).unwrap();
+    assert_eq!(stats.liability(), 1_500);
+
+    // Transfer fees leave the vault.
+    stats.record_transfer(10).unwrap();
+    assert_eq!(stats.liability(), 1_490);
+
+    // A delayed withdrawal of a 500 note with a 5 fee keeps 495 owed.
+    stats.record_withdraw(500, 495).unwrap();
+    assert_eq!(stats.total_shielded, 990);
+    assert_eq!(stats.liability(), 1_485);
+
+    stats.record_payout(495).unwrap();
+    assert_eq!(stats.liability(), 990);
+
+    assert_eq!(
+        (
+            stats.deposit_count,
+            stats.transfer_count,
+            stats.withdraw_count
+        ),
+        (2, 1, 1)
+    );
+}
+
+#[test]
+fn test_withdrawing_more_than_shielded_fails() {
+    let mut stats = PoolStats::default();
+    stats.record_deposit(100).unwrap();
+    assert!(stats.record_withdraw(101, 0).is_err());
+    assert!(stats.record_payout(101).is_err());
+}
@@ -0,0 +1,42 @@
+use hand_cranked_privacy::{error::HandCrankedError, state::SwapReserve};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn reserve(reserve_a: u64, reserve_b: u64) -> SwapReserve {
+    SwapReserve {
+        is_initialized: true,
+        authority: Pubkey::new_unique(),
+        pool_a: Pubkey::new_unique(),
+        pool_b: Pubkey::new_unique(),
+        reserve_a,
+        reserve_b,
+        fee_bps: 30,
+    }
+}
+
+#[test]
+fn test_swap_follows_constant_product() {
+    let mut reserve = reserve(1_000, 1_000);
+    let pool_a = reserve.pool_a;
+
+    // 100 in, 99 after the 0.3% fee: 1_000 * 99 / 1_099 = 90 out.
+    assert_eq!(reserve.swap(&pool_a, 100).unwrap(), 90);
+    assert_eq!((reserve.reserve_a, reserve.reserve_b), (1_100, 910));
+
+    // And back the other way.
+    let pool_b = reserve.pool_b;
+    assert_eq!(reserve.swap(&pool_b, 90).unwrap(), 97);
+    assert_eq!((reserve.reserve_a, reserve.reserve_b), (1_003, 1_000));
+}
+
+#[test]
+fn test_swap_rejects_unknown_pool_and_empty_reserve() {
+    let mut reserve = reserve(0, 0);
+    assert_eq!(
+        reserve.swap(&Pubkey::new_unique(), 100),
+        Err(HandCrankedError::InvalidAccountData.into())
+    );
+
+    let pool_a = reserve.pool_a;
+    let err: ProgramError = HandCrankedError::InsufficientLiquidity.into();
+    assert_eq!(reserve.swap(&pool_a, 100), Err(err));
+}
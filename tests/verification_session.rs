@@ -0,0 +1,204 @@
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSource, ProofSystem},
+    processor2::Processor,
+    state::{Cluster, PoolParams, VerificationSession, NATIVE_ASSET},
+    zk::{
+        session::{SessionStep, PREPARED_INPUTS_LEN},
+        Circuit,
+    },
+};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+
+async fn send(
+    context: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    tx.sign(&all_signers, blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+fn instruction(
+    program_id: Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: HandCrankedInstruction,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+#[tokio::test]
+async fn test_prefunded_session_can_still_be_opened() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+        "hand_cranked_privacy",
+        program_id,
+        processor!(Processor::process),
+    );
+    let mut context = program_test.start_with_context().await;
+    let prover = context.payer.pubkey();
+    let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &program_id).0;
+    let pool = pda(&[b"pool", &0u64.to_le_bytes()]);
+    let session = pda(&[b"session", prover.as_ref(), &1u64.to_le_bytes()]);
+
+    let init = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(pda(&[b"queue", pool.as_ref()]), false),
+            AccountMeta::new(prover, true),
+            AccountMeta::new(pda(&[b"vault", pool.as_ref()]), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(
+                pda(&[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()]),
+                false,
+            ),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id: 0,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        },
+    );
+    send(&mut context, init, &[]).await.unwrap();
+
+    send(
+        &mut context,
+        system_instruction::transfer(&prover, &session, 1),
+        &[],
+    )
+    .await
+    .unwrap();
+    let open = instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(session, false),
+            AccountMeta::new(prover, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::OpenVerificationSession {
+            session_id: 1,
+            proof_system: ProofSystem::Groth16,
+            circuit: Circuit::JoinSplit,
+            proof: ProofSource::Inline(vec![0; 256]),
+            public_inputs: vec![[1; 32]; 9],
+        },
+    );
+    send(&mut context, open, &[]).await.unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(session)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    let opened = VerificationSession::unpack(&account.data).unwrap();
+    assert_eq!((opened.prover, opened.pool), (prover, pool));
+}
+
+#[tokio::test]
+async fn test_sessions_are_only_trusted_at_their_address() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "hand_cranked_privacy",
+        program_id,
+        processor!(Processor::process),
+    );
+
+    // A program-owned account holding a valid session for `prover`, but not
+    // at the session address its fields name.
+    let prover = Keypair::new();
+    let data = VerificationSession {
+        is_initialized: true,
+        prover: prover.pubkey(),
+        pool: Pubkey::new_unique(),
+        session_id: 1,
+        opened_slot: 0,
+        proof_system: ProofSystem::Groth16,
+        circuit: Circuit::JoinSplit,
+        step: SessionStep::PrepareInputs(0),
+        prepared_inputs: [0; PREPARED_INPUTS_LEN],
+        public_inputs: vec![],
+        proof: vec![0; 256],
+    }
+    .try_to_vec()
+    .unwrap();
+    let forged = Pubkey::new_unique();
+    program_test.add_account(
+        forged,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let step = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(forged, false),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+        ],
+        HandCrankedInstruction::StepVerificationSession { max_steps: 1 },
+    );
+    assert_eq!(
+        send(&mut context, step, &[]).await.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidAccountData)
+    );
+
+    let close = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(forged, false),
+            AccountMeta::new(prover.pubkey(), false),
+            AccountMeta::new_readonly(prover.pubkey(), true),
+        ],
+        HandCrankedInstruction::CloseVerificationSession,
+    );
+    assert_eq!(
+        send(&mut context, close, &[&prover])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        custom_error(HandCrankedError::InvalidAccountData)
+    );
+}
@@ -0,0 +1,322 @@
+use borsh::BorshSerialize;
+use hand_cranked_privacy::{
+    error::HandCrankedError,
+    instruction::{HandCrankedInstruction, ProofSystem},
+    processor2::Processor,
+    state::{Cluster, PoolParams, VerifyingKeyState, NATIVE_ASSET},
+    zk::Circuit,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+
+async fn send(
+    context: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context
+        .banks_client
+        .get_new_latest_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    let mut tx = Transaction::new_with_payer(&[ix], Some(&context.payer.pubkey()));
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    tx.sign(&all_signers, blockhash);
+    context.banks_client.process_transaction(tx).await
+}
+
+fn instruction(
+    program_id: Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: HandCrankedInstruction,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+fn custom_error(error: HandCrankedError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+fn initialize(
+    program_id: Pubkey,
+    pool: Pubkey,
+    key: Pubkey,
+    authority: Pubkey,
+    circuit: Circuit,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(key, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Groth16,
+            circuit,
+            len: 16,
+        },
+    )
+}
+
+fn write(
+    program_id: Pubkey,
+    pool: Pubkey,
+    key: Pubkey,
+    authority: Pubkey,
+    offset: u32,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(key, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+        HandCrankedInstruction::WriteVerifyingKey {
+            offset,
+            data: vec![7; 8],
+        },
+    )
+}
+
+fn finalize(program_id: Pubkey, pool: Pubkey, key: Pubkey, authority: Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(key, false),
+            AccountMeta::new_readonly(authority, true),
+        ],
+        HandCrankedInstruction::FinalizeVerifyingKey,
+    )
+}
+
+#[tokio::test]
+async fn test_only_the_pool_authority_sets_verifying_keys() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+        "hand_cranked_privacy",
+        program_id,
+        processor!(Processor::process),
+    );
+    let mut context = program_test.start_with_context().await;
+    let authority = context.payer.pubkey();
+    let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &program_id).0;
+    let pool = pda(&[b"pool", &0u64.to_le_bytes()]);
+    let key = pda(&[
+        b"verifying-key",
+        pool.as_ref(),
+        &[ProofSystem::Groth16 as u8],
+        &[Circuit::JoinSplit as u8],
+    ]);
+
+    let init = instruction(
+        program_id,
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(pda(&[b"queue", pool.as_ref()]), false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new(pda(&[b"vault", pool.as_ref()]), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(
+                pda(&[b"stats", pool.as_ref(), NATIVE_ASSET.as_ref()]),
+                false,
+            ),
+        ],
+        HandCrankedInstruction::InitializePool {
+            pool_id: 0,
+            asset: NATIVE_ASSET,
+            denomination: None,
+            params: PoolParams {
+                cluster: Cluster::Localnet,
+                crank_reward: 5_000,
+                min_withdraw_delay: 0,
+                max_withdraw_delay: 0,
+                curator: Pubkey::default(),
+                auditor: None,
+                min_note_age: None,
+            },
+        },
+    );
+    send(&mut context, init, &[]).await.unwrap();
+
+    let stranger = Keypair::new();
+    let result = send(
+        &mut context,
+        initialize(program_id, pool, key, stranger.pubkey(), Circuit::JoinSplit),
+        &[&stranger],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::Unauthorized)
+    );
+
+    // The key account must be the one of the circuit it is created for.
+    let result = send(
+        &mut context,
+        initialize(program_id, pool, key, authority, Circuit::Membership),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidAccountData)
+    );
+
+    // Lamports sent to the key address first do not block its creation.
+    send(
+        &mut context,
+        system_instruction::transfer(&authority, &key, 1),
+        &[],
+    )
+    .await
+    .unwrap();
+    send(
+        &mut context,
+        initialize(program_id, pool, key, authority, Circuit::JoinSplit),
+        &[],
+    )
+    .await
+    .unwrap();
+    let result = send(
+        &mut context,
+        initialize(program_id, pool, key, authority, Circuit::JoinSplit),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::AlreadyInitialized)
+    );
+
+    let result = send(
+        &mut context,
+        write(program_id, pool, key, stranger.pubkey(), 0),
+        &[&stranger],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::Unauthorized)
+    );
+    let result = send(
+        &mut context,
+        write(program_id, pool, key, authority, 9),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::InvalidInstruction)
+    );
+
+    send(
+        &mut context,
+        write(program_id, pool, key, authority, 8),
+        &[],
+    )
+    .await
+    .unwrap();
+    let account = context
+        .banks_client
+        .get_account(key)
+        .await
+        .unwrap()
+        .unwrap();
+    let (header, bytes) = VerifyingKeyState::unpack(&account.data).unwrap();
+    assert_eq!(
+        (header.pool, header.circuit, header.len, header.finalized),
+        (pool, Circuit::JoinSplit, 16, false)
+    );
+    assert_eq!(bytes, &[[0; 8], [7; 8]].concat()[..]);
+
+    let result = send(
+        &mut context,
+        finalize(program_id, pool, key, stranger.pubkey()),
+        &[&stranger],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::Unauthorized)
+    );
+    send(
+        &mut context,
+        finalize(program_id, pool, key, authority),
+        &[],
+    )
+    .await
+    .unwrap();
+
+    // A finalized key is fixed.
+    let result = send(
+        &mut context,
+        write(program_id, pool, key, authority, 0),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::VerifyingKeyFinalized)
+    );
+    let result = send(
+        &mut context,
+        finalize(program_id, pool, key, authority),
+        &[],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::VerifyingKeyFinalized)
+    );
+    let account = context
+        .banks_client
+        .get_account(key)
+        .await
+        .unwrap()
+        .unwrap();
+    let (header, bytes) = VerifyingKeyState::unpack(&account.data).unwrap();
+    assert!(header.finalized);
+    assert_eq!(bytes, &[[0; 8], [7; 8]].concat()[..]);
+
+    // PLONK proofs are only verified off-chain, so no pool takes PLONK keys.
+    let plonk_key = pda(&[
+        b"verifying-key",
+        pool.as_ref(),
+        &[ProofSystem::Plonk as u8],
+        &[Circuit::JoinSplit as u8],
+    ]);
+    let plonk = instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(plonk_key, false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        HandCrankedInstruction::InitializeVerifyingKey {
+            proof_system: ProofSystem::Plonk,
+            circuit: Circuit::JoinSplit,
+            len: 16,
+        },
+    );
+    assert_eq!(
+        send(&mut context, plonk, &[]).await.unwrap_err().unwrap(),
+        custom_error(HandCrankedError::UnsupportedProofSystem)
+    );
+}
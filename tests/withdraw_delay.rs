@@ -0,0 +1,40 @@
+use hand_cranked_privacy::state::{Cluster, PoolParams};
+use solana_program::pubkey::Pubkey;
+
+fn params(min_withdraw_delay: u64, max_withdraw_delay: u64) -> PoolParams {
+    PoolParams {
+        cluster: Cluster::Localnet,
+        crank_reward: 5_000,
+        min_withdraw_delay,
+        max_withdraw_delay,
+        curator: Pubkey::default(),
+        auditor: None,
+        min_note_age: None,
+    }
+}
+
+#[test]
+fn test_release_slot_stays_in_the_window() {
+    let params = params(10, 20);
+    for i in 0..64u8 {
+        let release = params.release_slot(&[i; 32], 1_000).unwrap();
+        assert!((1_010..=1_020).contains(&release));
+    }
+
+    let fixed = self::params(7, 7);
+    assert_eq!(fixed.release_slot(&[1; 32], 100).unwrap(), 107);
+}
+
+#[test]
+fn test_unbounded_windows_are_rejected() {
+    assert!(params(20, 10).check_withdraw_window().is_err());
+    assert!(params(0, u64::MAX).check_withdraw_window().is_err());
+    assert!(params(0, u64::MAX).release_slot(&[1; 32], 0).is_err());
+    assert!(params(0, PoolParams::MAX_WITHDRAW_DELAY)
+        .check_withdraw_window()
+        .is_ok());
+
+    // Near the end of the slot range the release slot overflows instead
+    // of silently saturating.
+    assert!(params(10, 20).release_slot(&[1; 32], u64::MAX - 5).is_err());
+}